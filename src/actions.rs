@@ -1,11 +1,20 @@
+use std::fmt::{self, Display, Formatter};
 use std::time::{SystemTime, UNIX_EPOCH};
 
-use crate::error::Result;
+use crate::dice::Dice;
+use crate::effects::{self, EffectKind};
+use crate::encounter::Encounter;
+use crate::error::{Error, Result};
+use crate::grid::Pos;
+use crate::items::Item;
+use crate::script::{Host, Script, Value};
 use crate::serde::{Deserialize, Field, FieldReader, FieldType, Serialize, Serializer};
+use crate::strings::Rng;
+use crate::verbs::{Clause, Formula, Verb};
 use crate::Entity;
 use ActionKind::*;
 
-fn start() -> i128 {
+pub fn start() -> i128 {
     SystemTime::now()
         .duration_since(UNIX_EPOCH)
         .map(|d| d.as_millis() as i128)
@@ -21,6 +30,60 @@ pub enum ActionKind {
     Spawn,
     Die,
     ElectroCute,
+    LevelUp,
+    Use,
+    Equip,
+    Unequip,
+    Defect,
+    Custom,
+    Setup,
+    Begin,
+    Victory,
+    Defeat,
+    Flee,
+    Move,
+}
+
+impl ActionKind {
+    pub const ALL: [ActionKind; 18] = [
+        Fight,
+        Love,
+        Neutral,
+        Spawn,
+        Die,
+        ElectroCute,
+        LevelUp,
+        Use,
+        Equip,
+        Unequip,
+        Defect,
+        Custom,
+        Setup,
+        Begin,
+        Victory,
+        Defeat,
+        Flee,
+        Move,
+    ];
+
+    // The built in verbs keep a kind of their own so logs from before
+    // `actions.txt` read the same, anything else it defines is `Custom`
+    pub fn id(self) -> Option<&'static str> {
+        match self {
+            Fight => Some("fight"),
+            Love => Some("love"),
+            Neutral => Some("neutral"),
+            ElectroCute => Some("electrocute"),
+            _ => None,
+        }
+    }
+
+    pub fn from_id(id: &str) -> Self {
+        Self::ALL
+            .into_iter()
+            .find(|kind| kind.id() == Some(id))
+            .unwrap_or(Custom)
+    }
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -29,36 +92,384 @@ pub struct Action {
     pub entity: String,
     pub kind: ActionKind,
     pub target: Option<String>,
+    pub rolls: Vec<u64>,
+    // What was used, equipped or unequipped
+    pub item: Option<Item>,
+    // Id of the verb in `actions.txt`, empty for what isn't one
+    pub verb: String,
+    // Where a move went
+    pub to: Option<Pos>,
+    // What it did to the target, filled in once it's done
+    pub damage: u8,
+    pub healed: u8,
+    // Where it is in its session's log, from 1, once it's in there
+    pub seq: u64,
+    // Tried while stunned, so it took the turn and nothing else
+    pub stunned: bool,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Outcome {
+    pub damage: u8,
+    pub healed: u8,
+    pub died: bool,
+    // The acting entity was stunned and lost its turn
+    pub stunned: bool,
+    // Burn damage the acting entity took at the end of its turn
+    pub burned: u8,
+    pub rolls: Vec<u64>,
+    // The target liked the actor so much it joined the party
+    pub defected: bool,
+    // Party members who levelled up from the XP this action earned
+    pub levelled_up: Vec<String>,
+    // Actions its script asked for, done right after this one
+    pub queued: Vec<Action>,
 }
 
 impl Action {
     pub fn interact(kind: ActionKind, entity: &Entity, target: &Entity) -> Result<Self> {
         let inst = Self {
-            start: start(),
-            kind,
-            entity: entity.name.to_owned(),
             target: Some(target.name.to_string()),
+            verb: kind.id().unwrap_or_default().to_string(),
+            ..Self::logged(kind, entity.name.to_owned())
         };
         Ok(inst)
     }
 
-    pub fn spawn(entity: String) -> Self {
+    pub fn perform(verb: &Verb, entity: &Entity, target: &Entity) -> Result<Self> {
+        let inst = Self {
+            target: Some(target.name.to_string()),
+            verb: verb.id.clone(),
+            ..Self::logged(ActionKind::from_id(&verb.id), entity.name.to_owned())
+        };
+        Ok(inst)
+    }
+
+    // The encounter moving on, logged against whoever moved it
+    pub fn transition(to: Encounter, entity: String) -> Self {
+        let kind = match to {
+            Encounter::Setup => Setup,
+            Encounter::InProgress => Begin,
+            Encounter::Victory => Victory,
+            Encounter::Defeat => Defeat,
+            Encounter::Fled => Flee,
+        };
+        Self::logged(kind, entity)
+    }
+
+    // Something that happened to the entity rather than something it did
+    fn logged(kind: ActionKind, entity: String) -> Self {
         Self {
             start: start(),
             entity,
             target: None,
-            kind: Spawn,
+            kind,
+            rolls: vec![],
+            item: None,
+            verb: String::new(),
+            to: None,
+            damage: 0,
+            healed: 0,
+            seq: 0,
+            stunned: false,
         }
     }
 
-    pub fn exec(&mut self, entity: &mut Entity) {
-        let action: String = String::from("fight");
-        log!("Action is : {action:?} and Entity is {0:?}", entity.name);
-        match self.kind {
-            ActionKind::Fight => {
-                eprintln!("You're fighting {0}", entity.name);
+    pub fn spawn(entity: String) -> Self {
+        Self::logged(Spawn, entity)
+    }
+
+    pub fn die(entity: String) -> Self {
+        Self::logged(Die, entity)
+    }
+
+    pub fn level_up(entity: String) -> Self {
+        Self::logged(LevelUp, entity)
+    }
+
+    // `entity` joins the party for the love of `to`
+    pub fn defect(entity: String, to: String) -> Self {
+        Self {
+            target: Some(to),
+            ..Self::logged(Defect, entity)
+        }
+    }
+
+    pub fn walk(entity: String, to: Pos) -> Self {
+        Self {
+            to: Some(to),
+            ..Self::logged(Move, entity)
+        }
+    }
+
+    pub fn use_item(item: Item, entity: &Entity, target: &Entity) -> Result<Self> {
+        let inst = Self {
+            item: Some(item),
+            ..Self::interact(Use, entity, target)?
+        };
+        Ok(inst)
+    }
+
+    pub fn equip(entity: String, item: Item) -> Self {
+        Self {
+            item: Some(item),
+            ..Self::logged(Equip, entity)
+        }
+    }
+
+    pub fn unequip(entity: String, item: Item) -> Self {
+        Self {
+            item: Some(item),
+            ..Self::logged(Unequip, entity)
+        }
+    }
+
+    // Applies the formula, then runs the script with everyone in the scene
+    // within its reach
+    pub fn exec(
+        &self,
+        formula: &Formula,
+        script: Option<&Script>,
+        scene: &mut Scene<'_>,
+        rng: &mut Rng,
+        luck: i8,
+    ) -> Result<Outcome> {
+        let entity = scene
+            .entity(&self.entity)
+            .ok_or_else(|| Error::NoEntity(self.entity.clone()))?
+            .clone();
+        let target_name = self.target.clone().ok_or(Error::InvalidArgs("action "))?;
+        let target = scene
+            .entity_mut(&target_name)
+            .ok_or_else(|| Error::NoEntity(target_name.clone()))?;
+        log!("{0} does {1:?} to {2}", self.entity, self.kind, target.name);
+        let mut outcome = Outcome::default();
+        let mut roll = |dice: &Dice, outcome: &mut Outcome| {
+            let roll = dice.roll_with_luck(rng, luck);
+            outcome.rolls.extend(roll.dice);
+            roll.total.clamp(0, u8::MAX as i64) as u8
+        };
+
+        for clause in &formula.0 {
+            match clause {
+                Clause::Heal(dice) => {
+                    let amount = roll(dice, &mut outcome);
+                    let missing = target.stats.max_health.saturating_sub(target.health);
+                    let healed = amount.min(missing);
+                    target.health += healed;
+                    outcome.healed = outcome.healed.saturating_add(healed);
+                }
+                Clause::Damage(dice) => {
+                    let dice = match (self.kind, &entity.damage) {
+                        (Fight, Some(damage)) => damage,
+                        _ => dice,
+                    };
+                    let amount = match roll(dice, &mut outcome) {
+                        0 => 0,
+                        amount => amount
+                            .saturating_add(entity.stats.attack)
+                            .saturating_sub(target.stats.defense),
+                    };
+                    let amount = effects::absorb(&mut target.effects, amount);
+                    let damage = amount.min(target.health);
+                    target.health -= damage;
+                    outcome.damage = outcome.damage.saturating_add(damage);
+                    outcome.died |= damage > 0 && !target.is_alive();
+                }
+                Clause::Effect(_) => {}
             }
-            _ => eprintln!("Typed unknown action you have"),
+        }
+
+        // Harmful effects only stick if the action actually hurt
+        if !formula.is_harmful() || outcome.damage > 0 {
+            for clause in &formula.0 {
+                if let Clause::Effect(effect) = clause {
+                    effects::apply(&mut target.effects, *effect);
+                }
+            }
+        }
+
+        if let Some(script) = script {
+            let mut host = ScriptHost {
+                scene,
+                rng,
+                luck,
+                rolls: vec![],
+                queued: vec![],
+            };
+            let vars = vec![
+                ("actor", Value::Str(self.entity.clone())),
+                ("target", Value::Str(target_name.clone())),
+                ("damage", Value::Int(outcome.damage as i64)),
+                ("healed", Value::Int(outcome.healed as i64)),
+            ];
+            script.run(&mut host, vars)?;
+            outcome.rolls.extend(host.rolls);
+            outcome.queued = host.queued;
+            // The script may have finished the target off, or brought it back
+            outcome.died = !scene.entity(&target_name).is_some_and(Entity::is_alive);
+        }
+        Ok(outcome)
+    }
+}
+
+// Everyone an action can reach
+pub struct Scene<'a> {
+    pub party: &'a mut [Entity],
+    pub opponents: &'a mut [Entity],
+}
+
+impl Scene<'_> {
+    fn entity(&self, name: &str) -> Option<&Entity> {
+        self.party
+            .iter()
+            .chain(self.opponents.iter())
+            .find(|e| e.name == name)
+    }
+
+    fn entity_mut(&mut self, name: &str) -> Option<&mut Entity> {
+        self.party
+            .iter_mut()
+            .chain(self.opponents.iter_mut())
+            .find(|e| e.name == name)
+    }
+
+    fn in_party(&self, name: &str) -> bool {
+        self.party.iter().any(|e| e.name == name)
+    }
+}
+
+// What a script gets to touch while an action runs
+struct ScriptHost<'a, 'b> {
+    scene: &'a mut Scene<'b>,
+    rng: &'a mut Rng,
+    luck: i8,
+    rolls: Vec<u64>,
+    queued: Vec<Action>,
+}
+
+impl Host for ScriptHost<'_, '_> {
+    fn get(&self, name: &str, field: &str) -> Option<i64> {
+        let entity = self.scene.entity(name)?;
+        let turns = |kind| {
+            entity
+                .effects
+                .iter()
+                .filter(|e| e.kind == kind)
+                .map(|e| e.turns as i64)
+                .max()
+                .unwrap_or(0)
+        };
+        let value = match field {
+            "health" => entity.health as i64,
+            "max_health" => entity.stats.max_health as i64,
+            "attack" => entity.stats.attack as i64,
+            "defense" => entity.stats.defense as i64,
+            "speed" => entity.stats.speed as i64,
+            "level" => entity.stats.level as i64,
+            "xp" => entity.xp as i64,
+            "mana" => entity.mana as i64,
+            "stamina" => entity.stamina as i64,
+            "max_mana" => entity.stats.max_mana as i64,
+            "max_stamina" => entity.stats.max_stamina as i64,
+            "alive" => entity.is_alive() as i64,
+            "stun" => turns(EffectKind::Stun),
+            "burn" => turns(EffectKind::Burn),
+            "charm" => turns(EffectKind::Charm),
+            "shield" => turns(EffectKind::Shield),
+            _ => return None,
+        };
+        Some(value)
+    }
+
+    fn set(&mut self, name: &str, field: &str, value: i64) -> Option<()> {
+        let entity = self.scene.entity_mut(name)?;
+        let byte = value.clamp(0, u8::MAX as i64) as u8;
+        match field {
+            "health" => entity.health = byte.min(entity.stats.max_health),
+            "max_health" => {
+                entity.stats.max_health = byte;
+                entity.health = entity.health.min(byte);
+            }
+            "attack" => entity.stats.attack = byte,
+            "defense" => entity.stats.defense = byte,
+            "speed" => entity.stats.speed = byte,
+            "level" => entity.stats.level = byte,
+            "xp" => entity.xp = value.max(0) as u64,
+            "mana" => entity.mana = byte.min(entity.stats.max_mana),
+            "stamina" => entity.stamina = byte.min(entity.stats.max_stamina),
+            _ => return None,
+        }
+        Some(())
+    }
+
+    fn roll(&mut self, dice: &str) -> Result<i64> {
+        let roll = dice.parse::<Dice>()?.roll_with_luck(self.rng, self.luck);
+        self.rolls.extend(roll.dice);
+        Ok(roll.total)
+    }
+
+    fn pick(&mut self, name: &str, enemy: bool) -> Option<String> {
+        self.scene.entity(name)?;
+        let side = self.scene.in_party(name) != enemy;
+        let candidates = self
+            .scene
+            .party
+            .iter()
+            .chain(self.scene.opponents.iter())
+            .filter(|e| e.is_alive() && e.name != name)
+            .filter(|e| self.scene.in_party(&e.name) == side)
+            .map(|e| e.name.clone())
+            .collect::<Vec<_>>();
+        match candidates.len() {
+            0 => None,
+            n => Some(candidates[self.rng.below(n as u64) as usize].clone()),
+        }
+    }
+
+    fn act(&mut self, verb: &str, from: &str, to: &str) {
+        self.queued.push(Action {
+            target: Some(to.to_string()),
+            verb: verb.to_string(),
+            ..Action::logged(ActionKind::from_id(verb), from.to_string())
+        });
+    }
+}
+
+// A line of the combat log, like "Gilgamesh fights Tommy for 3 damage"
+impl Display for Action {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let who = &self.entity;
+        let target = self.target.as_deref().unwrap_or("no one");
+        let item = self.item.map(Item::name).unwrap_or("something");
+        match self.kind {
+            Fight => write!(f, "{who} fights {target}")?,
+            Love => write!(f, "{who} shows {target} some love")?,
+            Neutral => write!(f, "{who} shrugs at {target}")?,
+            ElectroCute => write!(f, "{who} electrocutes {target}")?,
+            Custom => write!(f, "{who} uses {} on {target}", self.verb)?,
+            Use => write!(f, "{who} uses a {item} on {target}")?,
+            Spawn => write!(f, "{who} shows up")?,
+            Die => write!(f, "{who} dies")?,
+            LevelUp => write!(f, "{who} levels up")?,
+            Equip => write!(f, "{who} puts on the {item}")?,
+            Unequip => write!(f, "{who} takes off the {item}")?,
+            Defect => write!(f, "{who} switches sides for the love of {target}")?,
+            Setup => write!(f, "New opponents show up")?,
+            Begin => write!(f, "{who} starts the fight")?,
+            Victory => write!(f, "Victory, the party wins")?,
+            Defeat => write!(f, "Defeat, the party is wiped out")?,
+            Flee => write!(f, "{who} and the party run for it")?,
+            Move => match self.to {
+                Some(to) => write!(f, "{who} moves to {to}")?,
+                None => write!(f, "{who} moves")?,
+            },
+        }
+        match (self.damage, self.healed) {
+            (0, 0) => Ok(()),
+            (damage, 0) => write!(f, " for {damage} damage"),
+            (0, healed) => write!(f, ", healing {healed}"),
+            (damage, healed) => write!(f, " for {damage} damage, healing {healed}"),
         }
     }
 }
@@ -70,6 +481,15 @@ impl Serialize for Action {
         size += Field::Str(self.entity.clone()).serialize(buf);
         size += Field::ActionKind(self.kind).serialize(buf);
         size += Field::Str(self.target.clone().unwrap_or_default()).serialize(buf);
+        let rolls = self.rolls.iter().copied().map(Field::U64).collect();
+        size += Field::Vec(rolls).serialize(buf);
+        size += self.item.into_iter().collect::<Vec<_>>().serialize(buf);
+        size += Field::Str(self.verb.clone()).serialize(buf);
+        size += self.to.into_iter().collect::<Vec<_>>().serialize(buf);
+        size += Field::Byte(self.damage).serialize(buf);
+        size += Field::Byte(self.healed).serialize(buf);
+        size += Field::U64(self.seq).serialize(buf);
+        size += Field::Bool(self.stunned).serialize(buf);
         s(buf, size)
     }
 }
@@ -79,7 +499,7 @@ impl Deserialize for Action {
     where
         Self: Sized,
     {
-        let action = Self {
+        let mut action = Self {
             start: reader.read_field()?,
             entity: reader.read_field()?,
             kind: reader.read_field()?,
@@ -87,8 +507,48 @@ impl Deserialize for Action {
                 let target: String = reader.read_field()?;
                 (!target.is_empty()).then_some(target)
             },
+            rolls: reader.read_field_or_default()?,
+            item: reader.read_field_or_default::<Vec<Item>, _>()?.pop(),
+            verb: reader.read_field_or_default()?,
+            to: reader.read_field_or_default::<Vec<Pos>, _>()?.pop(),
+            damage: reader.read_field_or_default()?,
+            healed: reader.read_field_or_default()?,
+            seq: reader.read_field_or_default()?,
+            stunned: reader.read_field_or_default()?,
         };
+        if action.verb.is_empty() {
+            action.verb = action.kind.id().unwrap_or_default().to_string();
+        }
 
         Ok(action)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{Action, ActionKind};
+    use crate::grid::Pos;
+    use crate::items::Item;
+
+    #[test]
+    fn actions_read_like_a_combat_log() {
+        let fight = Action {
+            target: Some("Tommy".to_string()),
+            damage: 3,
+            ..Action::logged(ActionKind::Fight, "Gilgamesh".to_string())
+        };
+        assert_eq!(fight.to_string(), "Gilgamesh fights Tommy for 3 damage");
+        let potion = Action {
+            target: Some("Gilgamesh".to_string()),
+            item: Some(Item::Potion),
+            healed: 2,
+            ..Action::logged(ActionKind::Use, "Gilgamesh".to_string())
+        };
+        assert_eq!(
+            potion.to_string(),
+            "Gilgamesh uses a potion on Gilgamesh, healing 2"
+        );
+        let walk = Action::walk("Tommy".to_string(), Pos::new(2, 3));
+        assert_eq!(walk.to_string(), "Tommy moves to 2,3");
+    }
+}
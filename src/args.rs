@@ -1,23 +1,62 @@
 use std::env::args;
+use std::net::SocketAddr;
+use std::time::{SystemTime, UNIX_EPOCH};
 
 use crate::{
-    actions::ActionKind,
+    dice::Dice,
     error::{Error, Result},
+    grid::{Grid, Pos},
+    items::Item,
+    session::Session,
+    simulate::Simulation,
+    try_catch::Exception,
+    verbs::{self, Verbs},
+    watch, Entity,
 };
 
+const DEFAULT_LISTEN: &str = "127.0.0.1:4041";
+
 #[derive(Debug)]
 pub enum Args {
-    Action(ActionKind, String, usize),
-    New(String),
+    Action(String, String, Option<String>, Option<u64>),
+    Use(Item, String, Option<String>, Option<u64>),
+    // Unequips when false
+    Equip(Item, String, Option<String>, bool),
+    New(Entity, u64, i8),
+    Template(Entity),
     Load(String),
+    Relations(String),
+    Roll(Dice, Option<u64>),
     Help(Help),
-    FeelingLucky,
+    Simulate(Simulation),
+    FeelingLucky(String, Option<i8>),
+    NextEncounter(String, Option<usize>),
+    Flee(String, Option<String>),
+    CampaignNew(String, Vec<String>, u64, i8),
+    CampaignAdvance(String, Option<usize>),
+    CampaignStatus(String),
+    Grid(String, Grid),
+    Move(Pos, String, Option<String>),
+    Serve(SocketAddr),
+    List,
+    Watch(String, usize),
+    Login(String),
+    Lobby,
+    Host(String, u8),
+    Join(String, String),
+    Start(String),
+    Say(String, String),
+    Play(String, bool),
 }
 
 #[derive(Debug)]
 pub enum Help {
     General,
-    Action,
+    New,
+    Action(Verbs),
+    Items,
+    Simulate,
+    Campaign,
 }
 
 impl Help {
@@ -29,40 +68,298 @@ HELP!
 -----
 -h, --help        | Show this help
 new <name>        | Create a new session
+template <name>   | Save an entity template to entities/
 load <name>       | Load a session
+relations <name>  | Who likes who, love raises it and hurting lowers it
 action <action>   | Act upon a session
-lucky            | Feeling lucky?"
+use <item> <name> | Use a potion or a scroll, see use --help
+equip <item> <name> | Put on a piece of equipment (unequip takes it off)
+roll <dice>       | Roll dice like 2d6+3, 1d20 adv or 4d6kh3 (--seed <int>)
+next-encounter <name> [count] | New opponents from the templates in entities/
+flee <name> [who] | Run away from the fight
+grid <name> <width>x<height> [--wall x,y] | Fight on a grid, everyone gets a square
+move <x,y> <name> [who] | Move on the grid, as far as speed allows
+campaign          | One party through encounter after encounter, see campaign --help
+simulate          | Run headless battles
+serve [--listen <addr>] | Host sessions for players over TCP (default 127.0.0.1:4041)
+lucky <name> [luck] | Feeling lucky? Show or set the luck of a session (-3 to 3)
+list              | The sessions there are
+watch <name> [--last <int>] | Follow the fight as it happens, after the last 10 actions
+login <name>      | Pick a player name for relays, saved with a new token in token.txt
+lobby             | The sessions on a relay looking for players
+host <name> [--slots <int>] | Open a session for players to join (default one per party member)
+join <name> --entity <who> | Take a seat in a hosted session, playing one of the party
+start <name>      | Start a session you host without waiting for every seat
+say <name> <message> | Talk to everyone in the session, /me for doing rather than saying
+play <name> [--tui] | Fight from a prompt until save or quit, or full screen with a key per action
+--remote <addr>   | Play on a relay instead of sessions/, also read from RELAY_CODE_REMOTE"
             ),
-            Help::Action => println!(
+            Help::New => println!(
                 "\
+HELP for new and template!
+--------------------------
+new -h, --help        | Show this help
+new <name> [flags]
+template <name> [flags]
+--from <template>     | Start from a template (put this first)
+--hp <int>            | Max health (default 5)
+--atk <int>           | Attack, added to damage dealt
+--def <int>           | Defense, taken off damage received
+--spd <int>           | Speed, the fastest act first
+--lvl <int>           | Level
+--mana <int>          | Max mana, spent on spells (default 3)
+--stamina <int>       | Max stamina, spent on feats (default 3)
+--damage <dice>       | Damage roll when fighting, like 2d6+3
+--item <item>         | Something to carry, once per item
+--seed <int>          | Seed for the session dice (new only)
+--luck <int>          | Luck of the session, -3 to 3 (new only)
+"
+            ),
+            Help::Action(verbs) => {
+                println!(
+                    "\
 HELP for action!
 ----------------
 action -h, --help | Show this help
-action fight <target>
-action love <target> 
-action electrocute <target>
-action neutral <target>
-action <verb> <target> --override-my-fate=<int>
+action <verb> <session> [target] --seed <int> | Seed a local session before anything happens
+"
+                );
+                for verb in verbs.iter() {
+                    let mut names = verb.name.clone();
+                    for alias in &verb.aliases {
+                        names += &format!(", {alias}");
+                    }
+                    let cost = verb
+                        .cost
+                        .map(|cost| format!(", {cost}"))
+                        .unwrap_or_default();
+                    let cooldown = verb
+                        .cooldown
+                        .map(|cooldown| format!(", every {cooldown}"))
+                        .unwrap_or_default();
+                    let range = verb
+                        .range
+                        .map(|range| format!(", reach {range}"))
+                        .unwrap_or_default();
+                    println!(
+                        "{names:<24} | {}{range}{cost}{cooldown} | {}",
+                        verb.targets, verb.help
+                    );
+                }
+                println!("\nAdd your own or change these in {}", verbs::FILENAME);
+            }
+            Help::Items => println!(
+                "\
+HELP for items!
+---------------
+use -h, --help                       | Show this help
+use <item> <session> [target]        | Use a consumable, on yourself without a target
+use <item> <session> [target] --seed <int> | Seed a local session before anything happens
+equip <item> <session> [entity]      | Wear it, swapping out whatever is in the slot
+unequip <item> <session> [entity]    | Take it off and put it back in the inventory
+
+potion   | heals 1d4+1
+scroll   | casts electrocute
+sword    | +2 attack
+buckler  | +1 defense
+mail     | +3 max health, +1 defense
+boots    | +2 speed
+"
+            ),
+            Help::Simulate => println!(
+                "\
+HELP for simulate!
+------------------
+simulate -h, --help      | Show this help
+--party <name,name>      | Names in the party
+--opponents <name,name>  | Names of the opponents
+--runs <int>             | Number of battles (default 1000)
+--seed <int>             | Seed for the dice (default: the time)
 "
             ),
+            Help::Campaign => println!(
+                "\
+HELP for campaign!
+------------------
+campaign -h, --help                   | Show this help
+campaign new <name> <name,name> [flags] | Start a campaign with that party, from templates if there are any
+campaign advance <name> [count]       | On to the next encounter, once the last one is over
+campaign status <name>                | How it's been going
+--seed <int>                          | Seed for the campaign (new only)
+--luck <int>                          | Luck of the campaign, -3 to 3 (new only)
+
+While a campaign is on, action, use, flee and the rest take its name to play
+the current encounter"
+            ),
         }
     }
 }
 
-fn parse_action_kind<S: AsRef<str>>(action: S) -> Result<ActionKind> {
-    match action.as_ref().to_lowercase().as_str() {
-        "fight" => Ok(ActionKind::Fight),
-        "love" => Ok(ActionKind::Love),
-        "electrocute" => Ok(ActionKind::ElectroCute),
-        "neutral" => Ok(ActionKind::Neutral),
-        _ => Err(Error::InvalidActionType),
+fn parse_names(names: String) -> Vec<String> {
+    names
+        .split(',')
+        .filter(|name| !name.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+fn time_seed() -> Result<u64> {
+    let now = SystemTime::now().duration_since(UNIX_EPOCH)?;
+    Ok(now.as_millis() as u64)
+}
+
+fn parse_seed(seed: Option<String>, subcommand: &'static str) -> Result<u64> {
+    seed.and_then(|seed| seed.parse().ok())
+        .ok_or(Error::InvalidArgs(subcommand))
+}
+
+fn parse_luck(luck: Option<String>) -> Result<i8> {
+    let luck = luck.ok_or(Error::InvalidArgs(""))?;
+    match luck.parse::<i8>() {
+        Ok(luck) if luck.abs() <= Session::MAX_LUCK => Ok(luck),
+        _ => Err(Exception {
+            kind: "RIP Bozo",
+            message: format!("luck goes from -3 to 3, not {luck}"),
+        }
+        .into()),
+    }
+}
+
+fn parse_stat(value: Option<String>) -> Result<u8> {
+    value
+        .and_then(|value| value.parse().ok())
+        .ok_or(Error::InvalidArgs("new "))
+}
+
+// Flags shared by `new` and `template`, returns false for flags it doesn't know
+fn parse_entity_flag(
+    entity: &mut Entity,
+    flag: &str,
+    args: &mut impl Iterator<Item = String>,
+) -> Result<bool> {
+    match flag {
+        "--from" => {
+            let template = args.next().ok_or(Error::InvalidArgs("new "))?;
+            *entity = Entity::template(&template, entity.name.clone())?;
+        }
+        "--hp" => entity.set_max_health(parse_stat(args.next())?),
+        "--atk" => entity.stats.attack = parse_stat(args.next())?,
+        "--def" => entity.stats.defense = parse_stat(args.next())?,
+        "--spd" => entity.stats.speed = parse_stat(args.next())?,
+        "--lvl" => entity.stats.level = parse_stat(args.next())?,
+        "--mana" => {
+            entity.stats.max_mana = parse_stat(args.next())?;
+            entity.mana = entity.stats.max_mana;
+        }
+        "--stamina" => {
+            entity.stats.max_stamina = parse_stat(args.next())?;
+            entity.stamina = entity.stats.max_stamina;
+        }
+        "--item" => {
+            let item = args.next().ok_or(Error::InvalidArgs("new "))?;
+            entity.inventory.push(item.parse()?);
+        }
+        "--damage" => {
+            let dice = args.next().ok_or(Error::InvalidArgs("new "))?;
+            entity.damage = Some(dice.parse::<Dice>()?);
+        }
+        _ => return Ok(false),
+    }
+    Ok(true)
+}
+
+fn parse_simulation(mut args: impl Iterator<Item = String>) -> Result<Args> {
+    let mut simulation = Simulation {
+        party: vec![],
+        opponents: vec![],
+        runs: 1000,
+        seed: time_seed()?,
+        luck: 0,
+    };
+
+    while let Some(flag) = args.next() {
+        if matches!(flag.as_str(), "-h" | "--help") {
+            return Ok(Args::Help(Help::Simulate));
+        }
+        let value = args.next().ok_or(Error::InvalidArgs("simulate "))?;
+        match flag.as_str() {
+            "--party" => simulation.party = parse_names(value),
+            "--opponents" => simulation.opponents = parse_names(value),
+            "--runs" => {
+                simulation.runs = value.parse().map_err(|_| Error::InvalidArgs("simulate "))?
+            }
+            "--seed" => simulation.seed = parse_seed(Some(value), "simulate ")?,
+            "--luck" => simulation.luck = parse_luck(Some(value))?,
+            _ => return Err(Error::InvalidArgs("simulate ")),
+        }
+    }
+
+    Ok(Args::Simulate(simulation))
+}
+
+fn parse_campaign(mut args: impl Iterator<Item = String>) -> Result<Args> {
+    let command = args.next();
+    if matches!(command.as_deref(), None | Some("-h" | "--help")) {
+        return Ok(Args::Help(Help::Campaign));
+    }
+    let name = args.next().ok_or(Error::InvalidArgs("campaign "))?;
+    match command.as_deref() {
+        Some("new") => {
+            let party = parse_names(args.next().ok_or(Error::InvalidArgs("campaign "))?);
+            if party.is_empty() {
+                return Err(Error::InvalidArgs("campaign "));
+            }
+            let mut seed = None;
+            let mut luck = 0;
+            while let Some(flag) = args.next() {
+                match flag.as_str() {
+                    "--seed" => seed = Some(parse_seed(args.next(), "campaign ")?),
+                    "--luck" => luck = parse_luck(args.next())?,
+                    _ => return Err(Error::InvalidArgs("campaign ")),
+                }
+            }
+            let seed = match seed {
+                Some(seed) => seed,
+                None => time_seed()?,
+            };
+            Ok(Args::CampaignNew(name, party, seed, luck))
+        }
+        Some("advance") => {
+            let count = args
+                .next()
+                .map(|count| count.parse().map_err(|_| Error::InvalidArgs("campaign ")))
+                .transpose()?;
+            Ok(Args::CampaignAdvance(name, count))
+        }
+        Some("status") => Ok(Args::CampaignStatus(name)),
+        _ => Err(Error::InvalidArgs("campaign ")),
+    }
+}
+
+// `--remote <addr>` goes with any command, so it's taken out before the rest
+fn split_remote(args: impl Iterator<Item = String>) -> Result<(Vec<String>, Option<SocketAddr>)> {
+    let mut rest = vec![];
+    let mut remote = None;
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--remote" => {
+                let addr = args.next().ok_or(Error::InvalidArgs(""))?;
+                remote = Some(addr.parse().map_err(|_| Error::InvalidArgs(""))?);
+            }
+            _ => rest.push(arg),
+        }
     }
+    Ok((rest, remote))
 }
 
 impl Args {
-    pub fn parse() -> Result<Args> {
-        let mut args = args().skip(1);
+    pub fn parse() -> Result<(Args, Option<SocketAddr>)> {
+        let (args, remote) = split_remote(args().skip(1))?;
+        Ok((Self::parse_command(args.into_iter())?, remote))
+    }
 
+    fn parse_command(mut args: impl Iterator<Item = String>) -> Result<Args> {
         let next_arg = match args.next() {
             None => return Ok(Args::Help(Help::General)),
             Some(arg) => arg,
@@ -70,31 +367,237 @@ impl Args {
 
         match next_arg.as_str() {
             "new" => {
-                let name = args.next().ok_or(Error::InvalidArgs(""))?;
-                Ok(Args::New(name))
+                let name = args.next().ok_or(Error::InvalidArgs("new "))?;
+                if matches!(name.as_str(), "-h" | "--help") {
+                    return Ok(Args::Help(Help::New));
+                }
+                let mut entity = Entity::new(name);
+                let mut seed = None;
+                let mut luck = 0;
+                while let Some(flag) = args.next() {
+                    match flag.as_str() {
+                        "--seed" => seed = Some(parse_seed(args.next(), "new ")?),
+                        "--luck" => luck = parse_luck(args.next())?,
+                        flag if parse_entity_flag(&mut entity, flag, &mut args)? => {}
+                        _ => return Err(Error::InvalidArgs("new ")),
+                    }
+                }
+                let seed = match seed {
+                    Some(seed) => seed,
+                    None => time_seed()?,
+                };
+                Ok(Args::New(entity, seed, luck))
+            }
+            "template" => {
+                let name = args.next().ok_or(Error::InvalidArgs("new "))?;
+                if matches!(name.as_str(), "-h" | "--help") {
+                    return Ok(Args::Help(Help::New));
+                }
+                let mut entity = Entity::new(name);
+                while let Some(flag) = args.next() {
+                    if !parse_entity_flag(&mut entity, &flag, &mut args)? {
+                        return Err(Error::InvalidArgs("new "));
+                    }
+                }
+                Ok(Args::Template(entity))
             }
             "load" => {
                 let name = args.next().ok_or(Error::InvalidArgs(""))?;
                 Ok(Args::Load(name))
             }
+            "relations" => {
+                let name = args.next().ok_or(Error::InvalidArgs(""))?;
+                Ok(Args::Relations(name))
+            }
             "action" => {
                 let howljf = args.next();
                 if matches!(howljf.as_deref(), Some("-h" | "--help")) {
-                    return Ok(Args::Help(Help::Action));
+                    return Ok(Args::Help(Help::Action(Verbs::load()?)));
                 }
                 let action_arg = howljf.ok_or(Error::InvalidArgs("action "))?;
-                let target_arg = args.next().ok_or(Error::InvalidArgs("action "))?;
-                let joaijs0jjsjljl = match args.next().unwrap_or(String::new()).as_ref() {
-                    "--override-my-fate=2112" => 2112,
-                    _ => 0,
-                };
-                let action_arg = parse_action_kind(action_arg)?;
-                log!("Action arg is {action_arg:?} where target_arg is {target_arg}");
-                Ok(Args::Action(action_arg, target_arg, joaijs0jjsjljl))
+                let session_arg = args.next().ok_or(Error::InvalidArgs("action "))?;
+                let mut target_arg = None;
+                let mut seed = None;
+                while let Some(arg) = args.next() {
+                    match arg.as_str() {
+                        "--seed" => seed = Some(parse_seed(args.next(), "action ")?),
+                        // A typo shouldn't quietly do something else
+                        _ if arg.starts_with("--") => return Err(Error::InvalidArgs("action ")),
+                        _ if target_arg.is_none() => target_arg = Some(arg),
+                        _ => return Err(Error::InvalidArgs("action ")),
+                    }
+                }
+                let action_arg = Verbs::load()?.find(&action_arg)?.id.clone();
+                log!("Action arg is {action_arg:?} where target_arg is {target_arg:?}");
+                Ok(Args::Action(action_arg, session_arg, target_arg, seed))
+            }
+            command @ ("use" | "equip" | "unequip") => {
+                let item = args.next();
+                if matches!(item.as_deref(), Some("-h" | "--help")) {
+                    return Ok(Args::Help(Help::Items));
+                }
+                let item = item.ok_or(Error::InvalidArgs("use "))?.parse()?;
+                let session = args.next().ok_or(Error::InvalidArgs("use "))?;
+                let mut target = None;
+                let mut seed = None;
+                while let Some(arg) = args.next() {
+                    match arg.as_str() {
+                        "--seed" => seed = Some(parse_seed(args.next(), "use ")?),
+                        _ if target.is_none() => target = Some(arg),
+                        _ => return Err(Error::InvalidArgs("use ")),
+                    }
+                }
+                match command {
+                    "use" => Ok(Args::Use(item, session, target, seed)),
+                    command => Ok(Args::Equip(item, session, target, command == "equip")),
+                }
+            }
+            "roll" => {
+                let mut expr = vec![];
+                let mut seed = None;
+                while let Some(arg) = args.next() {
+                    match arg.as_str() {
+                        "--seed" => seed = Some(parse_seed(args.next(), "")?),
+                        _ => expr.push(arg),
+                    }
+                }
+                Ok(Args::Roll(expr.join(" ").parse()?, seed))
+            }
+            "simulate" => parse_simulation(args),
+            "lucky" => {
+                let name = args.next().ok_or(Error::InvalidArgs(""))?;
+                let luck = args.next().map(|luck| parse_luck(Some(luck))).transpose()?;
+                Ok(Args::FeelingLucky(name, luck))
+            }
+            "next-encounter" => {
+                let name = args.next().ok_or(Error::InvalidArgs(""))?;
+                let count = args
+                    .next()
+                    .map(|count| count.parse().map_err(|_| Error::InvalidArgs("")))
+                    .transpose()?;
+                Ok(Args::NextEncounter(name, count))
+            }
+            "campaign" => parse_campaign(args),
+            "grid" => {
+                let name = args.next().ok_or(Error::InvalidArgs(""))?;
+                let size = args.next().ok_or(Error::InvalidArgs(""))?;
+                let (width, height) = size.split_once('x').ok_or(Error::InvalidArgs(""))?;
+                let size = |n: &str| n.parse().map_err(|_| Error::InvalidArgs(""));
+                let (width, height) = (size(width)?, size(height)?);
+                let mut walls = vec![];
+                while let Some(flag) = args.next() {
+                    match flag.as_str() {
+                        "--wall" => {
+                            let wall = args.next().ok_or(Error::InvalidArgs(""))?;
+                            walls.push(wall.parse().map_err(|_| Error::InvalidArgs(""))?);
+                        }
+                        _ => return Err(Error::InvalidArgs("")),
+                    }
+                }
+                Ok(Args::Grid(name, Grid::new(width, height, walls)?))
+            }
+            "move" => {
+                let to = args.next().ok_or(Error::InvalidArgs(""))?.parse()?;
+                let name = args.next().ok_or(Error::InvalidArgs(""))?;
+                Ok(Args::Move(to, name, args.next()))
+            }
+            "flee" => {
+                let name = args.next().ok_or(Error::InvalidArgs(""))?;
+                Ok(Args::Flee(name, args.next()))
+            }
+            "serve" => {
+                let mut addr = DEFAULT_LISTEN.to_string();
+                while let Some(flag) = args.next() {
+                    match flag.as_str() {
+                        "--listen" => addr = args.next().ok_or(Error::InvalidArgs(""))?,
+                        _ => return Err(Error::InvalidArgs("")),
+                    }
+                }
+                Ok(Args::Serve(
+                    addr.parse().map_err(|_| Error::InvalidArgs(""))?,
+                ))
+            }
+            "list" => Ok(Args::List),
+            "watch" => {
+                let name = args.next().ok_or(Error::InvalidArgs(""))?;
+                let mut last = watch::BACKFILL;
+                while let Some(flag) = args.next() {
+                    match flag.as_str() {
+                        "--last" => {
+                            let n = args.next().ok_or(Error::InvalidArgs(""))?;
+                            last = n.parse().map_err(|_| Error::InvalidArgs(""))?;
+                        }
+                        _ => return Err(Error::InvalidArgs("")),
+                    }
+                }
+                Ok(Args::Watch(name, last))
+            }
+            "login" => Ok(Args::Login(args.next().ok_or(Error::InvalidArgs(""))?)),
+            "lobby" => Ok(Args::Lobby),
+            "host" => {
+                let name = args.next().ok_or(Error::InvalidArgs(""))?;
+                let mut slots = 0;
+                while let Some(flag) = args.next() {
+                    match flag.as_str() {
+                        "--slots" => {
+                            let n = args.next().ok_or(Error::InvalidArgs(""))?;
+                            slots = n.parse().map_err(|_| Error::InvalidArgs(""))?;
+                        }
+                        _ => return Err(Error::InvalidArgs("")),
+                    }
+                }
+                Ok(Args::Host(name, slots))
+            }
+            "join" => {
+                let name = args.next().ok_or(Error::InvalidArgs(""))?;
+                let mut entity = None;
+                while let Some(flag) = args.next() {
+                    match flag.as_str() {
+                        "--entity" => entity = args.next(),
+                        _ => return Err(Error::InvalidArgs("")),
+                    }
+                }
+                Ok(Args::Join(name, entity.ok_or(Error::InvalidArgs(""))?))
+            }
+            "start" => Ok(Args::Start(args.next().ok_or(Error::InvalidArgs(""))?)),
+            "play" => {
+                let name = args.next().ok_or(Error::InvalidArgs(""))?;
+                match args.next().as_deref() {
+                    None => Ok(Args::Play(name, false)),
+                    Some("--tui") => Ok(Args::Play(name, true)),
+                    Some(_) => Err(Error::InvalidArgs("")),
+                }
+            }
+            "say" => {
+                let name = args.next().ok_or(Error::InvalidArgs(""))?;
+                let message = args.collect::<Vec<_>>().join(" ");
+                if message.trim().is_empty() {
+                    return Err(Error::InvalidArgs(""));
+                }
+                Ok(Args::Say(name, message))
             }
-            "lucky" => Ok(Args::FeelingLucky),
             // "--help" | "-h" => Ok(Args::Help),
             _ => Ok(Args::Help(Help::General)),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::Args;
+    use crate::error::{Error, Result};
+
+    fn parse(args: &[&str]) -> Result<Args> {
+        Args::parse_command(args.iter().map(|arg| arg.to_string()))
+    }
+
+    #[test]
+    fn actions_refuse_flags_they_dont_know() {
+        let seeded = parse(&["action", "fight", "test", "tommy", "--seed", "5"]).unwrap();
+        assert!(matches!(seeded, Args::Action(_, _, Some(_), Some(5))));
+        for typo in [&["--sed", "5"][..], &["--targt", "tommy"]] {
+            let args = [&["action", "fight", "test"][..], typo].concat();
+            assert!(matches!(parse(&args), Err(Error::InvalidArgs("action "))));
+        }
+    }
+}
@@ -0,0 +1,232 @@
+use std::fmt::{self, Display, Formatter};
+use std::fs;
+use std::path::Path;
+
+use crate::actions::Action;
+use crate::encounter::Encounter;
+use crate::error::{Error, Result};
+use crate::levels::Levels;
+use crate::relations::Relations;
+use crate::serde::{serialize, Deserialize, Field, FieldReader, FieldType, Serialize, Serializer};
+use crate::session::Session;
+use crate::strings::Rng;
+use crate::verbs::Verbs;
+use crate::Entity;
+
+const DIRNAME: &str = "campaigns";
+
+// A party going through one encounter after another. The encounters are
+// sessions of their own, named after the campaign, and the last one is the
+// one being played: `action`, `use` and the rest find it by the campaign name
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Campaign {
+    pub name: String,
+    pub seed: u64,
+    pub luck: i8,
+    // As the party left the last encounter, and will start the next one
+    pub party: Vec<Entity>,
+    pub encounters: Vec<Session>,
+    // Who joined, and when each encounter started and how it ended
+    pub actions: Vec<Action>,
+}
+
+fn path(name: &str) -> String {
+    format!("{DIRNAME}/{name}.the_most_powerful.lol")
+}
+
+impl Campaign {
+    pub fn new(name: String, party: Vec<Entity>, seed: u64) -> Self {
+        let actions = party
+            .iter()
+            .map(|entity| Action::spawn(entity.name.clone()))
+            .collect();
+        Self {
+            name,
+            seed,
+            party,
+            actions,
+            ..Default::default()
+        }
+    }
+
+    pub fn current(&self) -> Option<&Session> {
+        self.encounters.last()
+    }
+
+    pub fn current_mut(&mut self) -> Option<&mut Session> {
+        self.encounters.last_mut()
+    }
+
+    // Closes the current encounter and starts the next one with opponents
+    // rolled from the templates
+    pub fn advance(&mut self, count: Option<usize>) -> Result<&Session> {
+        let relations = self.finish()?;
+        let seed = Rng::stream(self.seed, self.encounters.len() as u64).next_u64();
+        let mut session = Session::encounter(self.name.clone(), self.party.clone(), vec![], seed);
+        session.luck = self.luck;
+        session.relations = relations;
+        session.next_encounter(count)?;
+        self.actions
+            .push(Action::transition(Encounter::Setup, self.name.clone()));
+        self.encounters.push(session);
+        Ok(self.encounters.last().expect("just pushed"))
+    }
+
+    // Takes the party back from the current encounter once it's over, as it
+    // is: hurt, levelled up and with whoever defected to it
+    fn finish(&mut self) -> Result<Relations> {
+        let Some(current) = self.encounters.last() else {
+            return Ok(Relations::default());
+        };
+        let ended = current.encounter;
+        if matches!(ended, Encounter::InProgress | Encounter::Defeat) {
+            return Err(Error::Encounter(ended));
+        }
+        self.party = current.party.clone();
+        let relations = current.relations.clone();
+        match ended {
+            // Nobody fought yet, so these opponents get rolled again
+            Encounter::Setup => {
+                self.encounters.pop();
+            }
+            ended => {
+                let by = self.party.first().map(|e| e.name.clone());
+                self.actions
+                    .push(Action::transition(ended, by.unwrap_or_default()));
+            }
+        }
+        Ok(relations)
+    }
+
+    pub fn exists(name: &str) -> bool {
+        Path::new(&path(name)).exists()
+    }
+
+    pub fn load(name: &str) -> Result<Self> {
+        let bytes = match fs::read(path(name)) {
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Err(Error::NoSession),
+            bytes => bytes?,
+        };
+        let mut campaign: Self = FieldReader::new(&bytes).read_field()?;
+        let (levels, verbs) = (Levels::load()?, Verbs::load()?);
+        for session in &mut campaign.encounters {
+            session.levels = levels.clone();
+            session.verbs = verbs.clone();
+        }
+        Ok(campaign)
+    }
+
+    pub fn save(&self) -> Result<()> {
+        fs::create_dir_all(DIRNAME)?;
+        fs::write(path(&self.name), serialize(self))?;
+        Ok(())
+    }
+}
+
+impl Display for Campaign {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Campaign {} (seed {})", self.name, self.seed)?;
+        writeln!(f, "=========")?;
+        for (i, session) in self.encounters.iter().enumerate() {
+            let opponents = session.opponents.iter().map(|e| e.name.as_str());
+            let opponents = opponents.collect::<Vec<_>>().join(", ");
+            writeln!(f, "{:>3}. {:<12} {opponents}", i + 1, session.encounter)?;
+        }
+        if self.encounters.is_empty() {
+            writeln!(f, "No encounters yet, try campaign advance")?;
+        }
+        writeln!(f, "\nParty:")?;
+        let party = self.current().map_or(&self.party, |s| &s.party);
+        for entity in party {
+            let status = match entity.is_alive() {
+                true => format!("{}/{} hp", entity.health, entity.stats.max_health),
+                false => "dead".to_string(),
+            };
+            let items = entity.inventory.iter().map(ToString::to_string);
+            let items = items.collect::<Vec<_>>().join(" ");
+            writeln!(
+                f,
+                "  {:<15} Lv{:<3} {:>4} xp  {status:<10} {items}",
+                entity.name, entity.stats.level, entity.xp
+            )?;
+        }
+        Ok(())
+    }
+}
+
+impl Serialize for Campaign {
+    fn serialize(&self, buf: &mut Serializer) -> usize {
+        let s = buf.unknown_size(FieldType::Campaign);
+        let mut size = Field::Str(self.name.clone()).serialize(buf);
+        size += Field::U64(self.seed).serialize(buf);
+        size += Field::Byte(self.luck as u8).serialize(buf);
+        size += self.party.serialize(buf);
+        size += self.encounters.serialize(buf);
+        size += self.actions.serialize(buf);
+        s(buf, size)
+    }
+}
+
+impl Deserialize for Campaign {
+    fn deserialize(reader: &mut FieldReader<'_>) -> Result<Self>
+    where
+        Self: Sized,
+    {
+        let campaign = Self {
+            name: reader.read_field()?,
+            seed: reader.read_field()?,
+            luck: reader.read_field::<u8, _>()? as i8,
+            party: reader.read_field()?,
+            encounters: reader.read_field()?,
+            actions: reader.read_field()?,
+        };
+
+        Ok(campaign)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Campaign;
+    use crate::actions::ActionKind;
+    use crate::encounter::Encounter;
+    use crate::serde::{serialize, FieldReader};
+    use crate::session::Session;
+    use crate::Entity;
+
+    #[test]
+    fn campaign_round_trip() {
+        let gilgamesh = Entity::new("Gilgamesh".to_string());
+        let tommy = Entity::new("Tommy".to_string());
+        let mut campaign = Campaign::new("epic".to_string(), vec![gilgamesh.clone()], 7);
+        let mut session = Session::encounter("epic".to_string(), vec![gilgamesh], vec![tommy], 1);
+        session.party[0].xp = 12;
+        campaign.encounters.push(session);
+
+        let bytes = serialize(&campaign);
+        let actual: Campaign = FieldReader::new(&bytes).read_field().unwrap();
+        assert_eq!(actual, campaign);
+        assert_eq!(actual.actions[0].kind, ActionKind::Spawn);
+        assert_eq!(actual.current().unwrap().party[0].xp, 12);
+    }
+
+    #[test]
+    fn party_carries_over() {
+        let gilgamesh = Entity::new("Gilgamesh".to_string());
+        let tommy = Entity::new("Tommy".to_string());
+        let mut campaign = Campaign::new("epic".to_string(), vec![gilgamesh.clone()], 7);
+        let session = Session::encounter("epic".to_string(), vec![gilgamesh], vec![tommy], 1);
+        campaign.encounters.push(session);
+
+        let current = campaign.current_mut().unwrap();
+        current.encounter = Encounter::InProgress;
+        current.party[0].xp = 30;
+        assert!(campaign.finish().is_err());
+        campaign.current_mut().unwrap().encounter = Encounter::Victory;
+        campaign.finish().unwrap();
+
+        assert_eq!(campaign.party[0].xp, 30);
+        let log = campaign.actions.iter().map(|a| a.kind).collect::<Vec<_>>();
+        assert_eq!(log, [ActionKind::Spawn, ActionKind::Victory]);
+    }
+}
@@ -0,0 +1,57 @@
+use std::fmt::{self, Display, Formatter};
+
+use crate::actions;
+use crate::error::Result;
+use crate::serde::{Field, FieldReader, Serialize, Serializer};
+
+// Said as something the player does rather than says
+const EMOTE: &str = "/me ";
+// Long enough for a plan, short enough to read mid-fight
+pub const MAX_LEN: usize = 280;
+
+// What the players say to each other, kept apart from what their entities do
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Chat {
+    // Milliseconds, like an action's, so the two can be told in order
+    pub start: i128,
+    pub from: String,
+    pub text: String,
+}
+
+impl Chat {
+    pub fn new(from: String, text: &str) -> Self {
+        Self {
+            start: actions::start(),
+            from,
+            text: text.trim().chars().take(MAX_LEN).collect(),
+        }
+    }
+
+    // A column at a time, like the owners in a session
+    pub fn serialize_all(chat: &[Self], buf: &mut Serializer) -> usize {
+        let column = |f: fn(&Self) -> Field| Field::Vec(chat.iter().map(f).collect());
+        column(|c| Field::I128(c.start)).serialize(buf)
+            + column(|c| Field::Str(c.from.clone())).serialize(buf)
+            + column(|c| Field::Str(c.text.clone())).serialize(buf)
+    }
+
+    // Nothing at all from sessions saved before there was chat
+    pub fn read_all(reader: &mut FieldReader<'_>) -> Result<Vec<Self>> {
+        let starts: Vec<i128> = reader.read_field_or_default()?;
+        let from: Vec<String> = reader.read_field_or_default()?;
+        let text: Vec<String> = reader.read_field_or_default()?;
+        let chat = starts.into_iter().zip(from).zip(text);
+        Ok(chat
+            .map(|((start, from), text)| Self { start, from, text })
+            .collect())
+    }
+}
+
+impl Display for Chat {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self.text.strip_prefix(EMOTE) {
+            Some(emote) => write!(f, "* {} {emote}", self.from),
+            None => write!(f, "{}: {}", self.from, self.text),
+        }
+    }
+}
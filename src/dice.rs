@@ -0,0 +1,277 @@
+use std::fmt::{self, Display, Formatter};
+use std::str::FromStr;
+
+use crate::error::{Error, Result};
+use crate::strings::Rng;
+
+const MAX_DICE: u64 = 100;
+const MAX_SIDES: u64 = 1000;
+const MAX_FLAT: u64 = 1_000_000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Keep {
+    Highest(u64),
+    Lowest(u64),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Term {
+    Dice {
+        count: u64,
+        sides: u64,
+        keep: Option<Keep>,
+    },
+    Flat(u64),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Advantage {
+    Advantage,
+    Disadvantage,
+}
+
+// A parsed dice expression such as `2d6+3`, `1d20 adv` or `4d6kh3`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Dice {
+    terms: Vec<(bool, Term)>,
+    advantage: Option<Advantage>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Roll {
+    pub total: i64,
+    // Every die that hit the table, including the ones that were dropped
+    pub dice: Vec<u64>,
+}
+
+impl Roll {
+    fn reroll(&mut self, other: Roll, best: bool) {
+        self.dice.extend(&other.dice);
+        if (best && other.total > self.total) || (!best && other.total < self.total) {
+            self.total = other.total;
+        }
+    }
+}
+
+impl Dice {
+    pub fn roll(&self, rng: &mut Rng) -> Roll {
+        let mut roll = self.roll_once(rng);
+        if let Some(advantage) = self.advantage {
+            roll.reroll(self.roll_once(rng), advantage == Advantage::Advantage);
+        }
+        roll
+    }
+
+    // Every point of luck rolls the whole thing again, keeping the best
+    // result when luck is positive and the worst when it's negative
+    pub fn roll_with_luck(&self, rng: &mut Rng, luck: i8) -> Roll {
+        let mut roll = self.roll(rng);
+        for _ in 0..luck.unsigned_abs() {
+            roll.reroll(self.roll(rng), luck > 0);
+        }
+        roll
+    }
+
+    fn roll_once(&self, rng: &mut Rng) -> Roll {
+        let mut roll = Roll::default();
+        for (negative, term) in &self.terms {
+            let value = match *term {
+                Term::Flat(value) => value,
+                Term::Dice { count, sides, keep } => {
+                    let mut dice = (0..count).map(|_| rng.below(sides) + 1).collect::<Vec<_>>();
+                    roll.dice.extend(&dice);
+                    dice.sort_unstable();
+                    let kept = match keep {
+                        None => &dice[..],
+                        Some(Keep::Highest(n)) => &dice[dice.len() - n as usize..],
+                        Some(Keep::Lowest(n)) => &dice[..n as usize],
+                    };
+                    kept.iter().sum()
+                }
+            };
+            match negative {
+                true => roll.total = roll.total.saturating_sub(value as i64),
+                false => roll.total = roll.total.saturating_add(value as i64),
+            }
+        }
+        roll
+    }
+}
+
+fn number(input: &mut &str) -> Option<u64> {
+    let end = input
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(input.len());
+    let (digits, rest) = input.split_at(end);
+    *input = rest;
+    digits.parse().ok()
+}
+
+fn term(input: &mut &str) -> Result<Term> {
+    let original = *input;
+    let invalid = || Error::InvalidDice(original.to_string());
+    let count = number(input);
+    let Some(rest) = input.strip_prefix('d') else {
+        return count
+            .filter(|&n| n <= MAX_FLAT)
+            .map(Term::Flat)
+            .ok_or_else(invalid);
+    };
+    *input = rest;
+
+    let count = count.unwrap_or(1);
+    let sides = number(input).ok_or_else(invalid)?;
+    if !(1..=MAX_DICE).contains(&count) || !(1..=MAX_SIDES).contains(&sides) {
+        return Err(invalid());
+    }
+
+    let keep = if let Some(rest) = input.strip_prefix("kh") {
+        *input = rest;
+        Some(Keep::Highest(number(input).ok_or_else(invalid)?))
+    } else if let Some(rest) = input.strip_prefix("kl") {
+        *input = rest;
+        Some(Keep::Lowest(number(input).ok_or_else(invalid)?))
+    } else {
+        None
+    };
+    if let Some(Keep::Highest(n) | Keep::Lowest(n)) = keep {
+        if n == 0 || n > count {
+            return Err(invalid());
+        }
+    }
+
+    Ok(Term::Dice { count, sides, keep })
+}
+
+impl FromStr for Dice {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let lower = s.trim().to_lowercase();
+        let (expr, advantage) = match lower.rsplit_once(char::is_whitespace) {
+            Some((expr, "adv")) => (expr, Some(Advantage::Advantage)),
+            Some((expr, "dis")) => (expr, Some(Advantage::Disadvantage)),
+            _ => (lower.as_str(), None),
+        };
+
+        let expr = expr.replace(char::is_whitespace, "");
+        let mut input = expr.as_str();
+        let mut terms = vec![(false, term(&mut input)?)];
+        while let Some(sign) = input.chars().next() {
+            let negative = match sign {
+                '+' => false,
+                '-' => true,
+                _ => return Err(Error::InvalidDice(s.to_string())),
+            };
+            input = &input[1..];
+            terms.push((negative, term(&mut input)?));
+        }
+
+        Ok(Self { terms, advantage })
+    }
+}
+
+impl Display for Dice {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        for (i, (negative, term)) in self.terms.iter().enumerate() {
+            match (i, negative) {
+                (0, false) => {}
+                (_, true) => write!(f, "-")?,
+                (_, false) => write!(f, "+")?,
+            }
+            match term {
+                Term::Flat(value) => write!(f, "{value}")?,
+                Term::Dice { count, sides, keep } => {
+                    write!(f, "{count}d{sides}")?;
+                    match keep {
+                        Some(Keep::Highest(n)) => write!(f, "kh{n}")?,
+                        Some(Keep::Lowest(n)) => write!(f, "kl{n}")?,
+                        None => {}
+                    }
+                }
+            }
+        }
+        match self.advantage {
+            Some(Advantage::Advantage) => write!(f, " adv"),
+            Some(Advantage::Disadvantage) => write!(f, " dis"),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Display for Roll {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {:?}", self.total, self.dice)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Dice;
+    use crate::strings::Rng;
+
+    #[test]
+    fn parse_and_display() {
+        for expr in [
+            "2d6+3",
+            "1d20 adv",
+            "4d6kh3",
+            "1d8-1+1d4",
+            "3d6kl1 dis",
+            "7",
+        ] {
+            let dice: Dice = expr.parse().unwrap();
+            assert_eq!(dice.to_string(), expr);
+        }
+        assert_eq!("D6".parse::<Dice>().unwrap().to_string(), "1d6");
+    }
+
+    #[test]
+    fn reject_nonsense() {
+        for expr in [
+            "",
+            "d",
+            "2d",
+            "2d6+",
+            "4d6kh5",
+            "0d6",
+            "1d0",
+            "2x6",
+            "1d20 lol",
+            "1000001",
+            "9223372036854775807+9223372036854775807",
+            "18446744073709551615",
+        ] {
+            assert!(expr.parse::<Dice>().is_err(), "{expr}");
+        }
+    }
+
+    #[test]
+    fn keep_highest() {
+        let dice: Dice = "4d6kh3".parse().unwrap();
+        let mut rng = Rng::new(2112);
+        for _ in 0..100 {
+            let roll = dice.roll(&mut rng);
+            let mut rolled = roll.dice.clone();
+            rolled.sort();
+            assert_eq!(rolled.len(), 4);
+            assert_eq!(roll.total, rolled[1..].iter().sum::<u64>() as i64);
+        }
+    }
+
+    #[test]
+    fn advantage_rolls_twice() {
+        let dice: Dice = "1d20+2 adv".parse().unwrap();
+        let roll = dice.roll(&mut Rng::new(7));
+        assert_eq!(roll.dice.len(), 2);
+        assert_eq!(roll.total, *roll.dice.iter().max().unwrap() as i64 + 2);
+    }
+
+    #[test]
+    fn bad_luck_keeps_the_worst() {
+        let dice: Dice = "1d20".parse().unwrap();
+        let roll = dice.roll_with_luck(&mut Rng::new(7), -3);
+        assert_eq!(roll.dice.len(), 4);
+        assert_eq!(roll.total, *roll.dice.iter().min().unwrap() as i64);
+    }
+}
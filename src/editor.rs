@@ -0,0 +1,319 @@
+use std::io::{self, Read, Write};
+use std::process::{Command, Stdio};
+
+use crate::error::Result;
+
+// What a terminal sends, as far as editing a line goes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Key {
+    Char(char),
+    Backspace,
+    Delete,
+    Left,
+    Right,
+    Home,
+    End,
+    Up,
+    Down,
+    Tab,
+    Enter,
+    Escape,
+    // Ctrl-C and Ctrl-D
+    Interrupt,
+    Eof,
+}
+
+// One key from the bytes, which may take a few of them
+pub fn read_key(input: &mut impl Read) -> Result<Key> {
+    loop {
+        let key = match byte(input)? {
+            None | Some(4) => Key::Eof,
+            Some(3) => Key::Interrupt,
+            Some(1) => Key::Home,
+            Some(5) => Key::End,
+            Some(9) => Key::Tab,
+            Some(b'\r' | b'\n') => Key::Enter,
+            Some(8 | 127) => Key::Backspace,
+            Some(27) => escape(input)?,
+            Some(b) if b < 32 => continue,
+            Some(b) => Key::Char(utf8(input, b)?),
+        };
+        return Ok(key);
+    }
+}
+
+fn byte(input: &mut impl Read) -> Result<Option<u8>> {
+    let mut byte = [0];
+    match input.read(&mut byte)? {
+        0 => Ok(None),
+        _ => Ok(Some(byte[0])),
+    }
+}
+
+// Arrows and the like come as ESC [ and a letter, or a number and ~
+fn escape(input: &mut impl Read) -> Result<Key> {
+    if byte(input)? != Some(b'[') {
+        return Ok(Key::Escape);
+    }
+    Ok(match byte(input)? {
+        Some(b'A') => Key::Up,
+        Some(b'B') => Key::Down,
+        Some(b'C') => Key::Right,
+        Some(b'D') => Key::Left,
+        Some(b'H') => Key::Home,
+        Some(b'F') => Key::End,
+        Some(b'3') if byte(input)? == Some(b'~') => Key::Delete,
+        _ => Key::Escape,
+    })
+}
+
+fn utf8(input: &mut impl Read, first: u8) -> Result<char> {
+    let len = match first {
+        0xf0.. => 4,
+        0xe0.. => 3,
+        0xc0.. => 2,
+        _ => 1,
+    };
+    let mut bytes = vec![first];
+    for _ in 1..len {
+        bytes.extend(byte(input)?);
+    }
+    let text = String::from_utf8_lossy(&bytes);
+    Ok(text.chars().next().unwrap_or(char::REPLACEMENT_CHARACTER))
+}
+
+// The terminal without line buffering or echo, as it was again on drop
+pub struct Raw(String);
+
+impl Raw {
+    // Nothing if there's no terminal, like when stdin is a pipe
+    pub fn enter() -> Option<Self> {
+        let saved = stty(&["-g"])?;
+        stty(&["raw", "-echo"])?;
+        Some(Self(saved.trim().to_string()))
+    }
+}
+
+impl Drop for Raw {
+    fn drop(&mut self) {
+        let _ = stty(&[&self.0]);
+    }
+}
+
+// Rows and columns, if there's a terminal to ask
+pub fn size() -> Option<(usize, usize)> {
+    let size = stty(&["size"])?;
+    let mut size = size.split_whitespace().map(|n| n.parse().ok());
+    Some((size.next()??, size.next()??))
+}
+
+// stty works on whatever terminal its stdin is
+fn stty(args: &[&str]) -> Option<String> {
+    let output = Command::new("stty")
+        .args(args)
+        .stdin(Stdio::inherit())
+        .output()
+        .ok()?;
+    output
+        .status
+        .success()
+        .then(|| String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum Edit {
+    Typing,
+    Line(String),
+    // More than one way to finish the word
+    Suggest(Vec<String>),
+    Interrupt,
+    Eof,
+}
+
+// A line being typed, and the ones typed before it
+#[derive(Debug, Default)]
+pub struct Editor {
+    line: Vec<char>,
+    cursor: usize,
+    history: Vec<String>,
+    // Where up and down have got to, the end being the line being typed
+    back: usize,
+}
+
+impl Editor {
+    pub fn line(&self) -> String {
+        self.line.iter().collect()
+    }
+
+    fn set(&mut self, line: &str) {
+        self.line = line.chars().collect();
+        self.cursor = self.line.len();
+    }
+
+    // `complete` gets the line up to the cursor, and returns the words the
+    // last one might be
+    pub fn press(&mut self, key: Key, complete: impl Fn(&str) -> Vec<String>) -> Edit {
+        match key {
+            Key::Char(c) => {
+                self.line.insert(self.cursor, c);
+                self.cursor += 1;
+            }
+            Key::Backspace if self.cursor > 0 => {
+                self.cursor -= 1;
+                self.line.remove(self.cursor);
+            }
+            Key::Delete if self.cursor < self.line.len() => {
+                self.line.remove(self.cursor);
+            }
+            Key::Left => self.cursor = self.cursor.saturating_sub(1),
+            Key::Right => self.cursor = (self.cursor + 1).min(self.line.len()),
+            Key::Home => self.cursor = 0,
+            Key::End => self.cursor = self.line.len(),
+            Key::Up if self.back > 0 => {
+                self.back -= 1;
+                self.set(&self.history[self.back].clone());
+            }
+            Key::Down if self.back < self.history.len() => {
+                self.back += 1;
+                let line = self.history.get(self.back).cloned().unwrap_or_default();
+                self.set(&line);
+            }
+            Key::Tab => return self.complete(complete),
+            Key::Enter => {
+                let line = self.line();
+                if !line.trim().is_empty() && self.history.last() != Some(&line) {
+                    self.history.push(line.clone());
+                }
+                self.back = self.history.len();
+                self.set("");
+                return Edit::Line(line);
+            }
+            Key::Interrupt => {
+                self.back = self.history.len();
+                self.set("");
+                return Edit::Interrupt;
+            }
+            Key::Eof if self.line.is_empty() => return Edit::Eof,
+            _ => {}
+        }
+        Edit::Typing
+    }
+
+    // As much of the word as all the options agree on
+    fn complete(&mut self, complete: impl Fn(&str) -> Vec<String>) -> Edit {
+        let before = self.line[..self.cursor].iter().collect::<String>();
+        let options = complete(&before);
+        let start = before
+            .chars()
+            .rev()
+            .take_while(|c| !c.is_whitespace())
+            .count();
+        let start = self.cursor - start;
+        let mut word = match options.first() {
+            Some(first) => first.chars().collect::<Vec<_>>(),
+            None => return Edit::Typing,
+        };
+        for option in &options[1..] {
+            let same = word
+                .iter()
+                .zip(option.chars())
+                .take_while(|(a, b)| **a == *b);
+            word.truncate(same.count());
+        }
+        if options.len() == 1 {
+            word.push(' ');
+        }
+        if word.len() >= self.cursor - start {
+            self.line.splice(start..self.cursor, word.iter().copied());
+            self.cursor = start + word.len();
+        }
+        match options.len() {
+            1 => Edit::Typing,
+            _ => Edit::Suggest(options),
+        }
+    }
+
+    // The prompt and the line over whatever was there, cursor in place
+    pub fn render(&self, prompt: &str) -> String {
+        let column = prompt.chars().count() + self.cursor;
+        let right = match column {
+            0 => String::new(),
+            n => format!("\x1b[{n}C"),
+        };
+        format!("\r\x1b[K{prompt}{}\r{right}", self.line())
+    }
+
+    // Edits a line on a terminal, or just reads one if there isn't one.
+    // Nothing once input runs out
+    pub fn read_line(
+        &mut self,
+        prompt: &str,
+        complete: impl Fn(&str) -> Vec<String>,
+    ) -> Result<Option<String>> {
+        let mut out = io::stdout();
+        let Some(_raw) = Raw::enter() else {
+            print!("{prompt}");
+            out.flush()?;
+            let mut line = String::new();
+            return match io::stdin().read_line(&mut line)? {
+                0 => Ok(None),
+                _ => Ok(Some(line.trim_end().to_string())),
+            };
+        };
+        let mut stdin = io::stdin().lock();
+        write!(out, "{}", self.render(prompt))?;
+        out.flush()?;
+        loop {
+            match self.press(read_key(&mut stdin)?, &complete) {
+                Edit::Typing => {}
+                Edit::Line(line) => {
+                    write!(out, "\r\n")?;
+                    return Ok(Some(line));
+                }
+                Edit::Suggest(options) => write!(out, "\r\n{}\r\n", options.join("  "))?,
+                Edit::Interrupt => write!(out, "^C\r\n")?,
+                Edit::Eof => {
+                    write!(out, "\r\n")?;
+                    return Ok(None);
+                }
+            }
+            write!(out, "{}", self.render(prompt))?;
+            out.flush()?;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{read_key, Edit, Editor, Key};
+
+    fn complete(before: &str) -> Vec<String> {
+        let options = ["fight", "flee", "Tommy"];
+        let word = before.split(' ').next_back().unwrap_or_default();
+        let options = options.iter().filter(|o| o.starts_with(word));
+        options.map(|o| o.to_string()).collect()
+    }
+
+    #[test]
+    fn lines_are_edited_completed_and_remembered() {
+        let bytes = "fi\tTomy\x1b[D\x1b[Dm\x1b[F\r".as_bytes();
+        let mut input = bytes;
+        let mut editor = Editor::default();
+        let mut edits = vec![];
+        while !input.is_empty() {
+            let key = read_key(&mut input).unwrap();
+            edits.push(editor.press(key, complete));
+        }
+        assert_eq!(edits.pop(), Some(Edit::Line("fight Tommy".to_string())));
+
+        editor.press(Key::Char('f'), complete);
+        let options = vec!["fight".to_string(), "flee".to_string()];
+        assert_eq!(editor.press(Key::Tab, complete), Edit::Suggest(options));
+        assert_eq!(editor.line(), "f");
+        editor.press(Key::Up, complete);
+        assert_eq!(editor.line(), "fight Tommy");
+        editor.press(Key::Down, complete);
+        assert_eq!(editor.line(), "");
+        assert_eq!(read_key(&mut "é".as_bytes()).unwrap(), Key::Char('é'));
+    }
+}
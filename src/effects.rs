@@ -0,0 +1,154 @@
+use std::fmt::{self, Display, Formatter};
+
+use crate::error::{Error, Result};
+use crate::serde::{Deserialize, Field, FieldReader, FieldType, Serialize, Serializer};
+
+#[repr(u8)]
+#[derive(Debug, Eq, PartialEq, Clone, Copy)]
+pub enum EffectKind {
+    Stun,
+    Burn,
+    Charm,
+    Shield,
+}
+
+impl TryFrom<u8> for EffectKind {
+    type Error = Error;
+
+    fn try_from(value: u8) -> Result<Self> {
+        match value {
+            0 => Ok(Self::Stun),
+            1 => Ok(Self::Burn),
+            2 => Ok(Self::Charm),
+            3 => Ok(Self::Shield),
+            _ => Err(Error::InvalidFieldType),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Effect {
+    pub kind: EffectKind,
+    pub magnitude: u8,
+    // Turns of the entity carrying the effect, not rounds
+    pub turns: u8,
+}
+
+impl Effect {
+    pub const fn new(kind: EffectKind, magnitude: u8, turns: u8) -> Self {
+        Self {
+            kind,
+            magnitude,
+            turns,
+        }
+    }
+}
+
+impl Display for Effect {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let name = match self.kind {
+            EffectKind::Stun => "stun",
+            EffectKind::Burn => "burn",
+            EffectKind::Charm => "charm",
+            EffectKind::Shield => "shield",
+        };
+        match self.magnitude {
+            0 => write!(f, "{name}/{}t", self.turns),
+            magnitude => write!(f, "{name}:{magnitude}/{}t", self.turns),
+        }
+    }
+}
+
+// Adds the effect, or refreshes it if the entity already has one of the
+// same kind
+pub fn apply(effects: &mut Vec<Effect>, effect: Effect) {
+    match effects.iter_mut().find(|e| e.kind == effect.kind) {
+        Some(existing) => {
+            existing.magnitude = existing.magnitude.max(effect.magnitude);
+            existing.turns = existing.turns.max(effect.turns);
+        }
+        None => effects.push(effect),
+    }
+}
+
+pub fn has(effects: &[Effect], kind: EffectKind) -> bool {
+    effects.iter().any(|e| e.kind == kind)
+}
+
+// Soaks up as much of `damage` as the shields allow and returns what's left
+pub fn absorb(effects: &mut Vec<Effect>, mut damage: u8) -> u8 {
+    for shield in effects.iter_mut().filter(|e| e.kind == EffectKind::Shield) {
+        let absorbed = shield.magnitude.min(damage);
+        shield.magnitude -= absorbed;
+        damage -= absorbed;
+    }
+    effects.retain(|e| e.kind != EffectKind::Shield || e.magnitude > 0);
+    damage
+}
+
+// End of a turn: counts every effect down by one and returns the burn damage
+// taken this turn
+pub fn tick(effects: &mut Vec<Effect>) -> u8 {
+    let burn = effects
+        .iter()
+        .filter(|e| e.kind == EffectKind::Burn)
+        .map(|e| e.magnitude)
+        .fold(0u8, u8::saturating_add);
+    for effect in effects.iter_mut() {
+        effect.turns = effect.turns.saturating_sub(1);
+    }
+    effects.retain(|e| e.turns > 0);
+    burn
+}
+
+impl Serialize for Effect {
+    fn serialize(&self, buf: &mut Serializer) -> usize {
+        let s = buf.unknown_size(FieldType::Effect);
+        let mut size = Field::Byte(self.kind as u8).serialize(buf);
+        size += Field::Byte(self.magnitude).serialize(buf);
+        size += Field::Byte(self.turns).serialize(buf);
+        s(buf, size)
+    }
+}
+
+impl Deserialize for Effect {
+    fn deserialize(reader: &mut FieldReader<'_>) -> Result<Self>
+    where
+        Self: Sized,
+    {
+        let effect = Self {
+            kind: reader.read_field::<u8, _>()?.try_into()?,
+            magnitude: reader.read_field()?,
+            turns: reader.read_field()?,
+        };
+
+        Ok(effect)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{absorb, apply, tick, Effect, EffectKind};
+
+    #[test]
+    fn tick_burns_and_expires() {
+        let mut effects = vec![
+            Effect::new(EffectKind::Burn, 2, 2),
+            Effect::new(EffectKind::Stun, 0, 1),
+        ];
+        assert_eq!(tick(&mut effects), 2);
+        assert_eq!(effects, [Effect::new(EffectKind::Burn, 2, 1)]);
+        assert_eq!(tick(&mut effects), 2);
+        assert!(effects.is_empty());
+    }
+
+    #[test]
+    fn shield_soaks_damage() {
+        let mut effects = vec![];
+        apply(&mut effects, Effect::new(EffectKind::Shield, 2, 3));
+        apply(&mut effects, Effect::new(EffectKind::Shield, 1, 5));
+        assert_eq!(effects, [Effect::new(EffectKind::Shield, 2, 5)]);
+        assert_eq!(absorb(&mut effects, 3), 1);
+        assert!(effects.is_empty());
+    }
+}
@@ -0,0 +1,51 @@
+use std::fmt::{self, Display, Formatter};
+
+use crate::error::{Error, Result};
+
+// Where a session is in its current fight. Fresh opponents start it in
+// `Setup`, the first action makes it `InProgress`, and it ends when one
+// side has no one standing or the party runs away
+#[repr(u8)]
+#[derive(Debug, Default, Eq, PartialEq, Clone, Copy)]
+pub enum Encounter {
+    #[default]
+    Setup,
+    InProgress,
+    Victory,
+    Defeat,
+    Fled,
+}
+
+impl Encounter {
+    pub fn is_over(self) -> bool {
+        matches!(self, Self::Victory | Self::Defeat | Self::Fled)
+    }
+}
+
+impl TryFrom<u8> for Encounter {
+    type Error = Error;
+
+    fn try_from(value: u8) -> Result<Self> {
+        match value {
+            0 => Ok(Self::Setup),
+            1 => Ok(Self::InProgress),
+            2 => Ok(Self::Victory),
+            3 => Ok(Self::Defeat),
+            4 => Ok(Self::Fled),
+            _ => Err(Error::InvalidFieldType),
+        }
+    }
+}
+
+impl Display for Encounter {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let state = match self {
+            Self::Setup => "setting up",
+            Self::InProgress => "in progress",
+            Self::Victory => "victory",
+            Self::Defeat => "defeat",
+            Self::Fled => "fled",
+        };
+        write!(f, "{state}")
+    }
+}
@@ -4,17 +4,66 @@ use std::io::Error as IoErr;
 use std::str::Utf8Error;
 use std::time::SystemTimeError;
 
+use crate::encounter::Encounter;
+use crate::grid::Pos;
+use crate::session;
 use crate::try_catch::Exception;
+use crate::verbs::{Cooldown, Cost};
 
 pub type Result<T, E = Error> = std::result::Result<T, E>;
 
 pub enum Error {
     InvalidArgs(&'static str),
     InvalidActionType,
+    NoVerb(String),
+    BadTarget(String, String),
+    InvalidDice(String),
     InvalidFieldType,
+    InvalidData(&'static str, usize),
     MissingFieldLen,
     MissingFieldType,
     NoSession,
+    NoEntity(String),
+    NoTemplate(String),
+    EntityDead(String),
+    InvalidItem(String),
+    NoItem(String, &'static str),
+    CantEquip(&'static str),
+    CantUse(&'static str),
+    Charmed(String),
+    Refuses(String, String),
+    Exhausted(String, Cost, u8),
+    Cooldown(String, Cooldown),
+    Encounter(Encounter),
+    NoGrid,
+    OutOfRange(String, String, u8, u8),
+    OutOfSight(String, String),
+    CantReach(String, Pos),
+    TooFar(String, Pos, u8),
+    Script(String),
+    Remote(String),
+    Version(u16, u16),
+    SessionExists(String),
+    BadSessionName(String),
+    OutOfSync(u64, u64),
+    TooBig(usize),
+    NoToken,
+    BadToken(String),
+    BadName(String, usize),
+    NotYours(String, String),
+    NotPlaying(String, String),
+    NotAdmin(String),
+    Taken(String, String),
+    Full(String, u8),
+    NotHost(String, String),
+    Waiting(String, usize, u8),
+    Underway(String),
+    NoRelay,
+    NothingToUndo,
+    NoUndo,
+    Unsaved(String),
+    Seeded(String),
+    NoTerminal,
     Io(IoErr),
     Utf8(Utf8Error),
     SystemTime(SystemTimeError),
@@ -41,10 +90,59 @@ impl Display for Error {
         match self {
             Self::InvalidArgs(subcommand) => write!(f, "invalid argument\n try {subcommand}--help, but no one will hear you scream in userland"),
             Self::InvalidActionType => write!(f, "invalid action type"),
+            Self::NoVerb(verb) => write!(f, "no one knows how to {verb}, check actions.txt"),
+            Self::BadTarget(verb, target) => write!(f, "you can't {verb} {target}"),
+            Self::InvalidDice(expr) => write!(f, "invalid dice expression: {expr:?}"),
             Self::InvalidFieldType => write!(f, "invalid field type"),
+            Self::InvalidData(file, line) => write!(f, "{file} line {line} makes no sense"),
             Self::MissingFieldLen => write!(f, "missing field length"),
             Self::MissingFieldType => write!(f, "missing field type"),
             Self::NoSession => write!(f, "404: your session is in another castle, or you havn't created it yet"),
+            Self::NoEntity(name) => write!(f, "no one called {name} is here"),
+            Self::NoTemplate(name) => write!(f, "there is no template called {name} in entities/"),
+            Self::EntityDead(name) => write!(f, "{name} is dead, let them rest"),
+            Self::InvalidItem(item) => write!(f, "there is no such thing as a {item}"),
+            Self::NoItem(name, item) => write!(f, "{name} has no {item}"),
+            Self::CantEquip(item) => write!(f, "you can't wear a {item}"),
+            Self::CantUse(item) => write!(f, "a {item} is for wearing, try equip"),
+            Self::Charmed(name) => write!(f, "{name} is charmed and refuses to hurt anyone"),
+            Self::Refuses(name, target) => write!(f, "{name} loves {target} too much to hurt them"),
+            Self::Exhausted(name, cost, have) => write!(f, "{name} has {have} of the {cost} it takes, ready in {}", Cooldown::Turns((cost.amount - have).div_ceil(session::RECOVERY) as u32)),
+            Self::Cooldown(verb, wait) => write!(f, "can't {verb} again yet, ready in {wait}"),
+            Self::Encounter(Encounter::Setup) => write!(f, "there's no one to fight yet, try next-encounter"),
+            Self::Encounter(Encounter::InProgress) => write!(f, "there's a fight going on, finish it or flee"),
+            Self::Encounter(Encounter::Victory) => write!(f, "the fight is won, try next-encounter"),
+            Self::Encounter(Encounter::Defeat) => write!(f, "everyone in the party is dead, it's over"),
+            Self::Encounter(Encounter::Fled) => write!(f, "the party ran away, try next-encounter"),
+            Self::NoGrid => write!(f, "there's no grid to move on, make one with grid"),
+            Self::OutOfRange(verb, target, distance, range) => write!(f, "{target} is {distance} squares away, {verb} only reaches {range}"),
+            Self::OutOfSight(name, target) => write!(f, "{name} can't see {target} from there"),
+            Self::CantReach(name, pos) => write!(f, "{name} can't get to {pos} from here"),
+            Self::TooFar(name, pos, speed) => write!(f, "{pos} is too far, {name} only moves {speed} a turn"),
+            Self::Script(message) => write!(f, "script trouble, {message}"),
+            Self::Remote(message) => write!(f, "the relay says {message}"),
+            Self::Version(ours, theirs) => write!(f, "this speaks protocol version {ours} and the other side {theirs}, someone needs an update"),
+            Self::SessionExists(name) => write!(f, "there's already a session called {name}, get your own"),
+            Self::BadSessionName(name) => write!(f, "{name:?} is no name for a session, it has to stay in sessions/"),
+            Self::OutOfSync(from, have) => write!(f, "missed some actions, have up to {have} but these start after {from}"),
+            Self::TooBig(len) => write!(f, "a message of {len} bytes? no thanks"),
+            Self::NoToken => write!(f, "no token.txt here, who are you? try login <name>"),
+            Self::BadToken(name) => write!(f, "that's not {name}'s token, nice try"),
+            Self::BadName(name, max) => write!(f, "{name:?} won't do, names and tokens are 1 to {max} characters without spaces or #"),
+            Self::NotYours(entity, player) => write!(f, "{entity} doesn't take orders from {player}"),
+            Self::NotPlaying(player, session) => write!(f, "{player} has no one in {session}, join it first"),
+            Self::NotAdmin(player) => write!(f, "{player} can't replace a whole session, only admins can"),
+            Self::Taken(entity, owner) => write!(f, "{entity} is already played by {owner}"),
+            Self::Full(session, slots) => write!(f, "all {slots} seats at {session} are taken, maybe next time"),
+            Self::NotHost(player, session) => write!(f, "{player} isn't hosting {session}, ask whoever is"),
+            Self::Waiting(session, seated, slots) => write!(f, "{session} hasn't started, {seated} of {slots} players are here"),
+            Self::Underway(session) => write!(f, "{session} is already underway, no one's opening seats now"),
+            Self::NoRelay => write!(f, "that needs a relay, try --remote <addr>"),
+            Self::NothingToUndo => write!(f, "nothing to undo, what's done is done"),
+            Self::NoUndo => write!(f, "no undo on a relay, everyone already saw it happen"),
+            Self::Unsaved(session) => write!(f, "{session} has unsaved actions, save first or quit again to leave them behind"),
+            Self::Seeded(name) => write!(f, "{name} is already rolling from its seed, --seed only works locally before anything happens"),
+            Self::NoTerminal => write!(f, "full screen needs a terminal, play without --tui instead"),
             Self::Io(err) => write!(f, "{err}"),
             Self::Utf8(err) => write!(f, "{err}"),
             Self::SystemTime(err) => write!(f, "{err}"),
@@ -0,0 +1,344 @@
+use std::collections::VecDeque;
+use std::fmt::{self, Display, Formatter};
+use std::str::FromStr;
+
+use crate::error::{Error, Result};
+use crate::serde::{Deserialize, Field, FieldReader, FieldType, Serialize, Serializer};
+use crate::Entity;
+
+pub const MAX_SIZE: u8 = 26;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Pos {
+    pub x: u8,
+    pub y: u8,
+}
+
+impl Pos {
+    pub fn new(x: u8, y: u8) -> Self {
+        Self { x, y }
+    }
+
+    // Diagonals count as one square, like a king moves
+    pub fn distance(self, to: Pos) -> u8 {
+        self.x.abs_diff(to.x).max(self.y.abs_diff(to.y))
+    }
+
+    // Squares on the way from here to there, both ends left out
+    fn line(self, to: Pos) -> Vec<Pos> {
+        let (mut x, mut y) = (self.x as i32, self.y as i32);
+        let (x1, y1) = (to.x as i32, to.y as i32);
+        let (dx, dy) = ((x1 - x).abs(), -(y1 - y).abs());
+        let (sx, sy) = ((x1 - x).signum(), (y1 - y).signum());
+        let mut err = dx + dy;
+        let mut line = vec![];
+        while (x, y) != (x1, y1) {
+            let e2 = 2 * err;
+            if e2 >= dy {
+                err += dy;
+                x += sx;
+            }
+            if e2 <= dx {
+                err += dx;
+                y += sy;
+            }
+            line.push(Pos::new(x as u8, y as u8));
+        }
+        line.pop();
+        line
+    }
+}
+
+// Where everyone stands. Only obstacles block sight, anyone standing in a
+// square blocks it for moving through
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Grid {
+    pub width: u8,
+    pub height: u8,
+    pub obstacles: Vec<Pos>,
+    pub positions: Vec<(String, Pos)>,
+}
+
+impl Grid {
+    pub fn new(width: u8, height: u8, obstacles: Vec<Pos>) -> Result<Self> {
+        let grid = Self {
+            width,
+            height,
+            obstacles,
+            positions: vec![],
+        };
+        if !(1..=MAX_SIZE).contains(&width) || !(1..=MAX_SIZE).contains(&height) {
+            return Err(Error::InvalidArgs(""));
+        }
+        if grid.obstacles.iter().any(|p| !grid.contains(*p)) {
+            return Err(Error::InvalidArgs(""));
+        }
+        Ok(grid)
+    }
+
+    pub fn contains(&self, pos: Pos) -> bool {
+        pos.x < self.width && pos.y < self.height
+    }
+
+    pub fn position(&self, name: &str) -> Option<Pos> {
+        self.positions
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, pos)| *pos)
+    }
+
+    fn is_free(&self, pos: Pos, entities: &[&Entity]) -> bool {
+        let occupied = entities
+            .iter()
+            .filter(|e| e.is_alive())
+            .any(|e| self.position(&e.name) == Some(pos));
+        self.contains(pos) && !self.obstacles.contains(&pos) && !occupied
+    }
+
+    // Anyone without a square gets the first free one on their side, the
+    // party fills columns from the left and opponents from the right
+    pub fn place(&mut self, party: &[Entity], opponents: &[Entity]) {
+        let everyone = party.iter().chain(opponents).collect::<Vec<_>>();
+        for (entity, left) in party
+            .iter()
+            .map(|e| (e, true))
+            .chain(opponents.iter().map(|e| (e, false)))
+        {
+            if self.position(&entity.name).is_some() {
+                continue;
+            }
+            let columns = (0..self.width).map(|x| match left {
+                true => x,
+                false => self.width - 1 - x,
+            });
+            let free = columns
+                .flat_map(|x| (0..self.height).map(move |y| Pos::new(x, y)))
+                .find(|pos| self.is_free(*pos, &everyone));
+            if let Some(pos) = free {
+                self.positions.push((entity.name.clone(), pos));
+            }
+        }
+    }
+
+    // Squares it takes to walk there around obstacles and whoever is in the
+    // way, if it can be reached at all
+    pub fn steps(&self, from: Pos, to: Pos, entities: &[&Entity]) -> Option<u8> {
+        if !self.is_free(to, entities) {
+            return None;
+        }
+        let mut steps = vec![None; self.width as usize * self.height as usize];
+        let index = |pos: Pos| pos.y as usize * self.width as usize + pos.x as usize;
+        steps[index(from)] = Some(0u8);
+        let mut queue = VecDeque::from([from]);
+        while let Some(pos) = queue.pop_front() {
+            let taken = steps[index(pos)].expect("queued squares have a count");
+            if pos == to {
+                return Some(taken);
+            }
+            for (dx, dy) in [
+                (-1, -1),
+                (0, -1),
+                (1, -1),
+                (-1, 0),
+                (1, 0),
+                (-1, 1),
+                (0, 1),
+                (1, 1),
+            ] {
+                let (x, y) = (pos.x as i32 + dx, pos.y as i32 + dy);
+                if x < 0 || y < 0 {
+                    continue;
+                }
+                let next = Pos::new(x as u8, y as u8);
+                if self.is_free(next, entities) && steps[index(next)].is_none() {
+                    steps[index(next)] = Some(taken + 1);
+                    queue.push_back(next);
+                }
+            }
+        }
+        None
+    }
+
+    pub fn in_sight(&self, from: Pos, to: Pos) -> bool {
+        !from.line(to).iter().any(|pos| self.obstacles.contains(pos))
+    }
+
+    pub fn move_to(&mut self, name: &str, to: Pos) {
+        self.positions.retain(|(n, _)| n != name);
+        self.positions.push((name.to_string(), to));
+    }
+
+    pub fn render<'a>(&'a self, party: &'a [Entity], opponents: &'a [Entity]) -> Render<'a> {
+        Render {
+            grid: self,
+            party,
+            opponents,
+        }
+    }
+}
+
+// The party are capital letters and opponents small ones, in the order
+// they are listed, with the dead crossed out
+pub struct Render<'a> {
+    grid: &'a Grid,
+    party: &'a [Entity],
+    opponents: &'a [Entity],
+}
+
+impl Render<'_> {
+    fn marks(&self) -> Vec<(char, &Entity)> {
+        let party = self.party.iter().zip('A'..='Z');
+        let opponents = self.opponents.iter().zip('a'..='z');
+        party.chain(opponents).map(|(e, c)| (c, e)).collect()
+    }
+}
+
+impl Display for Render<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let marks = self.marks();
+        let mark = |pos: Pos| {
+            let standing = marks
+                .iter()
+                .filter(|(_, e)| self.grid.position(&e.name) == Some(pos));
+            let mut standing = standing.collect::<Vec<_>>();
+            standing.sort_by_key(|(_, e)| !e.is_alive());
+            match standing.first() {
+                Some((c, e)) if e.is_alive() => *c,
+                Some(_) => 'x',
+                None if self.grid.obstacles.contains(&pos) => '#',
+                None => '.',
+            }
+        };
+        write!(f, "  ")?;
+        for x in 0..self.grid.width {
+            write!(f, "{}", x % 10)?;
+        }
+        writeln!(f)?;
+        for y in 0..self.grid.height {
+            write!(f, "{:>2}", y % 100)?;
+            for x in 0..self.grid.width {
+                write!(f, "{}", mark(Pos::new(x, y)))?;
+            }
+            writeln!(f)?;
+        }
+        for (c, entity) in marks {
+            if let Some(pos) = self.grid.position(&entity.name) {
+                writeln!(f, "{c} {} at {pos}", entity.name)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for Pos {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let invalid = || Error::InvalidArgs("");
+        let (x, y) = s.split_once(',').ok_or_else(invalid)?;
+        Ok(Self {
+            x: x.trim().parse().map_err(|_| invalid())?,
+            y: y.trim().parse().map_err(|_| invalid())?,
+        })
+    }
+}
+
+impl Display for Pos {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{},{}", self.x, self.y)
+    }
+}
+
+impl Serialize for Pos {
+    fn serialize(&self, buf: &mut Serializer) -> usize {
+        let s = buf.unknown_size(FieldType::Pos);
+        let mut size = Field::Byte(self.x).serialize(buf);
+        size += Field::Byte(self.y).serialize(buf);
+        s(buf, size)
+    }
+}
+
+impl Deserialize for Pos {
+    fn deserialize(reader: &mut FieldReader<'_>) -> Result<Self>
+    where
+        Self: Sized,
+    {
+        let pos = Self {
+            x: reader.read_field()?,
+            y: reader.read_field()?,
+        };
+
+        Ok(pos)
+    }
+}
+
+impl Serialize for Grid {
+    fn serialize(&self, buf: &mut Serializer) -> usize {
+        let s = buf.unknown_size(FieldType::Grid);
+        let mut size = Field::Byte(self.width).serialize(buf);
+        size += Field::Byte(self.height).serialize(buf);
+        size += self.obstacles.serialize(buf);
+        // Names and where they stand, in the same order
+        let names = self.positions.iter().map(|(n, _)| Field::Str(n.clone()));
+        size += Field::Vec(names.collect()).serialize(buf);
+        let positions = self.positions.iter().map(|(_, pos)| *pos);
+        size += positions.collect::<Vec<_>>().serialize(buf);
+        s(buf, size)
+    }
+}
+
+impl Deserialize for Grid {
+    fn deserialize(reader: &mut FieldReader<'_>) -> Result<Self>
+    where
+        Self: Sized,
+    {
+        let width = reader.read_field()?;
+        let height = reader.read_field()?;
+        let obstacles = reader.read_field()?;
+        let names: Vec<String> = reader.read_field()?;
+        let positions: Vec<Pos> = reader.read_field()?;
+        let grid = Self {
+            width,
+            height,
+            obstacles,
+            positions: names.into_iter().zip(positions).collect(),
+        };
+
+        Ok(grid)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Grid, Pos};
+    use crate::Entity;
+
+    #[test]
+    fn walls_block_sight_and_the_way() {
+        let wall = (0..4).map(|y| Pos::new(2, y)).collect();
+        let grid = Grid::new(5, 5, wall).unwrap();
+        let (from, to) = (Pos::new(0, 0), Pos::new(4, 0));
+        assert!(!grid.in_sight(from, to));
+        assert!(grid.in_sight(from, Pos::new(0, 4)));
+        // Around the bottom of the wall
+        assert_eq!(grid.steps(from, to, &[]), Some(8));
+        assert_eq!(grid.steps(from, Pos::new(2, 0), &[]), None);
+        assert_eq!(from.distance(to), 4);
+    }
+
+    #[test]
+    fn everyone_gets_a_square() {
+        let party = vec![Entity::new("Gilgamesh".to_string())];
+        let opponents = vec![
+            Entity::new("Tommy".to_string()),
+            Entity::new("Timmy".to_string()),
+        ];
+        let mut grid = Grid::new(3, 1, vec![]).unwrap();
+        grid.place(&party, &opponents);
+        assert_eq!(grid.position("Gilgamesh"), Some(Pos::new(0, 0)));
+        assert_eq!(grid.position("Tommy"), Some(Pos::new(2, 0)));
+        assert_eq!(grid.position("Timmy"), Some(Pos::new(1, 0)));
+        let render = grid.render(&party, &opponents).to_string();
+        assert!(render.starts_with("  012\n 0Aba\n"), "{render}");
+    }
+}
@@ -0,0 +1,219 @@
+use std::fmt::{self, Display, Formatter};
+use std::str::FromStr;
+
+use crate::error::{Error, Result};
+use crate::serde::{Field, Serialize, Serializer};
+use crate::verbs::{Targeting, Verb, Verbs};
+use crate::{Entity, Stats};
+use Item::*;
+
+#[repr(u8)]
+#[derive(Debug, Eq, PartialEq, Clone, Copy)]
+pub enum Item {
+    Potion,
+    Scroll,
+    Sword,
+    Buckler,
+    Mail,
+    Boots,
+}
+
+// Only one piece of equipment per slot, equipping another swaps them
+#[derive(Debug, Eq, PartialEq, Clone, Copy)]
+pub enum Slot {
+    Hand,
+    OffHand,
+    Body,
+    Feet,
+}
+
+impl Item {
+    pub const ALL: [Item; 6] = [Potion, Scroll, Sword, Buckler, Mail, Boots];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Potion => "potion",
+            Scroll => "scroll",
+            Sword => "sword",
+            Buckler => "buckler",
+            Mail => "mail",
+            Boots => "boots",
+        }
+    }
+
+    pub fn slot(self) -> Option<Slot> {
+        match self {
+            Potion | Scroll => None,
+            Sword => Some(Slot::Hand),
+            Buckler => Some(Slot::OffHand),
+            Mail => Some(Slot::Body),
+            Boots => Some(Slot::Feet),
+        }
+    }
+
+    pub fn is_consumable(self) -> bool {
+        self.slot().is_none()
+    }
+
+    // What a consumable does when used, scrolls cast the action they are
+    // named after, whatever `actions.txt` says it does. The item pays for
+    // it, so there is no cost or cooldown
+    pub fn verb(self, verbs: &Verbs) -> Result<Verb> {
+        let verb = match self {
+            Potion => Verb {
+                effect: "heal 1d4+1".parse().expect("built in formulas are valid"),
+                range: Some(1),
+                ..Verb::new(self.name())
+            },
+            Scroll => verbs.get("electrocute")?.clone(),
+            item => return Err(Error::CantUse(item.name())),
+        };
+        Ok(Verb {
+            name: format!("use a {self} on"),
+            targets: Targeting::Any,
+            cost: None,
+            cooldown: None,
+            ..verb
+        })
+    }
+
+    // Stats the item adds while it's equipped
+    pub fn bonus(self) -> Stats {
+        let none = Stats {
+            max_health: 0,
+            attack: 0,
+            defense: 0,
+            speed: 0,
+            level: 0,
+            max_mana: 0,
+            max_stamina: 0,
+        };
+        match self {
+            Potion | Scroll => none,
+            Sword => Stats { attack: 2, ..none },
+            Buckler => Stats { defense: 1, ..none },
+            Mail => Stats {
+                max_health: 3,
+                defense: 1,
+                ..none
+            },
+            Boots => Stats { speed: 2, ..none },
+        }
+    }
+}
+
+pub fn equip(entity: &mut Entity, item: Item) -> Result<()> {
+    let Some(slot) = item.slot() else {
+        return Err(Error::CantEquip(item.name()));
+    };
+    take(entity, item)?;
+    let worn = entity.equipped.iter().position(|i| i.slot() == Some(slot));
+    if let Some(worn) = worn {
+        let worn = entity.equipped[worn];
+        unequip(entity, worn)?;
+    }
+    let bonus = item.bonus();
+    let stats = &mut entity.stats;
+    stats.max_health = stats.max_health.saturating_add(bonus.max_health);
+    stats.attack = stats.attack.saturating_add(bonus.attack);
+    stats.defense = stats.defense.saturating_add(bonus.defense);
+    stats.speed = stats.speed.saturating_add(bonus.speed);
+    entity.health = entity.health.saturating_add(bonus.max_health);
+    entity.equipped.push(item);
+    Ok(())
+}
+
+pub fn unequip(entity: &mut Entity, item: Item) -> Result<()> {
+    let Some(i) = entity.equipped.iter().position(|i| *i == item) else {
+        return Err(Error::NoItem(entity.name.clone(), item.name()));
+    };
+    entity.equipped.remove(i);
+    let bonus = item.bonus();
+    let stats = &mut entity.stats;
+    stats.max_health = stats.max_health.saturating_sub(bonus.max_health);
+    stats.attack = stats.attack.saturating_sub(bonus.attack);
+    stats.defense = stats.defense.saturating_sub(bonus.defense);
+    stats.speed = stats.speed.saturating_sub(bonus.speed);
+    // Taking off the mail shouldn't be what kills you
+    entity.health = entity.health.min(stats.max_health).max(1);
+    entity.inventory.push(item);
+    Ok(())
+}
+
+// Takes one of the item out of the inventory
+pub fn take(entity: &mut Entity, item: Item) -> Result<()> {
+    match entity.inventory.iter().position(|i| *i == item) {
+        Some(i) => {
+            entity.inventory.remove(i);
+            Ok(())
+        }
+        None => Err(Error::NoItem(entity.name.clone(), item.name())),
+    }
+}
+
+impl TryFrom<u8> for Item {
+    type Error = Error;
+
+    fn try_from(value: u8) -> Result<Self> {
+        Self::ALL
+            .get(value as usize)
+            .copied()
+            .ok_or(Error::InvalidFieldType)
+    }
+}
+
+impl FromStr for Item {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let s = s.to_lowercase();
+        Self::ALL
+            .into_iter()
+            .find(|item| item.name() == s)
+            .ok_or(Error::InvalidItem(s))
+    }
+}
+
+impl Display for Item {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+impl Serialize for Item {
+    fn serialize(&self, buf: &mut Serializer) -> usize {
+        Field::Item(*self).serialize(buf)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{equip, unequip, Item};
+    use crate::Entity;
+
+    #[test]
+    fn equipment_swaps_and_comes_off() {
+        let mut entity = Entity::new("Gilgamesh".to_string());
+        entity.inventory = vec![Item::Sword, Item::Mail, Item::Potion];
+        equip(&mut entity, Item::Mail).unwrap();
+        assert_eq!(entity.stats.max_health, 8);
+        assert_eq!(entity.stats.defense, 1);
+        assert!(equip(&mut entity, Item::Potion).is_err());
+        assert!(equip(&mut entity, Item::Mail).is_err());
+
+        unequip(&mut entity, Item::Mail).unwrap();
+        assert_eq!(entity.stats.max_health, 5);
+        assert_eq!(entity.stats.defense, 0);
+        assert!(entity.is_alive());
+        assert_eq!(entity.inventory, [Item::Sword, Item::Potion, Item::Mail]);
+    }
+
+    #[test]
+    fn parse_items() {
+        for item in Item::ALL {
+            assert_eq!(item.to_string().parse::<Item>().unwrap(), item);
+            assert_eq!(Item::try_from(item as u8).unwrap(), item);
+        }
+        assert!("excalibur".parse::<Item>().is_err());
+    }
+}
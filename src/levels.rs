@@ -0,0 +1,155 @@
+use std::fs;
+use std::str::FromStr;
+
+use crate::error::{Error, Result};
+use crate::Entity;
+
+pub const FILENAME: &str = "levels.txt";
+
+// Used when there is no `levels.txt` next to the sessions, and as an example
+// of what goes in one
+const DEFAULT: &str = "\
+# XP a fallen opponent is worth for every one of its levels
+reward 5
+
+# level  total xp  hp  atk  def  spd
+2        10        2   1    0    0
+3        25        2   0    1    0
+4        45        3   1    0    1
+5        70        3   0    1    0
+6        100       3   1    0    1
+7        140       4   0    1    0
+8        185       4   1    0    1
+9        235       4   1    1    0
+10       290       5   1    1    1
+";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Level {
+    pub level: u8,
+    // Total XP needed, not XP since the last level
+    pub xp: u64,
+    // Stats gained on reaching the level
+    pub max_health: u8,
+    pub attack: u8,
+    pub defense: u8,
+    pub speed: u8,
+}
+
+impl Level {
+    pub fn grow(&self, entity: &mut Entity) {
+        let stats = &mut entity.stats;
+        stats.level = self.level;
+        stats.max_health = stats.max_health.saturating_add(self.max_health);
+        stats.attack = stats.attack.saturating_add(self.attack);
+        stats.defense = stats.defense.saturating_add(self.defense);
+        stats.speed = stats.speed.saturating_add(self.speed);
+        // Levelling up heals what the new max health added, nothing more
+        entity.health = entity.health.saturating_add(self.max_health);
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Levels {
+    reward: u64,
+    levels: Vec<Level>,
+}
+
+impl Default for Levels {
+    fn default() -> Self {
+        DEFAULT.parse().expect("built in levels are valid")
+    }
+}
+
+impl Levels {
+    pub fn load() -> Result<Self> {
+        match fs::read_to_string(FILENAME) {
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Self::default()),
+            text => text?.parse(),
+        }
+    }
+
+    pub fn reward(&self, fallen: &Entity) -> u64 {
+        self.reward * fallen.stats.level as u64
+    }
+
+    // The next level the entity has enough XP for, if any
+    pub fn next(&self, entity: &Entity) -> Option<&Level> {
+        self.levels
+            .iter()
+            .find(|level| level.level > entity.stats.level)
+            .filter(|level| entity.xp >= level.xp)
+    }
+}
+
+impl FromStr for Levels {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let mut levels = Self {
+            reward: 0,
+            levels: vec![],
+        };
+        for (i, line) in s.lines().enumerate() {
+            let invalid = || Error::InvalidData(FILENAME, i + 1);
+            let line = line.split('#').next().unwrap_or_default();
+            let words = line.split_whitespace().collect::<Vec<_>>();
+            match words[..] {
+                [] => {}
+                ["reward", xp] => levels.reward = xp.parse().map_err(|_| invalid())?,
+                [level, xp, max_health, attack, defense, speed] => {
+                    let number = |word: &str| word.parse::<u8>().map_err(|_| invalid());
+                    let level = Level {
+                        level: number(level)?,
+                        xp: xp.parse().map_err(|_| invalid())?,
+                        max_health: number(max_health)?,
+                        attack: number(attack)?,
+                        defense: number(defense)?,
+                        speed: number(speed)?,
+                    };
+                    // Both have to keep going up or no one would ever level
+                    if let Some(last) = levels.levels.last() {
+                        if level.level <= last.level || level.xp <= last.xp {
+                            return Err(invalid());
+                        }
+                    }
+                    levels.levels.push(level);
+                }
+                _ => return Err(invalid()),
+            }
+        }
+        Ok(levels)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Levels;
+    use crate::Entity;
+
+    #[test]
+    fn level_up_one_at_a_time() {
+        let levels: Levels = "reward 3\n2 10 2 1 0 0\n3 20 1 0 1 0 # comment\n"
+            .parse()
+            .unwrap();
+        let mut entity = Entity::new("gilgamesh".to_string());
+        assert_eq!(levels.reward(&entity), 3);
+        entity.xp = 25;
+        while let Some(level) = levels.next(&entity) {
+            level.grow(&mut entity);
+        }
+        assert_eq!(entity.stats.level, 3);
+        assert_eq!(entity.stats.max_health, 8);
+        assert_eq!((entity.stats.attack, entity.stats.defense), (1, 1));
+    }
+
+    #[test]
+    fn levels_must_go_up() {
+        assert!("2 10 1 1 1 1\n2 20 1 1 1 1".parse::<Levels>().is_err());
+        assert!("2 10 1 1 1 1\n3 5 1 1 1 1".parse::<Levels>().is_err());
+        assert!("reward lots".parse::<Levels>().is_err());
+        assert!(Levels::default()
+            .next(&Entity::new("tommy".into()))
+            .is_none());
+    }
+}
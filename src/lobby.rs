@@ -0,0 +1,140 @@
+use std::fmt::{self, Display, Formatter};
+
+use crate::encounter::Encounter;
+use crate::error::Result;
+use crate::serde::{Field, FieldReader, Serialize, Serializer};
+use crate::session::Session;
+
+// Who opened a session for joining, and how many players it's for
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Seats {
+    pub host: String,
+    pub slots: u8,
+    // Until every seat is taken, or the host starts without them
+    pub waiting: bool,
+}
+
+impl Seats {
+    // A column of hosts with one in it or none, then the rest of them
+    pub fn serialize(seats: Option<&Self>, buf: &mut Serializer) -> usize {
+        let host = seats.iter().map(|s| Field::Str(s.host.clone()));
+        let rest = seats.cloned().unwrap_or_default();
+        Field::Vec(host.collect()).serialize(buf)
+            + Field::Byte(rest.slots).serialize(buf)
+            + Field::Bool(rest.waiting).serialize(buf)
+    }
+
+    pub fn read(reader: &mut FieldReader<'_>) -> Result<Option<Self>> {
+        let host: Vec<String> = reader.read_field_or_default()?;
+        let slots = reader.read_field_or_default()?;
+        let waiting = reader.read_field_or_default()?;
+        Ok(host.into_iter().next().map(|host| Self {
+            host,
+            slots,
+            waiting,
+        }))
+    }
+}
+
+// Who plays who, as a column of entities and one of players
+pub fn serialize_owners(owners: &[(String, String)], buf: &mut Serializer) -> usize {
+    let entities = owners.iter().map(|(e, _)| Field::Str(e.clone()));
+    let players = owners.iter().map(|(_, p)| Field::Str(p.clone()));
+    Field::Vec(entities.collect()).serialize(buf) + Field::Vec(players.collect()).serialize(buf)
+}
+
+pub fn read_owners(reader: &mut FieldReader<'_>) -> Result<Vec<(String, String)>> {
+    let entities: Vec<String> = reader.read_field_or_default()?;
+    let players: Vec<String> = reader.read_field_or_default()?;
+    Ok(entities.into_iter().zip(players).collect())
+}
+
+// A hosted session as the lobby shows it
+#[derive(Debug, Clone, PartialEq)]
+pub struct Table {
+    pub session: String,
+    pub host: String,
+    pub players: u8,
+    pub slots: u8,
+    pub waiting: bool,
+    pub encounter: Encounter,
+}
+
+impl Table {
+    pub fn new(session: &Session) -> Option<Self> {
+        let seats = session.seats.as_ref()?;
+        Some(Self {
+            session: session.name().to_string(),
+            host: seats.host.clone(),
+            players: session.players().len() as u8,
+            slots: seats.slots,
+            waiting: seats.waiting,
+            encounter: session.encounter,
+        })
+    }
+
+    // A column at a time, like the owners in a session
+    pub fn serialize_all(tables: &[Self], buf: &mut Serializer) -> usize {
+        let column = |f: fn(&Self) -> Field| Field::Vec(tables.iter().map(f).collect());
+        column(|t| Field::Str(t.session.clone())).serialize(buf)
+            + column(|t| Field::Str(t.host.clone())).serialize(buf)
+            + column(|t| Field::Byte(t.players)).serialize(buf)
+            + column(|t| Field::Byte(t.slots)).serialize(buf)
+            + column(|t| Field::Bool(t.waiting)).serialize(buf)
+            + column(|t| Field::Byte(t.encounter as u8)).serialize(buf)
+    }
+
+    pub fn read_all(reader: &mut FieldReader<'_>) -> Result<Vec<Self>> {
+        let sessions: Vec<String> = reader.read_field()?;
+        let hosts: Vec<String> = reader.read_field()?;
+        let players: Vec<u8> = reader.read_field()?;
+        let slots: Vec<u8> = reader.read_field()?;
+        let waiting: Vec<bool> = reader.read_field()?;
+        let encounters: Vec<u8> = reader.read_field()?;
+        let mut tables = vec![];
+        for (i, (session, host)) in sessions.into_iter().zip(hosts).enumerate() {
+            let (Some(&players), Some(&slots), Some(&waiting), Some(&encounter)) = (
+                players.get(i),
+                slots.get(i),
+                waiting.get(i),
+                encounters.get(i),
+            ) else {
+                break;
+            };
+            tables.push(Self {
+                session,
+                host,
+                players,
+                slots,
+                waiting,
+                encounter: encounter.try_into()?,
+            });
+        }
+        Ok(tables)
+    }
+}
+
+impl Display for Table {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let state = match self.waiting {
+            true => "waiting for players".to_string(),
+            false => self.encounter.to_string(),
+        };
+        write!(
+            f,
+            "{:16} {:12} {}/{}  {state}",
+            self.session, self.host, self.players, self.slots
+        )
+    }
+}
+
+// Who sits where, after hosting, joining or starting
+pub fn print_seats(session: &Session) {
+    if let Some(table) = Table::new(session) {
+        println!("{table}");
+    }
+    for entity in &session.party {
+        let player = session.owner(&entity.name).unwrap_or("open");
+        println!("  {:16} {player}", entity.name);
+    }
+}
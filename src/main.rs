@@ -1,40 +1,232 @@
 //use std::io::Cursor;
+use std::fs;
 
 use args::Args;
-use error::Result;
+use campaign::Campaign;
+use dice::Dice;
+use effects::Effect;
+use items::Item;
+use error::{Error, Result};
 use serde::{Deserialize, Field, FieldReader, FieldType, Serialize, Serializer};
+use protocol::Change;
 use session::Session;
+use verbs::{Resource, Targeting};
 use actions::Action;
-use try_catch::TryCatch;
-
-use crate::try_catch::Exception;
 
 #[macro_use]
 mod log;
 mod actions;
 mod args;
+mod campaign;
+mod chat;
+mod dice;
+mod editor;
+mod effects;
+mod encounter;
 mod error;
-mod factory;
+mod grid;
+mod items;
+mod levels;
+mod lobby;
+mod play;
+mod players;
+mod protocol;
+mod relations;
+mod relay;
+mod script;
 mod serde;
 mod session;
+mod simulate;
+mod store;
 mod strings;
 mod try_catch;
+mod tui;
+mod verbs;
+mod watch;
+
+const TEMPLATE_DIRNAME: &str = "entities";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Stats {
+    pub max_health: u8,
+    pub attack: u8,
+    pub defense: u8,
+    pub speed: u8,
+    pub level: u8,
+    pub max_mana: u8,
+    pub max_stamina: u8,
+}
+
+impl Default for Stats {
+    fn default() -> Self {
+        Self {
+            max_health: 5,
+            attack: 0,
+            defense: 0,
+            speed: 1,
+            level: 1,
+            max_mana: 3,
+            max_stamina: 3,
+        }
+    }
+}
+
+impl Serialize for Stats {
+    fn serialize(&self, buf: &mut Serializer) -> usize {
+        let s = buf.unknown_size(FieldType::Stats);
+        let mut size = Field::Byte(self.max_health).serialize(buf);
+        size += Field::Byte(self.attack).serialize(buf);
+        size += Field::Byte(self.defense).serialize(buf);
+        size += Field::Byte(self.speed).serialize(buf);
+        size += Field::Byte(self.level).serialize(buf);
+        size += Field::Byte(self.max_mana).serialize(buf);
+        size += Field::Byte(self.max_stamina).serialize(buf);
+        s(buf, size)
+    }
+}
+
+impl Deserialize for Stats {
+    fn deserialize(reader: &mut FieldReader<'_>) -> Result<Self>
+    where
+        Self: Sized,
+    {
+        let stats = Self {
+            max_health: reader.read_field()?,
+            attack: reader.read_field()?,
+            defense: reader.read_field()?,
+            speed: reader.read_field()?,
+            level: reader.read_field()?,
+            // Stats from before mana and stamina get the default pools
+            max_mana: match reader.is_empty() {
+                true => Self::default().max_mana,
+                false => reader.read_field()?,
+            },
+            max_stamina: match reader.is_empty() {
+                true => Self::default().max_stamina,
+                false => reader.read_field()?,
+            },
+        };
+
+        Ok(stats)
+    }
+}
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Entity {
     pub name: String,
     health: u8,
     field_c: bool,
+    // Rolled instead of the default when this entity fights
+    pub damage: Option<Dice>,
+    pub effects: Vec<Effect>,
+    pub stats: Stats,
+    pub xp: u64,
+    pub inventory: Vec<Item>,
+    // Worn items, their bonus is already part of `stats`
+    pub equipped: Vec<Item>,
+    // What's left to pay for actions with, out of the maximum in `stats`
+    pub mana: u8,
+    pub stamina: u8,
 }
 
 impl Entity {
     pub fn new(name: String) -> Self {
+        let stats = Stats::default();
         Self {
             name,
-            health: 5,
+            health: stats.max_health,
             field_c: false,
+            damage: None,
+            effects: vec![],
+            stats,
+            xp: 0,
+            inventory: vec![],
+            equipped: vec![],
+            mana: stats.max_mana,
+            stamina: stats.max_stamina,
+        }
+    }
+
+    pub fn is_alive(&self) -> bool {
+        self.health > 0
+    }
+
+    pub fn set_max_health(&mut self, max_health: u8) {
+        self.stats.max_health = max_health;
+        self.health = max_health;
+    }
+
+    pub fn resource(&self, resource: Resource) -> u8 {
+        match resource {
+            Resource::Mana => self.mana,
+            Resource::Stamina => self.stamina,
         }
     }
+
+    pub fn resource_mut(&mut self, resource: Resource) -> &mut u8 {
+        match resource {
+            Resource::Mana => &mut self.mana,
+            Resource::Stamina => &mut self.stamina,
+        }
+    }
+
+    // Mana and stamina trickle back at the end of every turn
+    pub fn recover(&mut self, amount: u8) {
+        self.mana = self.mana.saturating_add(amount).min(self.stats.max_mana);
+        self.stamina = self.stamina.saturating_add(amount).min(self.stats.max_stamina);
+    }
+
+    // A fresh copy of the template stored under `entities/`, at full health
+    pub fn template(template: &str, name: String) -> Result<Self> {
+        let path = format!("{TEMPLATE_DIRNAME}/{template}.the_most_powerful.lol");
+        let bytes = match fs::read(path) {
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                return Err(Error::NoTemplate(template.to_string()))
+            }
+            bytes => bytes?,
+        };
+        let mut entity: Entity = FieldReader::new(&bytes).read_field()?;
+        entity.name = name;
+        entity.health = entity.stats.max_health;
+        entity.mana = entity.stats.max_mana;
+        entity.stamina = entity.stats.max_stamina;
+        entity.effects.clear();
+        Ok(entity)
+    }
+
+    // The template with that name if there is one, otherwise a plain entity
+    pub fn spawn(name: &str) -> Result<Self> {
+        match Self::template(name, name.to_string()) {
+            Err(Error::NoTemplate(_)) => Ok(Self::new(name.to_string())),
+            entity => entity,
+        }
+    }
+
+    // Every template under `entities/`, sorted so that picking one at random
+    // doesn't depend on the order the file system lists them in
+    pub fn templates() -> Result<Vec<String>> {
+        let dir = match fs::read_dir(TEMPLATE_DIRNAME) {
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(vec![]),
+            dir => dir?,
+        };
+        let mut names = vec![];
+        for entry in dir {
+            let name = entry?.file_name();
+            let name = name.to_string_lossy();
+            if let Some(name) = name.strip_suffix(".the_most_powerful.lol") {
+                names.push(name.to_string());
+            }
+        }
+        names.sort();
+        Ok(names)
+    }
+
+    pub fn save_template(&self) -> Result<()> {
+        fs::create_dir_all(TEMPLATE_DIRNAME)?;
+        let path = format!("{TEMPLATE_DIRNAME}/{}.the_most_powerful.lol", self.name);
+        fs::write(path, serde::serialize(self))?;
+        Ok(())
+    }
 }
 
 impl Serialize for Entity {
@@ -43,6 +235,15 @@ impl Serialize for Entity {
         let mut size = Field::Str(self.name.clone()).serialize(buf);
         size += Field::Byte(self.health).serialize(buf);
         size += Field::Bool(self.field_c).serialize(buf);
+        let damage = self.damage.as_ref().map(Dice::to_string);
+        size += Field::Str(damage.unwrap_or_default()).serialize(buf);
+        size += self.effects.serialize(buf);
+        size += self.stats.serialize(buf);
+        size += Field::U64(self.xp).serialize(buf);
+        size += self.inventory.serialize(buf);
+        size += self.equipped.serialize(buf);
+        size += Field::Byte(self.mana).serialize(buf);
+        size += Field::Byte(self.stamina).serialize(buf);
         s(buf, size)
     }
 }
@@ -52,66 +253,270 @@ impl Deserialize for Entity {
     where
         Self: Sized,
     {
-        let entity = Self {
-            name: reader.read_field()?,
-            health: reader.read_field()?,
+        let name = reader.read_field()?;
+        let health: u8 = reader.read_field()?;
+        let mut entity = Self {
+            name,
+            health,
             field_c: reader.read_field()?,
+            damage: {
+                let damage: String = reader.read_field_or_default()?;
+                match damage.is_empty() {
+                    true => None,
+                    false => Some(damage.parse()?),
+                }
+            },
+            effects: reader.read_field_or_default()?,
+            // Entities from before stats only knew about their health
+            stats: match reader.is_empty() {
+                true => Stats {
+                    max_health: health.max(Stats::default().max_health),
+                    ..Default::default()
+                },
+                false => reader.read_field()?,
+            },
+            xp: reader.read_field_or_default()?,
+            inventory: reader.read_field_or_default()?,
+            equipped: reader.read_field_or_default()?,
+            mana: 0,
+            stamina: 0,
+        };
+        // Entities from before resources start out rested
+        entity.mana = match reader.is_empty() {
+            true => entity.stats.max_mana,
+            false => reader.read_field()?,
+        };
+        entity.stamina = match reader.is_empty() {
+            true => entity.stats.max_stamina,
+            false => reader.read_field()?,
         };
 
         Ok(entity)
     }
 }
 
+// A relay only hears about the action, so it keeps rolling from its own seed
+fn reseed(store: &dyn store::SessionStore, session: &mut Session, seed: u64) -> Result<()> {
+    match store.shared() {
+        true => Err(Error::Seeded(session.name().to_string())),
+        false => session.reseed(seed),
+    }
+}
+
 fn main() -> Result<()> {
     log::set_log();
-    let args = Args::parse()?;
+    let (args, remote) = Args::parse()?;
+    let store = || store::open(remote);
 
     //let session = Session::load().unwrap();
     match args {
         Args::Help(help) => help.print(),
-        Args::Action(kind, target, u0usasdfaowjww) => {
-            println!("args are {kind:?} and {target} and {u0usasdfaowjww}");
-            let session = Session::load(&target, u0usasdfaowjww)?;
-            let jidjfoijojjnsnhahhaohohosohfoshsohfoshdfohadhoahfoadshofsahfasdfhdsafdashfdpsaofdspaofdpsao = Action::interact(kind, &session.party[0], &session.party[0])?;
+        Args::Action(verb, name, target, seed) => {
+            println!("args are {verb:?} and {name} and {target:?}");
+            let mut store = store()?;
+            let mut session = store.load(&name)?;
+            if let Some(seed) = seed {
+                reseed(store.as_ref(), &mut session, seed)?;
+            }
+            let verb = session.verbs.get(&verb)?;
+            let entity = session.party.first().ok_or(Error::NoSession)?;
+            let target = match &target {
+                Some(target) => session
+                    .entity(target)
+                    .ok_or_else(|| Error::NoEntity(target.clone()))?,
+                // Whoever is still standing on the other side
+                None if verb.targets == Targeting::Enemy => session
+                    .opponents
+                    .iter()
+                    .find(|e| e.is_alive())
+                    .ok_or_else(|| Error::BadTarget(verb.name.clone(), "anyone".to_string()))?,
+                None => entity,
+            };
+            let jidjfoijojjnsnhahhaohohosohfoshsohfoshdfohadhoahfoadshofsahfasdfhdsafdashfdpsaofdspaofdpsao = Action::perform(verb, entity, target)?;
             println!("{jidjfoijojjnsnhahhaohohosohfoshsohfoshdfohadhoahfoadshofsahfasdfhdsafdashfdpsaofdspaofdpsao:?}");
+            store.submit(&mut session, jidjfoijojjnsnhahhaohohosohfoshsohfoshdfohadhoahfoadshofsahfasdfhdsafdashfdpsaofdspaofdpsao)?;
+            eprintln!("{session}");
+        }
+        Args::Use(item, name, target, seed) => {
+            let mut store = store()?;
+            let mut session = store.load(&name)?;
+            if let Some(seed) = seed {
+                reseed(store.as_ref(), &mut session, seed)?;
+            }
+            let entity = session.party.first().ok_or(Error::NoSession)?;
+            let target = match &target {
+                Some(target) => session
+                    .entity(target)
+                    .ok_or_else(|| Error::NoEntity(target.clone()))?,
+                None => entity,
+            };
+            let action = Action::use_item(item, entity, target)?;
+            store.submit(&mut session, action)?;
+            eprintln!("{session}");
+        }
+        Args::Equip(item, name, entity, equip) => {
+            let mut store = store()?;
+            let mut session = store.load(&name)?;
+            let entity = match entity {
+                Some(entity) => entity,
+                None => session.party.first().ok_or(Error::NoSession)?.name.clone(),
+            };
+            let change = match equip {
+                true => Change::Equip(entity, item),
+                false => Change::Unequip(entity, item),
+            };
+            store.change(&mut session, change)?;
+            eprintln!("{session}");
+        }
+        Args::Template(entity) => {
+            entity.save_template()?;
+            eprintln!("template saved");
         }
-        Args::New(name) => {
-            let entity = Entity::new(name);
-            let session = Session::new(entity)?;
-            session.save()?;
+        Args::New(entity, seed, luck) => {
+            let mut session = Session::new(entity, seed)?;
+            session.luck = luck;
+            store()?.create(&session)?;
             eprintln!("session saved");
         }
+        Args::Simulate(simulation) => {
+            let report = simulation.run()?;
+            println!("{report}");
+        }
+        Args::Roll(dice, seed) => {
+            let mut rng = match seed {
+                Some(seed) => strings::Rng::new(seed),
+                None => strings::Rng::from_time(),
+            };
+            let roll = dice.roll(&mut rng);
+            println!("{dice}: {roll}");
+        }
         Args::Load(name) => {
             log!("name is {name:?}");
-            let session = Session::load(&name, 0)?;
+            let session = store()?.load(&name)?;
+            eprintln!("{session}");
+        }
+        Args::Relations(name) => {
+            let session = store()?.load(&name)?;
+            let names = session.party.iter().chain(&session.opponents);
+            let names = names.map(|e| e.name.as_str()).collect();
+            println!("{}", session.relations.matrix(names));
+        }
+        Args::NextEncounter(name, count) => {
+            let mut store = store()?;
+            let mut session = store.load(&name)?;
+            store.change(&mut session, Change::NextEncounter(count))?;
+            let names = session.opponents.iter().map(|e| e.name.as_str());
+            println!("{} showed up!", names.collect::<Vec<_>>().join(", "));
             eprintln!("{session}");
         }
-        Args::FeelingLucky => {
-            let try_catch = TryCatch::new(
-                Box::new(|| {
-                    let rng = strings::Rng::new();
-                    let lucky = strings::Boolean::get_data_from_config()
-                        .to_regular_old_boring_pre_2024_bool();
-                    let rng_lol = rng.rand().to_regular_old_boring_pre_2024_bool();
-
-                    ":)";
-                    if !lucky || !rng_lol {
-                        log!("You're not feeling lucky");
-                        return Err(Exception {
-                            kind: "RIP Bozo",
-                            message: "You're not feeling lucky".to_string(),
-                        });
-                    }
-
-                    Ok(())
-                }),
-                Box::new(|ex| {
-                    eprintln!("{ex}");
-                    panic!("L + Ratio");
-                }),
-            );
-
-            try_catch.do_try()?;
+        Args::Flee(name, entity) => {
+            let mut store = store()?;
+            let mut session = store.load(&name)?;
+            let entity = match entity {
+                Some(entity) => entity,
+                None => session.party.first().ok_or(Error::NoSession)?.name.clone(),
+            };
+            store.change(&mut session, Change::Flee(entity.clone()))?;
+            println!("{entity} and the party run for it");
+        }
+        Args::CampaignNew(name, party, seed, luck) => {
+            let party = party
+                .iter()
+                .map(|name| Entity::spawn(name))
+                .collect::<Result<Vec<_>>>()?;
+            let mut campaign = Campaign::new(name, party, seed);
+            campaign.luck = luck;
+            campaign.save()?;
+            println!("{campaign}");
+        }
+        Args::CampaignAdvance(name, count) => {
+            let mut campaign = Campaign::load(&name)?;
+            let session = campaign.advance(count)?;
+            let names = session.opponents.iter().map(|e| e.name.as_str());
+            println!("{} showed up!", names.collect::<Vec<_>>().join(", "));
+            campaign.save()?;
+        }
+        Args::CampaignStatus(name) => {
+            let campaign = Campaign::load(&name)?;
+            println!("{campaign}");
+        }
+        Args::Grid(name, grid) => {
+            let mut store = store()?;
+            let mut session = store.load(&name)?;
+            store.change(&mut session, Change::Grid(grid))?;
+            eprintln!("{session}");
+        }
+        Args::Move(to, name, entity) => {
+            let mut store = store()?;
+            let mut session = store.load(&name)?;
+            let entity = match entity {
+                Some(entity) => entity,
+                None => session.party.first().ok_or(Error::NoSession)?.name.clone(),
+            };
+            store.change(&mut session, Change::Move(entity.clone(), to))?;
+            // Stunned, it takes the turn without going anywhere
+            let grid = session.grid.as_ref().ok_or(Error::NoGrid)?;
+            match grid.position(&entity) == Some(to) {
+                true => println!("{entity} moves to {to}"),
+                false => println!("{entity} is stunned and stays put"),
+            }
+        }
+        Args::Serve(addr) => relay::serve(addr)?,
+        Args::Watch(name, last) => watch::watch(store()?.as_mut(), &name, last)?,
+        Args::Login(name) => {
+            let credentials = players::Credentials::new(name);
+            credentials.check()?;
+            credentials.save()?;
+            println!("playing as {}, keep {} to yourself", credentials.name, players::TOKEN_FILE);
+        }
+        Args::Lobby => {
+            let tables = store::open_remote(remote)?.lobby()?;
+            if tables.is_empty() {
+                println!("no one's hosting anything, try host <name>");
+            }
+            for table in tables {
+                println!("{table}");
+            }
+        }
+        Args::Host(name, slots) => {
+            let session = store::open_remote(remote)?.host(&name, slots)?;
+            lobby::print_seats(&session);
+        }
+        Args::Join(name, entity) => {
+            let session = store::open_remote(remote)?.join(&name, &entity)?;
+            lobby::print_seats(&session);
+        }
+        Args::Start(name) => {
+            let session = store::open_remote(remote)?.start(&name)?;
+            lobby::print_seats(&session);
+        }
+        Args::Play(name, false) => play::play(store()?.as_mut(), &name)?,
+        Args::Play(name, true) => tui::run(store()?.as_mut(), &name)?,
+        Args::Say(name, message) => {
+            let mut store = store()?;
+            let mut session = store.load(&name)?;
+            // A relay knows who it is anyway
+            let from = players::Credentials::load().map_or("someone".to_string(), |c| c.name);
+            store.say(&mut session, chat::Chat::new(from, &message))?;
+            println!("{}", session.chat.last().expect("just said"));
+        }
+        Args::List => {
+            for name in store()?.list()? {
+                println!("{name}");
+            }
+        }
+        Args::FeelingLucky(name, luck) => {
+            let mut store = store()?;
+            let mut session = store.load(&name)?;
+            if let Some(luck) = luck {
+                session.luck = luck;
+                store.save(&session)?;
+            }
+            match session.luck {
+                0 => println!("{name} is on their own"),
+                luck if luck > 0 => println!("{name} is feeling lucky (+{luck})"),
+                luck => println!("{name} is not feeling lucky ({luck})"),
+            }
         }
     }
 
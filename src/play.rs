@@ -0,0 +1,242 @@
+use std::time::Duration;
+
+use crate::actions::{Action, ActionKind};
+use crate::editor::Editor;
+use crate::error::{Error, Result};
+use crate::session::Session;
+use crate::store::SessionStore;
+use crate::verbs::Targeting;
+use crate::Entity;
+
+// Everything besides the verbs
+const COMMANDS: [&str; 6] = ["as", "status", "undo", "save", "quit", "help"];
+
+const HELP: &str = "\
+<verb> [target]   | Act, as whoever the prompt says, like fight tommy
+as <who>          | Act as someone else from now on
+status            | Everyone, as they are now
+undo              | Take back the last action, not on a relay
+save              | Write the session where it came from
+quit              | Leave, Ctrl-D too, twice if there's anything unsaved
+Tab finishes verbs and names, up and down go through what was typed";
+
+// A session kept in memory between commands, so a fight doesn't mean
+// loading it for every action. Nothing is written until save, except on a
+// relay, where actions go in as they happen so everyone sees them
+pub struct Repl<'a> {
+    store: &'a mut dyn SessionStore,
+    pub session: Session,
+    // Who the verbs are for
+    actor: String,
+    // The session before each action, for undo
+    before: Vec<Session>,
+    saved: bool,
+    // Quit was just refused for what isn't saved, so quitting again leaves
+    quitting: bool,
+}
+
+impl<'a> Repl<'a> {
+    pub fn new(store: &'a mut dyn SessionStore, name: &str) -> Result<Self> {
+        let session = store.load(name)?;
+        let actor = session.party.first().ok_or(Error::NoSession)?.name.clone();
+        Ok(Self {
+            store,
+            session,
+            actor,
+            before: vec![],
+            saved: true,
+            quitting: false,
+        })
+    }
+
+    pub fn actor(&self) -> &str {
+        &self.actor
+    }
+
+    // Catches up on whatever anyone else did, for up to `timeout`. Only on a
+    // relay, a session file changing underneath would lose what isn't saved
+    pub fn poll(&mut self, timeout: Duration) -> Result<bool> {
+        match self.store.shared() {
+            true => self.store.poll(&mut self.session, timeout),
+            false => Ok(false),
+        }
+    }
+
+    pub fn prompt(&self) -> String {
+        format!("{}> ", self.actor)
+    }
+
+    // Whoever it is, whatever case it's typed in
+    fn find(&self, name: &str) -> Result<&Entity> {
+        let mut everyone = self.session.party.iter().chain(&self.session.opponents);
+        everyone
+            .find(|e| e.name.eq_ignore_ascii_case(name))
+            .ok_or_else(|| Error::NoEntity(name.to_string()))
+    }
+
+    // Commands and verbs first, then names
+    pub fn complete(&self, before: &str) -> Vec<String> {
+        let words = before.split_whitespace().count();
+        let typing = !before.is_empty() && !before.ends_with(char::is_whitespace);
+        let word = match typing {
+            true => before.split_whitespace().next_back().unwrap_or_default(),
+            false => "",
+        };
+        let mut options = match words - typing as usize {
+            0 => {
+                let kinds = ActionKind::ALL.into_iter().filter_map(ActionKind::id);
+                let commands = COMMANDS.into_iter().chain(kinds).map(str::to_string);
+                let verbs = self.session.verbs.iter().map(|v| v.name.clone());
+                commands.chain(verbs).collect::<Vec<_>>()
+            }
+            _ => {
+                let everyone = self.session.party.iter().chain(&self.session.opponents);
+                everyone.map(|e| e.name.clone()).collect()
+            }
+        };
+        options.retain(|o| o.to_lowercase().starts_with(&word.to_lowercase()));
+        options.sort();
+        options.dedup();
+        options
+    }
+
+    // What the line printed, or nothing once it's time to go
+    pub fn run(&mut self, line: &str) -> Result<Option<String>> {
+        let words = line.split_whitespace().collect::<Vec<_>>();
+        let quitting = std::mem::take(&mut self.quitting);
+        let output = match words[..] {
+            [] => String::new(),
+            ["help"] => HELP.to_string(),
+            ["status"] => self.session.to_string(),
+            ["as", name] => {
+                self.actor = self.find(name)?.name.clone();
+                format!("acting as {}", self.actor)
+            }
+            // Everyone else on a relay has seen it happen by now
+            ["undo"] if self.store.shared() => return Err(Error::NoUndo),
+            ["undo"] => {
+                let before = self.before.pop().ok_or(Error::NothingToUndo)?;
+                let undone = &self.session.actions()[before.actions().len()..];
+                let undone = undone.iter().map(ToString::to_string).collect::<Vec<_>>();
+                self.session = before;
+                self.saved = false;
+                format!("took back: {}", undone.join(", "))
+            }
+            ["save"] if self.store.shared() => {
+                format!("{} is on the relay already", self.session.name())
+            }
+            ["save"] => {
+                self.store.save(&self.session)?;
+                self.saved = true;
+                format!("saved {}", self.session.name())
+            }
+            ["quit" | "exit"] if self.saved || quitting => return Ok(None),
+            ["quit" | "exit"] => {
+                self.quitting = true;
+                return Err(Error::Unsaved(self.session.name().to_string()));
+            }
+            [verb] => self.act(verb, None)?,
+            [verb, target] => self.act(verb, Some(target))?,
+            _ => return Err(Error::InvalidArgs("play ")),
+        };
+        Ok(Some(output))
+    }
+
+    // Picks a target like `action` does, when there isn't one
+    fn act(&mut self, verb: &str, target: Option<&str>) -> Result<String> {
+        let verb = self.session.verbs.find(verb)?.clone();
+        let entity = self.find(&self.actor)?;
+        let target = match target {
+            Some(target) => self.find(target)?,
+            None if verb.targets == Targeting::Enemy => {
+                let standing = self.session.opponents.iter().find(|e| e.is_alive());
+                standing.ok_or_else(|| Error::BadTarget(verb.name.clone(), "anyone".to_string()))?
+            }
+            None => entity,
+        };
+        let action = Action::perform(&verb, entity, target)?;
+        let before = self.session.clone();
+        match self.store.shared() {
+            true => self.store.submit(&mut self.session, action)?,
+            // On a copy, so a script failing halfway leaves the session be
+            false => {
+                let mut next = self.session.clone();
+                next.apply(action)?;
+                self.session = next;
+                self.saved = false;
+            }
+        }
+        let news = self
+            .session
+            .actions()
+            .iter()
+            .filter(|a| a.seq > before.seq());
+        let news = news.map(ToString::to_string).collect::<Vec<_>>();
+        self.before.push(before);
+        Ok(news.join("\n"))
+    }
+}
+
+pub fn play(store: &mut dyn SessionStore, name: &str) -> Result<()> {
+    let mut repl = Repl::new(store, name)?;
+    let mut editor = Editor::default();
+    println!("{}", repl.session);
+    println!("help for what there is to do");
+    loop {
+        let line = editor.read_line(&repl.prompt(), |before| repl.complete(before))?;
+        match repl.run(line.as_deref().unwrap_or("quit")) {
+            Ok(Some(output)) if output.is_empty() => {}
+            Ok(Some(output)) => println!("{output}"),
+            Ok(None) => return Ok(()),
+            // A typo shouldn't end the fight
+            Err(err) => println!("{err}"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Repl;
+    use crate::error::Error;
+    use crate::session::Session;
+    use crate::store::{Memory, SessionStore};
+    use crate::Entity;
+
+    #[test]
+    fn a_fight_happens_in_memory_until_saved() {
+        let gilgamesh = Entity::new("Gilgamesh".to_string());
+        let mut tommy = Entity::new("Tommy".to_string());
+        tommy.set_max_health(50);
+        let session = Session::encounter("test".to_string(), vec![gilgamesh], vec![tommy], 0);
+        let mut store = Memory::default();
+        store.save(&session).unwrap();
+        let mut repl = Repl::new(&mut store, "test").unwrap();
+
+        assert_eq!(repl.complete("fi"), ["fight"]);
+        assert_eq!(repl.complete("fight t"), ["Tommy"]);
+        assert!(repl.complete("").contains(&"undo".to_string()));
+        assert!(repl.complete("fight ").contains(&"Gilgamesh".to_string()));
+
+        let fought = repl.run("hit tommy").unwrap().unwrap();
+        assert!(fought.contains("Gilgamesh fights Tommy"), "{fought}");
+        let hurt = repl.session.entity("Tommy").unwrap().health;
+        assert!(hurt < 50);
+        assert_eq!(repl.store.load("test").unwrap(), session);
+        assert!(repl.run("as nobody").is_err());
+        repl.run("undo").unwrap();
+        assert_eq!(repl.session, session);
+        assert!(repl.run("undo").is_err());
+
+        repl.run("fight").unwrap();
+        assert_eq!(repl.store.load("test").unwrap(), session);
+        repl.run("save").unwrap();
+        assert_eq!(repl.store.load("test").unwrap(), repl.session);
+        let saved = repl.session.clone();
+
+        // Quitting with something unsaved takes asking twice
+        repl.run("fight").unwrap();
+        assert!(matches!(repl.run("quit"), Err(Error::Unsaved(_))));
+        assert_eq!(repl.run("quit").unwrap(), None);
+        assert_eq!(store.load("test").unwrap(), saved);
+    }
+}
@@ -0,0 +1,210 @@
+use std::fmt::{self, Display, Formatter};
+use std::fs::{self, File};
+use std::io::Read;
+use std::str::FromStr;
+
+use crate::error::{Error, Result};
+use crate::strings::Rng;
+
+// Who this side plays as on a relay, written by `login`
+pub const TOKEN_FILE: &str = "token.txt";
+// Everyone a relay knows, next to its sessions
+pub const FILENAME: &str = "players.txt";
+// For names and tokens alike, a token being 32
+const MAX_LEN: usize = 64;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Credentials {
+    pub name: String,
+    pub token: String,
+}
+
+impl Credentials {
+    pub fn new(name: String) -> Self {
+        Self {
+            name,
+            token: token(),
+        }
+    }
+
+    pub fn load() -> Result<Self> {
+        let text = match fs::read_to_string(TOKEN_FILE) {
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Err(Error::NoToken),
+            text => text?,
+        };
+        match text.split_whitespace().collect::<Vec<_>>()[..] {
+            [name, token] => Ok(Self {
+                name: name.to_string(),
+                token: token.to_string(),
+            }),
+            _ => Err(Error::InvalidData(TOKEN_FILE, 1)),
+        }
+    }
+
+    // Both go on a line of players.txt, so nothing that would break it up
+    pub fn check(&self) -> Result<()> {
+        for value in [&self.name, &self.token] {
+            let bad = |c: char| c.is_whitespace() || c.is_control() || c == '#';
+            if value.is_empty() || value.chars().count() > MAX_LEN || value.contains(bad) {
+                return Err(Error::BadName(value.clone(), MAX_LEN));
+            }
+        }
+        Ok(())
+    }
+
+    pub fn save(&self) -> Result<()> {
+        fs::write(TOKEN_FILE, format!("{} {}\n", self.name, self.token))?;
+        Ok(())
+    }
+}
+
+// From the system if it will give some, or the time if not
+fn token() -> String {
+    let mut bytes = [0; 16];
+    let random = File::open("/dev/urandom").and_then(|mut f| f.read_exact(&mut bytes));
+    if random.is_err() {
+        let mut rng = Rng::from_time();
+        for chunk in bytes.chunks_mut(8) {
+            chunk.copy_from_slice(&rng.next_u64().to_be_bytes());
+        }
+    }
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Role {
+    #[default]
+    Player,
+    // Can act as anyone and claim anyone, for running a game
+    Admin,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Player {
+    pub name: String,
+    pub token: String,
+    pub role: Role,
+}
+
+// The relay's accounts. Whoever connects first with a name gets it, from
+// then on only with the same token. Admins are made by editing the file
+#[derive(Debug, Default)]
+pub struct Players {
+    players: Vec<Player>,
+    // Kept in memory only without one
+    path: Option<&'static str>,
+}
+
+impl Players {
+    pub fn load() -> Result<Self> {
+        let mut players: Self = match fs::read_to_string(FILENAME) {
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Self::default(),
+            text => text?.parse()?,
+        };
+        players.path = Some(FILENAME);
+        Ok(players)
+    }
+
+    pub fn sign_in(&mut self, credentials: &Credentials) -> Result<Player> {
+        if let Some(player) = self.players.iter().find(|p| p.name == credentials.name) {
+            return match player.token == credentials.token {
+                true => Ok(player.clone()),
+                false => Err(Error::BadToken(credentials.name.clone())),
+            };
+        }
+        credentials.check()?;
+        let player = Player {
+            name: credentials.name.clone(),
+            token: credentials.token.clone(),
+            role: Role::Player,
+        };
+        self.players.push(player.clone());
+        if let Some(path) = self.path {
+            fs::write(path, self.to_string())?;
+        }
+        Ok(player)
+    }
+}
+
+impl FromStr for Players {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let mut players = Self::default();
+        for (i, line) in s.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or_default();
+            let (name, token, role) = match line.split_whitespace().collect::<Vec<_>>()[..] {
+                [] => continue,
+                [name, token, role] => (name, token, role),
+                _ => return Err(Error::InvalidData(FILENAME, i + 1)),
+            };
+            players.players.push(Player {
+                name: name.to_string(),
+                token: token.to_string(),
+                role: match role {
+                    "player" => Role::Player,
+                    "admin" => Role::Admin,
+                    _ => return Err(Error::InvalidData(FILENAME, i + 1)),
+                },
+            });
+        }
+        Ok(players)
+    }
+}
+
+impl Display for Players {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        writeln!(f, "# name  token  role (player or admin)")?;
+        for player in &self.players {
+            let role = match player.role {
+                Role::Player => "player",
+                Role::Admin => "admin",
+            };
+            writeln!(f, "{} {} {role}", player.name, player.token)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Credentials, Players, Role};
+
+    #[test]
+    fn names_belong_to_whoever_came_first() {
+        let mut players: Players = "alice 00ff admin # runs the game\n".parse().unwrap();
+        let alice = Credentials {
+            name: "alice".to_string(),
+            token: "00ff".to_string(),
+        };
+        assert_eq!(players.sign_in(&alice).unwrap().role, Role::Admin);
+
+        let bob = Credentials::new("bob".to_string());
+        assert_eq!(bob.token.len(), 32);
+        assert_eq!(players.sign_in(&bob).unwrap().role, Role::Player);
+        let impostor = Credentials::new("bob".to_string());
+        assert!(players.sign_in(&impostor).is_err());
+
+        let reread: Players = players.to_string().parse().unwrap();
+        assert_eq!(reread.players, players.players);
+
+        // Nothing that would break up or add a line in players.txt
+        for name in [
+            "",
+            "eve\neve 00ff admin",
+            "e ve",
+            "eve#",
+            "eve\u{7}",
+            &"e".repeat(65),
+        ] {
+            let eve = Credentials::new(name.to_string());
+            assert!(players.sign_in(&eve).is_err(), "{name:?}");
+        }
+        let tokenless = Credentials {
+            name: "eve".to_string(),
+            token: String::new(),
+        };
+        assert!(players.sign_in(&tokenless).is_err());
+        assert_eq!(reread.players, players.players);
+    }
+}
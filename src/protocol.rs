@@ -0,0 +1,500 @@
+use std::io::{ErrorKind, Read, Write};
+
+use crate::actions::Action;
+use crate::chat::Chat;
+use crate::encounter::Encounter;
+use crate::error::{Error, Result};
+use crate::grid::{Grid, Pos};
+use crate::items::Item;
+use crate::lobby::{self, Seats, Table};
+use crate::relations::Relations;
+use crate::serde::{
+    field_len, serialize, Deserialize, Field, FieldReader, FieldType, Serialize, Serializer,
+};
+use crate::session::Session;
+use crate::Entity;
+
+// Bumped whenever a message changes shape. Both sides say hello first and
+// won't talk to a different version
+pub const VERSION: u16 = 8;
+
+// Anything bigger is someone up to no good, not a session
+const MAX_FRAME: usize = 16 << 20;
+
+// What goes back and forth between the relay and its clients. Each message
+// is a field of its own, with a field type from 32 on
+#[derive(Debug, Clone, PartialEq)]
+pub enum Message {
+    // The version, and who's connecting with their token. The relay
+    // answers with its version and who it took them for, without a token
+    Hello(u16, String, String),
+    // Fails if the relay already has a session by that name
+    CreateSession(Session),
+    // Stores the session as it is, only from admins. Players send actions
+    // and changes instead, which the relay checks
+    SaveSession(Session),
+    // Answered with a snapshot, then the deltas as they happen. Claims the
+    // named entity in the party for the player joining, if there is one
+    JoinSession(String, Option<String>),
+    SubmitAction(String, Action),
+    ListSessions,
+    Snapshot(Session),
+    StateDelta(StateDelta),
+    Sessions(Vec<String>),
+    Error(String),
+    // Sent straight back
+    Ping(u64),
+    // Opens a session for this many players, 0 for one per party member
+    Host(String, u8),
+    // Only from the host, to stop waiting for whoever hasn't come
+    Start(String),
+    // Answered with every hosted session
+    Lobby,
+    Tables(Vec<Table>),
+    // Back after a drop, having seen up to this action and this many lines
+    // of chat. Answered with what was missed, or a snapshot if that's too
+    // much, then followed like a join
+    Resync(String, u64, u64),
+    // Said to everyone in the session, playing or watching. The relay
+    // sends it on as chat, from whoever it came from
+    Say(String, String),
+    Chat(String, Chat),
+    // Answered like an action, with what changed
+    Change(String, Change),
+}
+
+// Whatever players do to a session besides actions, done on the relay so
+// it can check who's asking
+#[derive(Debug, Clone, PartialEq)]
+pub enum Change {
+    Equip(String, Item),
+    Unequip(String, Item),
+    Flee(String),
+    Move(String, Pos),
+    Grid(Grid),
+    // How many opponents, or as many as the party has standing
+    NextEncounter(Option<usize>),
+}
+
+impl Change {
+    // Whose owner may ask for it, anyone playing if no one's in particular
+    pub fn entity(&self) -> Option<&str> {
+        match self {
+            Self::Equip(entity, _)
+            | Self::Unequip(entity, _)
+            | Self::Flee(entity)
+            | Self::Move(entity, _) => Some(entity),
+            Self::Grid(_) | Self::NextEncounter(_) => None,
+        }
+    }
+
+    // Which one it is goes first, then whatever it needs
+    fn serialize(&self, buf: &mut Serializer) -> usize {
+        let (kind, fields) = match self {
+            Self::Equip(entity, item) => (0, vec![Field::Str(entity.clone()), Field::Item(*item)]),
+            Self::Unequip(entity, item) => {
+                (1, vec![Field::Str(entity.clone()), Field::Item(*item)])
+            }
+            Self::Flee(entity) => (2, vec![Field::Str(entity.clone())]),
+            Self::Move(entity, to) => (3, vec![Field::Str(entity.clone()), Field::Pos(*to)]),
+            Self::Grid(grid) => (4, vec![Field::Grid(grid.clone())]),
+            Self::NextEncounter(count) => {
+                let count = count.iter().map(|&n| Field::U64(n as u64)).collect();
+                (5, vec![Field::Vec(count)])
+            }
+        };
+        let size = Field::Byte(kind).serialize(buf);
+        size + fields
+            .iter()
+            .map(|field| field.serialize(buf))
+            .sum::<usize>()
+    }
+
+    fn read(reader: &mut FieldReader<'_>) -> Result<Self> {
+        Ok(match reader.read_field::<u8, _>()? {
+            0 => Self::Equip(reader.read_field()?, reader.read_field()?),
+            1 => Self::Unequip(reader.read_field()?, reader.read_field()?),
+            2 => Self::Flee(reader.read_field()?),
+            3 => Self::Move(reader.read_field()?, reader.read_field()?),
+            4 => Self::Grid(reader.read_field()?),
+            5 => {
+                let count = reader.read_field::<Vec<u64>, _>()?.pop();
+                Self::NextEncounter(count.map(|n| n as usize))
+            }
+            _ => return Err(Error::InvalidFieldType),
+        })
+    }
+}
+
+// What an action changed: the actions it added to the log, and everyone
+// as they are after. Chat only comes along on a resync, it's sent as it
+// happens otherwise
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct StateDelta {
+    pub session: String,
+    // How many actions the session had before these
+    pub from: u64,
+    pub actions: Vec<Action>,
+    pub party: Vec<Entity>,
+    pub opponents: Vec<Entity>,
+    pub relations: Relations,
+    pub encounter: Encounter,
+    pub grid: Option<Grid>,
+    pub owners: Vec<(String, String)>,
+    pub seats: Option<Seats>,
+    // How many lines of chat there were before these
+    pub chat_from: u64,
+    pub chat: Vec<Chat>,
+}
+
+impl Message {
+    fn field_type(&self) -> FieldType {
+        match self {
+            Self::Hello(_, _, _) => FieldType::Hello,
+            Self::CreateSession(_) => FieldType::CreateSession,
+            Self::SaveSession(_) => FieldType::SaveSession,
+            Self::JoinSession(_, _) => FieldType::JoinSession,
+            Self::SubmitAction(_, _) => FieldType::SubmitAction,
+            Self::ListSessions => FieldType::ListSessions,
+            Self::Snapshot(_) => FieldType::Snapshot,
+            Self::StateDelta(_) => FieldType::StateDelta,
+            Self::Sessions(_) => FieldType::Sessions,
+            Self::Error(_) => FieldType::ErrorMessage,
+            Self::Ping(_) => FieldType::Ping,
+            Self::Host(_, _) => FieldType::Host,
+            Self::Start(_) => FieldType::Start,
+            Self::Lobby => FieldType::Lobby,
+            Self::Tables(_) => FieldType::Tables,
+            Self::Resync(_, _, _) => FieldType::Resync,
+            Self::Say(_, _) => FieldType::Say,
+            Self::Chat(_, _) => FieldType::Chat,
+            Self::Change(_, _) => FieldType::Change,
+        }
+    }
+
+    // The reader holds what's inside the field, its type says what to expect
+    pub fn read(field_type: FieldType, reader: &mut FieldReader<'_>) -> Result<Self> {
+        let message = match field_type {
+            FieldType::Hello => Self::Hello(
+                reader.read_field::<u64, _>()? as u16,
+                reader.read_field()?,
+                reader.read_field()?,
+            ),
+            FieldType::CreateSession => Self::CreateSession(reader.read_field()?),
+            FieldType::SaveSession => Self::SaveSession(reader.read_field()?),
+            FieldType::JoinSession => Self::JoinSession(
+                reader.read_field()?,
+                reader.read_field::<Vec<String>, _>()?.pop(),
+            ),
+            FieldType::SubmitAction => {
+                Self::SubmitAction(reader.read_field()?, reader.read_field()?)
+            }
+            FieldType::ListSessions => Self::ListSessions,
+            FieldType::Snapshot => Self::Snapshot(reader.read_field()?),
+            FieldType::StateDelta => Self::StateDelta(StateDelta::deserialize(reader)?),
+            FieldType::Sessions => Self::Sessions(reader.read_field()?),
+            FieldType::ErrorMessage => Self::Error(reader.read_field()?),
+            FieldType::Ping => Self::Ping(reader.read_field()?),
+            FieldType::Host => Self::Host(reader.read_field()?, reader.read_field()?),
+            FieldType::Start => Self::Start(reader.read_field()?),
+            FieldType::Lobby => Self::Lobby,
+            FieldType::Tables => Self::Tables(Table::read_all(reader)?),
+            FieldType::Resync => Self::Resync(
+                reader.read_field()?,
+                reader.read_field()?,
+                reader.read_field()?,
+            ),
+            FieldType::Say => Self::Say(reader.read_field()?, reader.read_field()?),
+            FieldType::Chat => Self::Chat(
+                reader.read_field()?,
+                Chat::read_all(reader)?
+                    .pop()
+                    .ok_or(Error::InvalidFieldType)?,
+            ),
+            FieldType::Change => Self::Change(reader.read_field()?, Change::read(reader)?),
+            _ => return Err(Error::InvalidFieldType),
+        };
+        match reader.is_empty() {
+            true => Ok(message),
+            false => Err(Error::InvalidFieldType),
+        }
+    }
+}
+
+impl Serialize for Message {
+    fn serialize(&self, buf: &mut Serializer) -> usize {
+        let s = buf.unknown_size(self.field_type());
+        let size = match self {
+            Self::Hello(version, name, token) => {
+                Field::U64(*version as u64).serialize(buf)
+                    + Field::Str(name.clone()).serialize(buf)
+                    + Field::Str(token.clone()).serialize(buf)
+            }
+            Self::CreateSession(session) | Self::SaveSession(session) | Self::Snapshot(session) => {
+                session.serialize(buf)
+            }
+            Self::JoinSession(name, entity) => {
+                let entity = entity.iter().cloned().map(Field::Str).collect();
+                Field::Str(name.clone()).serialize(buf) + Field::Vec(entity).serialize(buf)
+            }
+            Self::Error(message) => Field::Str(message.clone()).serialize(buf),
+            Self::SubmitAction(name, action) => {
+                Field::Str(name.clone()).serialize(buf) + action.serialize(buf)
+            }
+            Self::ListSessions => 0,
+            Self::StateDelta(delta) => delta.serialize(buf),
+            Self::Sessions(names) => {
+                let names = names.iter().cloned().map(Field::Str).collect();
+                Field::Vec(names).serialize(buf)
+            }
+            Self::Ping(n) => Field::U64(*n).serialize(buf),
+            Self::Host(name, slots) => {
+                Field::Str(name.clone()).serialize(buf) + Field::Byte(*slots).serialize(buf)
+            }
+            Self::Start(name) => Field::Str(name.clone()).serialize(buf),
+            Self::Lobby => 0,
+            Self::Tables(tables) => Table::serialize_all(tables, buf),
+            Self::Resync(name, seq, chat) => {
+                Field::Str(name.clone()).serialize(buf)
+                    + Field::U64(*seq).serialize(buf)
+                    + Field::U64(*chat).serialize(buf)
+            }
+            Self::Say(name, text) => {
+                Field::Str(name.clone()).serialize(buf) + Field::Str(text.clone()).serialize(buf)
+            }
+            // A column of one
+            Self::Chat(name, chat) => {
+                Field::Str(name.clone()).serialize(buf)
+                    + Chat::serialize_all(std::slice::from_ref(chat), buf)
+            }
+            Self::Change(name, change) => {
+                Field::Str(name.clone()).serialize(buf) + change.serialize(buf)
+            }
+        };
+        s(buf, size)
+    }
+}
+
+// Written inside the message rather than as a field of its own
+impl StateDelta {
+    fn serialize(&self, buf: &mut Serializer) -> usize {
+        let mut size = Field::Str(self.session.clone()).serialize(buf);
+        size += Field::U64(self.from).serialize(buf);
+        size += self.actions.serialize(buf);
+        size += self.party.serialize(buf);
+        size += self.opponents.serialize(buf);
+        size += self.relations.0.serialize(buf);
+        size += Field::Byte(self.encounter as u8).serialize(buf);
+        size += self.grid.iter().cloned().collect::<Vec<_>>().serialize(buf);
+        size += lobby::serialize_owners(&self.owners, buf);
+        size += Seats::serialize(self.seats.as_ref(), buf);
+        size += Field::U64(self.chat_from).serialize(buf);
+        size += Chat::serialize_all(&self.chat, buf);
+        size
+    }
+}
+
+impl Deserialize for StateDelta {
+    fn deserialize(reader: &mut FieldReader<'_>) -> Result<Self>
+    where
+        Self: Sized,
+    {
+        let delta = Self {
+            session: reader.read_field()?,
+            from: reader.read_field()?,
+            actions: reader.read_field()?,
+            party: reader.read_field()?,
+            opponents: reader.read_field()?,
+            relations: Relations(reader.read_field()?),
+            encounter: reader.read_field::<u8, _>()?.try_into()?,
+            grid: reader.read_field::<Vec<Grid>, _>()?.pop(),
+            owners: lobby::read_owners(reader)?,
+            seats: Seats::read(reader)?,
+            chat_from: reader.read_field()?,
+            chat: Chat::read_all(reader)?,
+        };
+
+        Ok(delta)
+    }
+}
+
+// Bytes off the wire, however they were split up, into whole messages
+#[derive(Debug, Default)]
+pub struct Frames {
+    buffer: Vec<u8>,
+}
+
+impl Frames {
+    pub fn push(&mut self, bytes: &[u8]) {
+        self.buffer.extend_from_slice(bytes);
+    }
+
+    // Nothing until a whole frame is in. A frame that doesn't hold a message
+    // is dropped, so the next one can still be read
+    pub fn next(&mut self) -> Result<Option<Message>> {
+        // Type, then a length, like any other field
+        let Some((header, len)) = field_len(&self.buffer) else {
+            return Ok(None);
+        };
+        if len > MAX_FRAME {
+            // There's no telling where the next frame starts
+            self.buffer.clear();
+            return Err(Error::TooBig(len));
+        }
+        if self.buffer.len() < header + len {
+            return Ok(None);
+        }
+        let frame = self.buffer.drain(..header + len).collect::<Vec<_>>();
+        match FieldReader::new(&frame).read_field()? {
+            Field::Message(message) => Ok(Some(*message)),
+            _ => Err(Error::InvalidFieldType),
+        }
+    }
+}
+
+pub fn send(stream: &mut impl Write, message: &Message) -> Result<()> {
+    stream.write_all(&serialize(message))?;
+    Ok(())
+}
+
+// Blocks until a whole message is in, keeping whatever came after it
+pub fn receive(stream: &mut impl Read, frames: &mut Frames) -> Result<Message> {
+    let mut chunk = [0; 4096];
+    loop {
+        if let Some(message) = frames.next()? {
+            return Ok(message);
+        }
+        match stream.read(&mut chunk)? {
+            0 => return Err(std::io::Error::from(ErrorKind::UnexpectedEof).into()),
+            n => frames.push(&chunk[..n]),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Change, Frames, Message, StateDelta, VERSION};
+    use crate::actions::Action;
+    use crate::chat::Chat;
+    use crate::encounter::Encounter;
+    use crate::grid::{Grid, Pos};
+    use crate::items::Item;
+    use crate::lobby::Table;
+    use crate::serde::serialize;
+    use crate::session::Session;
+    use crate::Entity;
+
+    fn messages() -> Vec<Message> {
+        let gilgamesh = Entity::new("Gilgamesh".to_string());
+        let tommy = Entity::new("Tommy".to_string());
+        let session =
+            Session::encounter("test".to_string(), vec![gilgamesh.clone()], vec![tommy], 3);
+        vec![
+            Message::Hello(VERSION, "alice".to_string(), "00ff".to_string()),
+            Message::CreateSession(session.clone()),
+            Message::SaveSession(session.clone()),
+            Message::JoinSession("test".to_string(), None),
+            Message::JoinSession("test".to_string(), Some("Gilgamesh".to_string())),
+            Message::SubmitAction("test".to_string(), Action::spawn("Tommy".to_string())),
+            Message::ListSessions,
+            Message::Snapshot(session),
+            Message::StateDelta(StateDelta {
+                session: "test".to_string(),
+                from: 2,
+                actions: vec![Action::walk("Gilgamesh".to_string(), Pos::new(1, 1))],
+                party: vec![gilgamesh],
+                encounter: Encounter::InProgress,
+                grid: Some(Grid::new(3, 3, vec![]).unwrap()),
+                owners: vec![("Gilgamesh".to_string(), "alice".to_string())],
+                chat_from: 1,
+                chat: vec![Chat::new("bob".to_string(), "missed me?")],
+                ..Default::default()
+            }),
+            Message::Sessions(vec!["test".to_string(), "other".to_string()]),
+            Message::Error("nope".to_string()),
+            Message::Ping(42),
+            Message::Host("test".to_string(), 4),
+            Message::Start("test".to_string()),
+            Message::Lobby,
+            Message::Resync("test".to_string(), 7, 2),
+            Message::Say("test".to_string(), "/me waves".to_string()),
+            Message::Chat("test".to_string(), Chat::new("alice".to_string(), "hi")),
+            Message::Change(
+                "test".to_string(),
+                Change::Equip("Gilgamesh".to_string(), Item::Sword),
+            ),
+            Message::Change(
+                "test".to_string(),
+                Change::Move("Gilgamesh".to_string(), Pos::new(2, 1)),
+            ),
+            Message::Change("test".to_string(), Change::NextEncounter(Some(3))),
+            Message::Change("test".to_string(), Change::NextEncounter(None)),
+            Message::Tables(vec![Table {
+                session: "test".to_string(),
+                host: "alice".to_string(),
+                players: 1,
+                slots: 4,
+                waiting: true,
+                encounter: Encounter::Setup,
+            }]),
+        ]
+    }
+
+    #[test]
+    fn messages_round_trip_a_byte_at_a_time() {
+        let messages = messages();
+        let bytes = messages.iter().flat_map(serialize).collect::<Vec<_>>();
+        let mut frames = Frames::default();
+        let mut received = vec![];
+        for byte in bytes {
+            frames.push(&[byte]);
+            while let Some(message) = frames.next().unwrap() {
+                received.push(message);
+            }
+        }
+        assert_eq!(received, messages);
+    }
+
+    #[test]
+    fn sessions_bigger_than_64_kib_round_trip() {
+        let gilgamesh = Entity::new("Gilgamesh".to_string());
+        let mut session = Session::encounter("test".to_string(), vec![gilgamesh], vec![], 0);
+        let verb = session.verbs.get("neutral").unwrap().clone();
+        for _ in 0..1000 {
+            let gilgamesh = session.entity("Gilgamesh").unwrap();
+            let action = Action::perform(&verb, gilgamesh, gilgamesh).unwrap();
+            session.apply(action).unwrap();
+        }
+        let snapshot = Message::Snapshot(session);
+        let bytes = serialize(&snapshot);
+        assert!(bytes.len() > u16::MAX as usize);
+        let mut frames = Frames::default();
+        frames.push(&bytes);
+        assert_eq!(frames.next().unwrap(), Some(snapshot));
+    }
+
+    #[test]
+    fn malformed_frames_are_errors() {
+        let ping = serialize(&Message::Ping(42));
+        // Not a message at all
+        let mut frames = Frames::default();
+        frames.push(&serialize(&crate::serde::Field::Str("hi".to_string())));
+        frames.push(&ping);
+        assert!(frames.next().is_err());
+        assert_eq!(frames.next().unwrap(), Some(Message::Ping(42)));
+
+        // An unknown field type, then a length longer than what's inside
+        for bad in [vec![99, 0, 1, 0], vec![42, 0, 4, 11, 0, 8, 0]] {
+            let mut frames = Frames::default();
+            frames.push(&bad);
+            frames.push(&ping);
+            assert!(frames.next().is_err(), "{bad:?}");
+            assert_eq!(frames.next().unwrap(), Some(Message::Ping(42)));
+        }
+
+        // Half a frame is nothing yet
+        let mut frames = Frames::default();
+        frames.push(&ping[..ping.len() - 1]);
+        assert_eq!(frames.next().unwrap(), None);
+    }
+}
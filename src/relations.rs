@@ -0,0 +1,168 @@
+use std::fmt::{self, Display, Formatter};
+
+use crate::actions::Action;
+use crate::error::Result;
+use crate::serde::{Deserialize, Field, FieldReader, FieldType, Serialize, Serializer};
+use crate::verbs::Formula;
+
+// How far affinity goes either way
+pub const MAX_AFFINITY: i8 = 10;
+// Won't attack whoever it feels this much for
+pub const ADORES: i8 = 4;
+// An opponent this fond of anyone in the party joins it
+pub const DEFECTS: i8 = 6;
+
+const LOVE: i8 = 2;
+const HURT: i8 = -2;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Relation {
+    pub from: String,
+    pub to: String,
+    pub affinity: i8,
+}
+
+// Directed: how `from` feels about `to` says nothing about the other way
+// round. Pairs that never met are at 0 and aren't stored
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Relations(pub Vec<Relation>);
+
+impl Relations {
+    pub fn get(&self, from: &str, to: &str) -> i8 {
+        self.0
+            .iter()
+            .find(|r| r.from == from && r.to == to)
+            .map_or(0, |r| r.affinity)
+    }
+
+    fn change(&mut self, from: &str, to: &str, by: i8) {
+        let affinity = (self.get(from, to) + by).clamp(-MAX_AFFINITY, MAX_AFFINITY);
+        self.0.retain(|r| r.from != from || r.to != to);
+        if affinity != 0 {
+            self.0.push(Relation {
+                from: from.to_string(),
+                to: to.to_string(),
+                affinity,
+            });
+        }
+    }
+
+    // The target is the one whose feelings change, towards whoever loved or
+    // hurt them. Nobody holds a grudge against themselves
+    pub fn felt(&mut self, action: &Action, formula: &Formula) {
+        let Some(target) = action.target.as_deref() else {
+            return;
+        };
+        if target == action.entity {
+            return;
+        }
+        match (formula.is_harmful(), formula.heals()) {
+            (true, _) => self.change(target, &action.entity, HURT),
+            (false, true) => self.change(target, &action.entity, LOVE),
+            _ => {}
+        }
+    }
+
+    pub fn matrix<'a>(&'a self, names: Vec<&'a str>) -> Matrix<'a> {
+        Matrix {
+            relations: self,
+            names,
+        }
+    }
+}
+
+pub struct Matrix<'a> {
+    relations: &'a Relations,
+    names: Vec<&'a str>,
+}
+
+impl Display for Matrix<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let width = self
+            .names
+            .iter()
+            .map(|n| n.chars().count())
+            .max()
+            .unwrap_or(0);
+        write!(f, "{:width$}", "")?;
+        for to in &self.names {
+            write!(f, "  {to:>width$}")?;
+        }
+        writeln!(f)?;
+        for from in &self.names {
+            write!(f, "{from:width$}")?;
+            for to in &self.names {
+                let cell = match self.relations.get(from, to) {
+                    _ if from == to => "-".to_string(),
+                    0 => ".".to_string(),
+                    affinity if affinity >= ADORES => format!("♥{affinity:+}"),
+                    affinity => format!("{affinity:+}"),
+                };
+                write!(f, "  {cell:>width$}")?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+impl Serialize for Relation {
+    fn serialize(&self, buf: &mut Serializer) -> usize {
+        let s = buf.unknown_size(FieldType::Relation);
+        let mut size = Field::Str(self.from.clone()).serialize(buf);
+        size += Field::Str(self.to.clone()).serialize(buf);
+        size += Field::Byte(self.affinity as u8).serialize(buf);
+        s(buf, size)
+    }
+}
+
+impl Deserialize for Relation {
+    fn deserialize(reader: &mut FieldReader<'_>) -> Result<Self>
+    where
+        Self: Sized,
+    {
+        let relation = Self {
+            from: reader.read_field()?,
+            to: reader.read_field()?,
+            affinity: reader.read_field::<u8, _>()? as i8,
+        };
+
+        Ok(relation)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Relations, MAX_AFFINITY};
+    use crate::actions::{Action, ActionKind};
+    use crate::verbs::Verbs;
+    use crate::Entity;
+
+    #[test]
+    fn feelings_are_directed_and_clamped() {
+        let gilgamesh = Entity::new("Gilgamesh".to_string());
+        let tommy = Entity::new("Tommy".to_string());
+        let love = Action::interact(ActionKind::Love, &gilgamesh, &tommy).unwrap();
+        let fight = Action::interact(ActionKind::Fight, &gilgamesh, &tommy).unwrap();
+        let verbs = Verbs::default();
+        let loving = &verbs.get("love").unwrap().effect;
+        let hurtful = &verbs.get("fight").unwrap().effect;
+        let mut relations = Relations::default();
+
+        relations.felt(&love, loving);
+        assert_eq!(relations.get("Tommy", "Gilgamesh"), 2);
+        assert_eq!(relations.get("Gilgamesh", "Tommy"), 0);
+
+        relations.felt(&fight, hurtful);
+        assert_eq!(relations.get("Tommy", "Gilgamesh"), 0);
+        assert!(relations.0.is_empty());
+
+        for _ in 0..20 {
+            relations.felt(&fight, hurtful);
+        }
+        assert_eq!(relations.get("Tommy", "Gilgamesh"), -MAX_AFFINITY);
+        let fight = Action::interact(ActionKind::Fight, &tommy, &tommy).unwrap();
+        relations.felt(&fight, hurtful);
+        assert_eq!(relations.get("Tommy", "Tommy"), 0);
+    }
+}
@@ -0,0 +1,623 @@
+use std::collections::HashMap;
+use std::io::ErrorKind;
+use std::net::{Shutdown, SocketAddr, TcpListener, TcpStream};
+use std::sync::mpsc::{self, SyncSender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use crate::actions;
+use crate::chat::Chat;
+use crate::error::{Error, Result};
+use crate::lobby::Table;
+use crate::players::{Credentials, Player, Players, Role};
+use crate::protocol::{receive, send, Change, Frames, Message, VERSION};
+use crate::session::{self, Session};
+use crate::store::{Local, SessionStore};
+
+// How far behind a client may be to get only what it missed
+const RESYNC_LIMIT: u64 = 64;
+
+// How many messages may wait on a client before it's taken to have left
+const OUTBOX: usize = 256;
+
+// Owns the sessions while it runs. Everything goes through one lock, so
+// actions are applied one at a time in the order they arrive, and everyone
+// watching sees them in that same order
+pub struct Relay {
+    store: Box<dyn SessionStore + Send>,
+    players: Players,
+    sessions: HashMap<String, Session>,
+    watchers: HashMap<String, Vec<Outbox>>,
+}
+
+// Everything on its way to one client, written out by a thread of its own so
+// no one waits on anyone else's connection. Whole messages go in one at a
+// time, so replies and news never end up in the middle of each other
+#[derive(Clone)]
+struct Outbox {
+    queue: SyncSender<Message>,
+    stream: Arc<TcpStream>,
+}
+
+impl Outbox {
+    fn open(stream: &TcpStream) -> Result<Self> {
+        let (queue, messages) = mpsc::sync_channel::<Message>(OUTBOX);
+        let mut writer = stream.try_clone()?;
+        thread::spawn(move || {
+            for message in messages {
+                if send(&mut writer, &message).is_err() {
+                    let _ = writer.shutdown(Shutdown::Both);
+                    return;
+                }
+            }
+        });
+        Ok(Self {
+            queue,
+            stream: Arc::new(stream.try_clone()?),
+        })
+    }
+
+    // False once the client is gone. One that lets too much pile up is
+    // hung up on rather than waited for
+    fn send(&self, message: Message) -> bool {
+        let sent = self.queue.try_send(message).is_ok();
+        if !sent {
+            let _ = self.stream.shutdown(Shutdown::Both);
+        }
+        sent
+    }
+
+    fn same(&self, other: &Outbox) -> bool {
+        Arc::ptr_eq(&self.stream, &other.stream)
+    }
+}
+
+impl Relay {
+    pub fn new(store: Box<dyn SessionStore + Send>, players: Players) -> Self {
+        Self {
+            store,
+            players,
+            sessions: HashMap::new(),
+            watchers: HashMap::new(),
+        }
+    }
+
+    fn session(&mut self, name: &str) -> Result<&mut Session> {
+        if !self.sessions.contains_key(name) {
+            session::check_name(name)?;
+            let session = self.store.load(name)?;
+            self.sessions.insert(name.to_string(), session);
+        }
+        Ok(self.sessions.get_mut(name).expect("just inserted"))
+    }
+
+    fn watching(&self, name: &str, outbox: &Outbox) -> bool {
+        let watchers = self.watchers.get(name);
+        watchers.is_some_and(|w| w.iter().any(|w| w.same(outbox)))
+    }
+
+    // From now on, until the client is gone
+    fn follow(&mut self, name: String, outbox: &Outbox) {
+        if !self.watching(&name, outbox) {
+            self.watchers.entry(name).or_default().push(outbox.clone());
+        }
+    }
+
+    fn handle(&mut self, message: Message, outbox: &Outbox, player: &Player) -> Result<()> {
+        let admin = player.role == Role::Admin;
+        match message {
+            Message::Hello(_, _, _) => {
+                let hello = Message::Hello(VERSION, player.name.clone(), String::new());
+                outbox.send(hello);
+            }
+            Message::CreateSession(mut session) => {
+                // Someone else's `new` shouldn't wipe out a game in progress
+                if self.session(session.name()).is_ok() {
+                    return Err(Error::SessionExists(session.name().to_string()));
+                }
+                let party = session.party.iter().map(|e| e.name.clone());
+                session.owners = party.map(|e| (e, player.name.clone())).collect();
+                self.store.save(&session)?;
+                self.sessions
+                    .insert(session.name().to_string(), session.clone());
+                outbox.send(Message::Snapshot(session));
+            }
+            Message::SaveSession(mut session) => {
+                // Anything at all could be in it, so it's for running a game
+                if !admin {
+                    return Err(Error::NotAdmin(player.name.clone()));
+                }
+                let name = session.name().to_string();
+                // Everything but who owns who
+                match self.session(&name) {
+                    Ok(existing) => session.owners = existing.owners.clone(),
+                    Err(_) => {
+                        let party = session.party.iter().map(|e| e.name.clone());
+                        session.owners = party.map(|e| (e, player.name.clone())).collect();
+                    }
+                }
+                self.store.save(&session)?;
+                self.sessions.insert(name.clone(), session.clone());
+                self.tell(&name, &Message::Snapshot(session), outbox);
+            }
+            Message::JoinSession(name, claim) => {
+                let mut session = self.session(&name)?.clone();
+                match claim {
+                    // Everyone watching sees who sat down, and maybe the start
+                    Some(entity) => {
+                        session.claim(&entity, &player.name, admin)?;
+                        self.store.save(&session)?;
+                        self.sessions.insert(name.clone(), session.clone());
+                        self.tell(&name, &Message::Snapshot(session), outbox);
+                    }
+                    None => {
+                        outbox.send(Message::Snapshot(session));
+                    }
+                }
+                self.follow(name, outbox);
+            }
+            Message::Resync(name, seq, chat) => {
+                let session = self.session(&name)?.clone();
+                // Too far behind, or ahead of a session that started over
+                let chat = usize::try_from(chat)
+                    .ok()
+                    .filter(|&c| c <= session.chat.len());
+                let reply = match (session.seq().checked_sub(seq), chat) {
+                    (Some(gap), Some(chat)) if gap <= RESYNC_LIMIT => {
+                        Message::StateDelta(session.delta(seq, chat))
+                    }
+                    _ => Message::Snapshot(session),
+                };
+                outbox.send(reply);
+                self.follow(name, outbox);
+            }
+            Message::Host(name, slots) => {
+                let mut session = self.session(&name)?.clone();
+                // Anyone playing may open it up, but once it's hosted a seat
+                // doesn't make someone the host
+                match &session.seats {
+                    Some(seats) if !admin && seats.host != player.name => {
+                        return Err(Error::NotHost(player.name.clone(), name));
+                    }
+                    None if !admin && session.owner_of(&player.name).is_none() => {
+                        return Err(Error::NotPlaying(player.name.clone(), name));
+                    }
+                    _ => {}
+                }
+                session.host(&player.name, slots)?;
+                self.store.save(&session)?;
+                self.sessions.insert(name.clone(), session.clone());
+                self.tell(&name, &Message::Snapshot(session), outbox);
+            }
+            Message::Start(name) => {
+                let mut session = self.session(&name)?.clone();
+                let host = session.seats.as_ref().map(|s| s.host.as_str());
+                if !admin && host != Some(player.name.as_str()) {
+                    return Err(Error::NotHost(player.name.clone(), name));
+                }
+                session.start(&player.name);
+                self.store.save(&session)?;
+                self.sessions.insert(name.clone(), session.clone());
+                self.tell(&name, &Message::Snapshot(session), outbox);
+            }
+            Message::Lobby => {
+                let mut tables = vec![];
+                for name in self.store.list()? {
+                    if let Some(table) = self.session(&name).ok().and_then(|s| Table::new(s)) {
+                        tables.push(table);
+                    }
+                }
+                outbox.send(Message::Tables(tables));
+            }
+            Message::SubmitAction(name, mut action) => {
+                let mut session = self.session(&name)?.clone();
+                if let Some(seats) = session.seats.as_ref().filter(|s| s.waiting) {
+                    let seated = session.players().len();
+                    return Err(Error::Waiting(name, seated, seats.slots));
+                }
+                if !admin && session.owner(&action.entity) != Some(player.name.as_str()) {
+                    return Err(Error::NotYours(action.entity, player.name.clone()));
+                }
+                // When it happened is up to the relay, or cooldowns are too
+                action.start = actions::start();
+                let from = session.seq();
+                self.store.submit(&mut session, action)?;
+                let delta = Message::StateDelta(session.delta(from, session.chat.len()));
+                self.sessions.insert(name.clone(), session);
+                self.tell(&name, &delta, outbox);
+            }
+            Message::Change(name, change) => {
+                let mut session = self.session(&name)?.clone();
+                match change.entity() {
+                    Some(entity) if !admin && session.owner(entity) != Some(&player.name) => {
+                        return Err(Error::NotYours(entity.to_string(), player.name.clone()));
+                    }
+                    None if !admin && session.owner_of(&player.name).is_none() => {
+                        return Err(Error::NotPlaying(player.name.clone(), name));
+                    }
+                    _ => {}
+                }
+                // Moving takes a turn, like an action
+                if let (Change::Move(_, _), Some(seats)) = (&change, &session.seats) {
+                    if seats.waiting {
+                        let seated = session.players().len();
+                        return Err(Error::Waiting(name, seated, seats.slots));
+                    }
+                }
+                let from = session.seq();
+                self.store.change(&mut session, change)?;
+                let delta = Message::StateDelta(session.delta(from, session.chat.len()));
+                self.sessions.insert(name.clone(), session);
+                self.tell(&name, &delta, outbox);
+            }
+            Message::Say(name, text) => {
+                let mut session = self.session(&name)?.clone();
+                // Only for whoever is at the table, playing or watching
+                let seated = session.owner_of(&player.name).is_some();
+                if !admin && !seated && !self.watching(&name, outbox) {
+                    return Err(Error::NotPlaying(player.name.clone(), name));
+                }
+                let chat = Chat::new(player.name.clone(), &text);
+                session.chat.push(chat.clone());
+                self.store.save(&session)?;
+                self.sessions.insert(name.clone(), session);
+                self.tell(&name, &Message::Chat(name.clone(), chat), outbox);
+            }
+            Message::ListSessions => {
+                let names = Message::Sessions(self.store.list()?);
+                outbox.send(names);
+            }
+            Message::Ping(n) => {
+                outbox.send(Message::Ping(n));
+            }
+            // Only the relay sends these
+            Message::Snapshot(_)
+            | Message::StateDelta(_)
+            | Message::Sessions(_)
+            | Message::Error(_)
+            | Message::Tables(_)
+            | Message::Chat(_, _) => return Err(Error::InvalidFieldType),
+        }
+        Ok(())
+    }
+
+    // Everyone watching, and whoever it came from even if they aren't.
+    // Whoever can't take any more has left
+    fn tell(&mut self, name: &str, message: &Message, from: &Outbox) {
+        if !self.watching(name, from) {
+            from.send(message.clone());
+        }
+        if let Some(watchers) = self.watchers.get_mut(name) {
+            watchers.retain(|outbox| outbox.send(message.clone()));
+        }
+    }
+}
+
+pub fn serve(addr: SocketAddr) -> Result<()> {
+    let listener = TcpListener::bind(addr)?;
+    println!("relaying on {}", listener.local_addr()?);
+    run(listener, Relay::new(Box::new(Local), Players::load()?))
+}
+
+pub fn run(listener: TcpListener, relay: Relay) -> Result<()> {
+    let relay = Arc::new(Mutex::new(relay));
+    for stream in listener.incoming() {
+        let stream = stream?;
+        let relay = Arc::clone(&relay);
+        thread::spawn(move || {
+            if let Err(err) = connection(stream, &relay) {
+                log!("connection dropped: {err}");
+            }
+        });
+    }
+    Ok(())
+}
+
+// Says hello, then takes whoever it is at their word, if their token agrees
+fn sign_in(
+    stream: &mut TcpStream,
+    frames: &mut Frames,
+    outbox: &Outbox,
+    relay: &Mutex<Relay>,
+) -> Result<Player> {
+    let (name, token) = match receive(stream, frames)? {
+        Message::Hello(VERSION, name, token) => (name, token),
+        Message::Hello(version, _, _) => return Err(Error::Version(VERSION, version)),
+        _ => return Err(Error::Version(VERSION, 0)),
+    };
+    let mut relay = relay.lock().expect("the relay survives its connections");
+    let player = relay.players.sign_in(&Credentials { name, token })?;
+    outbox.send(Message::Hello(VERSION, player.name.clone(), String::new()));
+    Ok(player)
+}
+
+// Everything going back goes through the outbox, whoever it's from
+fn connection(mut stream: TcpStream, relay: &Mutex<Relay>) -> Result<()> {
+    let mut frames = Frames::default();
+    let outbox = Outbox::open(&stream)?;
+    let player = match sign_in(&mut stream, &mut frames, &outbox, relay) {
+        Ok(player) => player,
+        Err(err) => {
+            outbox.send(Message::Error(err.to_string()));
+            return Ok(());
+        }
+    };
+    loop {
+        let message = match receive(&mut stream, &mut frames) {
+            Err(Error::Io(err)) if err.kind() == ErrorKind::UnexpectedEof => return Ok(()),
+            Err(err @ (Error::Io(_) | Error::TooBig(_))) => return Err(err),
+            message => message,
+        };
+        let result = message.and_then(|message| {
+            let mut relay = relay.lock().expect("the relay survives its connections");
+            relay.handle(message, &outbox, &player)
+        });
+        // A bad action only bothers whoever sent it
+        if let Err(err) = result {
+            outbox.send(Message::Error(err.to_string()));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::{SocketAddr, TcpListener, TcpStream};
+    use std::thread;
+
+    use super::{Outbox, Relay};
+    use crate::actions::Action;
+    use crate::encounter::Encounter;
+    use crate::grid::{Grid, Pos};
+    use crate::protocol::{receive, send, Change, Frames, Message, StateDelta, VERSION};
+    use crate::session::Session;
+    use crate::store::{Memory, SessionStore};
+    use crate::Entity;
+
+    struct Client(TcpStream, Frames);
+
+    impl Client {
+        fn connect(addr: SocketAddr, name: &str) -> Self {
+            let mut client = Self(TcpStream::connect(addr).unwrap(), Frames::default());
+            let token = format!("{name}-token");
+            client.send(Message::Hello(VERSION, name.to_string(), token));
+            assert!(matches!(client.receive(), Message::Hello(VERSION, _, _)));
+            client
+        }
+
+        fn send(&mut self, message: Message) {
+            send(&mut self.0, &message).unwrap();
+        }
+
+        fn receive(&mut self) -> Message {
+            receive(&mut self.0, &mut self.1).unwrap()
+        }
+
+        fn join(&mut self, claim: Option<&str>) -> Message {
+            self.send(Message::JoinSession(
+                "test".to_string(),
+                claim.map(str::to_string),
+            ));
+            self.receive()
+        }
+
+        fn fight(&mut self, session: &Session, who: &str) -> Message {
+            let verb = session.verbs.get("fight").unwrap();
+            let entity = session.entity(who).unwrap();
+            let action = Action::perform(verb, entity, session.entity("Tommy").unwrap()).unwrap();
+            self.send(Message::SubmitAction("test".to_string(), action));
+            self.receive()
+        }
+    }
+
+    fn relay() -> (SocketAddr, Session) {
+        let gilgamesh = Entity::new("Gilgamesh".to_string());
+        let enkidu = Entity::new("Enkidu".to_string());
+        let mut tommy = Entity::new("Tommy".to_string());
+        tommy.set_max_health(50);
+        let party = vec![gilgamesh, enkidu];
+        let mut session = Session::encounter("test".to_string(), party, vec![tommy], 0);
+        session.claim("Gilgamesh", "alice", false).unwrap();
+        let mut store = Memory::default();
+        store.save(&session).unwrap();
+        let players = "ishtar ishtar-token admin".parse().unwrap();
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        thread::spawn(move || super::run(listener, Relay::new(Box::new(store), players)));
+        (addr, session)
+    }
+
+    fn delta(message: Message) -> StateDelta {
+        match message {
+            Message::StateDelta(delta) => delta,
+            message => panic!("expected what changed, got {message:?}"),
+        }
+    }
+
+    #[test]
+    fn actions_reach_everyone_watching() {
+        let (addr, session) = relay();
+        let mut alice = Client::connect(addr, "alice");
+        let mut bob = Client::connect(addr, "bob");
+        alice.join(None);
+        bob.join(None);
+
+        let first = delta(alice.fight(&session, "Gilgamesh"));
+        assert_eq!(first.from, session.seq());
+        assert_eq!(delta(bob.receive()), first);
+
+        // Chat goes to everyone too, playing or not
+        bob.send(Message::Say("test".to_string(), "/me cheers".to_string()));
+        let Message::Chat(_, chat) = alice.receive() else {
+            panic!("expected chat");
+        };
+        assert_eq!(chat.to_string(), "* bob cheers");
+        assert_eq!(bob.receive(), Message::Chat("test".to_string(), chat));
+
+        // But not from outside it
+        let mut eve = Client::connect(addr, "eve");
+        eve.send(Message::Say("test".to_string(), "hi".to_string()));
+        let Message::Error(error) = eve.receive() else {
+            panic!("expected eve to be turned away");
+        };
+        assert!(error.ends_with("join it first"), "{error}");
+
+        let nobody = Action::spawn("Nobody".to_string());
+        bob.send(Message::SubmitAction("elsewhere".to_string(), nobody));
+        assert!(matches!(bob.receive(), Message::Error(_)));
+    }
+
+    #[test]
+    fn only_owners_and_admins_act() {
+        let (addr, session) = relay();
+        let mut alice = Client::connect(addr, "alice");
+        let mut bob = Client::connect(addr, "bob");
+        let mut ishtar = Client::connect(addr, "ishtar");
+        alice.join(None);
+
+        let refused = bob.fight(&session, "Gilgamesh");
+        assert_eq!(
+            refused,
+            Message::Error("Gilgamesh doesn't take orders from bob".to_string())
+        );
+        assert!(matches!(bob.join(Some("Gilgamesh")), Message::Error(_)));
+        assert!(matches!(bob.join(Some("Enkidu")), Message::Snapshot(_)));
+        assert!(matches!(alice.receive(), Message::Snapshot(_)));
+        let enkidu = delta(bob.fight(&session, "Enkidu"));
+        // Nothing bob tried before got in
+        assert_eq!(enkidu.from, session.seq());
+        assert_eq!(delta(alice.receive()), enkidu);
+
+        let overridden = delta(ishtar.fight(&session, "Gilgamesh"));
+        assert_eq!(delta(alice.receive()), overridden);
+
+        let mut impostor = Client(TcpStream::connect(addr).unwrap(), Frames::default());
+        let hello = Message::Hello(VERSION, "alice".to_string(), "guess".to_string());
+        impostor.send(hello);
+        assert!(matches!(impostor.receive(), Message::Error(_)));
+    }
+
+    #[test]
+    fn changes_are_checked_like_actions() {
+        let (addr, session) = relay();
+        let mut alice = Client::connect(addr, "alice");
+        let mut bob = Client::connect(addr, "bob");
+        let change = |change| Message::Change("test".to_string(), change);
+
+        // Only admins get to hand over a whole session
+        bob.send(Message::SaveSession(session.clone()));
+        assert!(matches!(bob.receive(), Message::Error(_)));
+        bob.send(change(Change::Grid(Grid::new(3, 3, vec![]).unwrap())));
+        assert!(matches!(bob.receive(), Message::Error(_)));
+        alice.send(change(Change::Grid(Grid::new(3, 3, vec![]).unwrap())));
+        let grid = delta(alice.receive()).grid.unwrap();
+        assert_eq!(grid.position("Gilgamesh"), Some(Pos::new(0, 0)));
+        let to = Pos::new(1, 0);
+
+        bob.send(change(Change::Move("Gilgamesh".to_string(), to)));
+        let refused = "Gilgamesh doesn't take orders from bob";
+        assert_eq!(bob.receive(), Message::Error(refused.to_string()));
+        alice.send(change(Change::Move("Gilgamesh".to_string(), to)));
+        let moved = delta(alice.receive());
+        assert_eq!(moved.grid.unwrap().position("Gilgamesh"), Some(to));
+        let moves = moved.actions.last().unwrap().to_string();
+        assert_eq!(moves, "Gilgamesh moves to 1,0");
+    }
+
+    #[test]
+    fn hosted_sessions_wait_for_their_seats() {
+        let (addr, session) = relay();
+        let mut alice = Client::connect(addr, "alice");
+        let mut bob = Client::connect(addr, "bob");
+        alice.send(Message::Host("test".to_string(), 2));
+        assert!(matches!(alice.receive(), Message::Snapshot(_)));
+        bob.send(Message::Start("test".to_string()));
+        assert!(matches!(bob.receive(), Message::Error(_)));
+        let waiting = bob.fight(&session, "Enkidu");
+        let expected = "test hasn't started, 0 of 2 players are here";
+        assert_eq!(waiting, Message::Error(expected.to_string()));
+
+        alice.join(Some("Gilgamesh"));
+        bob.send(Message::Lobby);
+        let Message::Tables(tables) = bob.receive() else {
+            panic!("expected the lobby");
+        };
+        assert_eq!((tables[0].players, tables[0].waiting), (1, true));
+
+        // The last seat starts it, for everyone watching
+        let Message::Snapshot(started) = bob.join(Some("Enkidu")) else {
+            panic!("expected a seat");
+        };
+        assert!(!started.seats.unwrap().waiting);
+        assert_eq!(started.encounter, Encounter::InProgress);
+        assert!(matches!(alice.receive(), Message::Snapshot(_)));
+        let mut carol = Client::connect(addr, "carol");
+        assert!(matches!(carol.join(Some("Enkidu")), Message::Error(_)));
+        bob.send(Message::Host("test".to_string(), 2));
+        let expected = "bob isn't hosting test, ask whoever is";
+        assert_eq!(bob.receive(), Message::Error(expected.to_string()));
+        delta(bob.fight(&session, "Enkidu"));
+    }
+
+    #[test]
+    fn resyncs_get_what_was_missed_or_everything() {
+        let (addr, mut session) = relay();
+        let verb = session.verbs.get("neutral").unwrap().clone();
+        for _ in 0..=super::RESYNC_LIMIT {
+            let gilgamesh = session.entity("Gilgamesh").unwrap();
+            session
+                .apply(Action::perform(&verb, gilgamesh, gilgamesh).unwrap())
+                .unwrap();
+        }
+        let mut ishtar = Client::connect(addr, "ishtar");
+        ishtar.send(Message::SaveSession(session.clone()));
+        assert!(matches!(ishtar.receive(), Message::Snapshot(_)));
+
+        ishtar.send(Message::Say("test".to_string(), "/me waves".to_string()));
+        assert!(matches!(ishtar.receive(), Message::Chat(_, _)));
+
+        let mut bob = Client::connect(addr, "bob");
+        bob.send(Message::Resync("test".to_string(), session.seq() - 2, 0));
+        assert_eq!(
+            delta(bob.receive()).actions,
+            session.actions()[session.actions().len() - 2..]
+        );
+        // Nothing new in the log, but there's still the chat and the seats
+        bob.send(Message::Resync("test".to_string(), session.seq(), 0));
+        let missed = delta(bob.receive());
+        assert!(missed.actions.is_empty());
+        assert_eq!(missed.chat[0].to_string(), "* ishtar waves");
+        assert_eq!(missed.owners, session.owners);
+        for (seq, chat) in [(0, 0), (session.seq() + 1, 0), (session.seq(), 2)] {
+            bob.send(Message::Resync("test".to_string(), seq, chat));
+            assert!(matches!(bob.receive(), Message::Snapshot(_)));
+        }
+    }
+
+    #[test]
+    fn other_versions_are_turned_away() {
+        let (addr, _) = relay();
+        let mut client = Client(TcpStream::connect(addr).unwrap(), Frames::default());
+        client.send(Message::Hello(
+            VERSION + 1,
+            "alice".to_string(),
+            String::new(),
+        ));
+        assert!(matches!(client.receive(), Message::Error(_)));
+    }
+
+    #[test]
+    fn a_client_that_stops_reading_is_hung_up_on() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let _client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (stream, _) = listener.accept().unwrap();
+        let outbox = Outbox::open(&stream).unwrap();
+
+        // Never waits, however much piles up, and gives up eventually
+        let news = Message::Sessions(vec!["x".repeat(1 << 16)]);
+        let sent = (0..10_000)
+            .take_while(|_| outbox.send(news.clone()))
+            .count();
+        assert!(sent < 10_000);
+        assert!(!outbox.send(Message::Ping(0)));
+    }
+}
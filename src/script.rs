@@ -0,0 +1,670 @@
+// A tiny scripting language for action effects that `effect` can't express.
+// It can only see what a `Host` hands it, has no loops it can't be stopped
+// in, every script is cut off after `MAX_STEPS`, and nothing nests deeper
+// than `MAX_DEPTH`:
+//
+//     # electrocute chains to a friend of the target if it's burning
+//     if get(target, "burn") > 0 {
+//         let next = ally_of(target)
+//         if next != "" { act("electrocute", actor, next) }
+//     }
+//
+// Values are integers or strings, `0` and `""` are false. Statements end at
+// a newline or `;`. Functions:
+//
+//     roll(dice)           | Roll something like "2d6+1" with the session dice
+//     get(name, field)     | health, max_health, attack, defense, speed, level,
+//                          | xp, mana, max_mana, stamina, max_stamina, alive,
+//                          | or turns left of stun, burn, charm, shield
+//     set(name, field, n)  | Any of the stats, health, mana and stamina stay
+//                          | within their maximum
+//     enemy_of(name)       | Someone alive on the other side, "" if no one is
+//     ally_of(name)        | Someone else alive on the same side, "" if no one is
+//     act(verb, from, to)  | Do another action right after this one
+//     min(a, b), max(a, b)
+
+use std::fs;
+
+use crate::error::{Error, Result};
+
+pub const DIRNAME: &str = "scripts";
+pub const MAX_STEPS: usize = 10_000;
+// Brackets, blocks and signs inside each other, well before the stack gives out
+pub const MAX_DEPTH: usize = 64;
+
+pub trait Host {
+    fn get(&self, name: &str, field: &str) -> Option<i64>;
+    fn set(&mut self, name: &str, field: &str, value: i64) -> Option<()>;
+    fn roll(&mut self, dice: &str) -> Result<i64>;
+    fn pick(&mut self, name: &str, enemy: bool) -> Option<String>;
+    fn act(&mut self, verb: &str, from: &str, to: &str);
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Value {
+    Int(i64),
+    Str(String),
+}
+
+impl Value {
+    fn truthy(&self) -> bool {
+        match self {
+            Value::Int(n) => *n != 0,
+            Value::Str(s) => !s.is_empty(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token {
+    Int(i64),
+    Str(String),
+    Ident(String),
+    Sym(&'static str),
+    End,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Expr {
+    Value(Value),
+    Var(String),
+    Call(String, Vec<Expr>),
+    Not(Box<Expr>),
+    Neg(Box<Expr>),
+    Binary(&'static str, Box<Expr>, Box<Expr>),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Stmt {
+    Let(String, Expr),
+    Assign(String, Expr),
+    If(Expr, Vec<Line>, Vec<Line>),
+    While(Expr, Vec<Line>),
+    Expr(Expr),
+}
+
+// A statement and the line it's on, for the errors
+type Line = (usize, Stmt);
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Script {
+    name: String,
+    body: Vec<Line>,
+}
+
+const SYMBOLS: [&str; 21] = [
+    "==", "!=", "<=", ">=", "&&", "||", "+", "-", "*", "/", "%", "<", ">", "!", "=", "(", ")", "{",
+    "}", ",", ";",
+];
+
+fn lex(name: &str, source: &str) -> Result<Vec<(usize, Token)>> {
+    let mut tokens = vec![];
+    for (i, line) in source.lines().enumerate() {
+        let n = i + 1;
+        let error = |message: &str| Error::Script(format!("{name} line {n}: {message}"));
+        let mut rest = line.trim_start();
+        while let Some(c) = rest.chars().next() {
+            if c == '#' {
+                break;
+            }
+            let len = if c.is_ascii_digit() {
+                let len = rest
+                    .find(|c: char| !c.is_ascii_digit())
+                    .unwrap_or(rest.len());
+                let value = rest[..len].parse().map_err(|_| error("number too big"))?;
+                tokens.push((n, Token::Int(value)));
+                len
+            } else if c.is_alphabetic() || c == '_' {
+                let len = rest
+                    .find(|c: char| !c.is_alphanumeric() && c != '_')
+                    .unwrap_or(rest.len());
+                tokens.push((n, Token::Ident(rest[..len].to_string())));
+                len
+            } else if c == '"' {
+                let end = rest[1..]
+                    .find('"')
+                    .ok_or_else(|| error("unfinished string"))?;
+                tokens.push((n, Token::Str(rest[1..end + 1].to_string())));
+                end + 2
+            } else {
+                let symbol = SYMBOLS
+                    .into_iter()
+                    .find(|s| rest.starts_with(s))
+                    .ok_or_else(|| error(&format!("what is {c:?}")))?;
+                tokens.push((n, Token::Sym(symbol)));
+                symbol.len()
+            };
+            rest = rest[len..].trim_start();
+        }
+        tokens.push((n, Token::Sym(";")));
+    }
+    tokens.push((source.lines().count(), Token::End));
+    Ok(tokens)
+}
+
+struct Parser<'a> {
+    name: &'a str,
+    tokens: Vec<(usize, Token)>,
+    at: usize,
+    depth: usize,
+}
+
+impl Parser<'_> {
+    fn peek(&self) -> &Token {
+        &self.tokens[self.at].1
+    }
+
+    fn line(&self) -> usize {
+        self.tokens[self.at].0
+    }
+
+    fn next(&mut self) -> Token {
+        let token = self.tokens[self.at].1.clone();
+        if token != Token::End {
+            self.at += 1;
+        }
+        token
+    }
+
+    fn error(&self, message: &str) -> Error {
+        Error::Script(format!("{} line {}: {message}", self.name, self.line()))
+    }
+
+    fn eat(&mut self, symbol: &str) -> bool {
+        match self.peek() {
+            Token::Sym(s) if *s == symbol => {
+                self.at += 1;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn expect(&mut self, symbol: &str) -> Result<()> {
+        match self.eat(symbol) {
+            true => Ok(()),
+            false => Err(self.error(&format!("expected {symbol:?}"))),
+        }
+    }
+
+    fn skip_separators(&mut self) {
+        while self.eat(";") {}
+    }
+
+    fn ident(&mut self) -> Result<String> {
+        match self.next() {
+            Token::Ident(name) => Ok(name),
+            _ => Err(self.error("expected a name")),
+        }
+    }
+
+    // Anything that parses inside itself goes through here
+    fn nested<T>(&mut self, parse: impl FnOnce(&mut Self) -> Result<T>) -> Result<T> {
+        if self.depth >= MAX_DEPTH {
+            return Err(self.error("nested too deeply"));
+        }
+        self.depth += 1;
+        let parsed = parse(self);
+        self.depth -= 1;
+        parsed
+    }
+
+    fn block(&mut self) -> Result<Vec<Line>> {
+        self.nested(Self::block_body)
+    }
+
+    fn block_body(&mut self) -> Result<Vec<Line>> {
+        self.expect("{")?;
+        let mut body = vec![];
+        loop {
+            self.skip_separators();
+            if self.eat("}") {
+                return Ok(body);
+            }
+            if *self.peek() == Token::End {
+                return Err(self.error("missing }"));
+            }
+            body.push(self.statement()?);
+        }
+    }
+
+    fn statement(&mut self) -> Result<Line> {
+        let line = self.line();
+        let stmt = match self.peek().clone() {
+            Token::Ident(word) if word == "let" => {
+                self.next();
+                let name = self.ident()?;
+                self.expect("=")?;
+                Stmt::Let(name, self.expr()?)
+            }
+            Token::Ident(word) if word == "if" => {
+                self.next();
+                let condition = self.expr()?;
+                let then = self.block()?;
+                let otherwise = match self.peek() {
+                    Token::Ident(word) if word == "else" => {
+                        self.next();
+                        match self.peek() {
+                            Token::Ident(word) if word == "if" => vec![self.statement()?],
+                            _ => self.block()?,
+                        }
+                    }
+                    _ => vec![],
+                };
+                Stmt::If(condition, then, otherwise)
+            }
+            Token::Ident(word) if word == "while" => {
+                self.next();
+                let condition = self.expr()?;
+                Stmt::While(condition, self.block()?)
+            }
+            Token::Ident(name) if self.tokens[self.at + 1].1 == Token::Sym("=") => {
+                self.at += 2;
+                Stmt::Assign(name, self.expr()?)
+            }
+            _ => Stmt::Expr(self.expr()?),
+        };
+        match self.peek() {
+            Token::Sym(";" | "}") | Token::End => Ok((line, stmt)),
+            _ => Err(self.error("expected the end of the line")),
+        }
+    }
+
+    fn expr(&mut self) -> Result<Expr> {
+        self.binary(0)
+    }
+
+    // Loosest first
+    fn binary(&mut self, level: usize) -> Result<Expr> {
+        const LEVELS: [&[&str]; 5] = [
+            &["||"],
+            &["&&"],
+            &["==", "!="],
+            &["<", "<=", ">", ">="],
+            &["+", "-"],
+        ];
+        const FACTORS: &[&str] = &["*", "/", "%"];
+
+        let operators = LEVELS.get(level).copied().unwrap_or(FACTORS);
+        let next = |parser: &mut Self| match level < LEVELS.len() {
+            true => parser.binary(level + 1),
+            false => parser.unary(),
+        };
+        let mut left = next(self)?;
+        while let Token::Sym(symbol) = *self.peek() {
+            if !operators.contains(&symbol) {
+                break;
+            }
+            self.next();
+            left = Expr::Binary(symbol, Box::new(left), Box::new(next(self)?));
+        }
+        Ok(left)
+    }
+
+    fn unary(&mut self) -> Result<Expr> {
+        self.nested(Self::operand)
+    }
+
+    fn operand(&mut self) -> Result<Expr> {
+        if self.eat("!") {
+            return Ok(Expr::Not(Box::new(self.unary()?)));
+        }
+        if self.eat("-") {
+            return Ok(Expr::Neg(Box::new(self.unary()?)));
+        }
+        match self.next() {
+            Token::Int(n) => Ok(Expr::Value(Value::Int(n))),
+            Token::Str(s) => Ok(Expr::Value(Value::Str(s))),
+            Token::Sym("(") => {
+                let expr = self.expr()?;
+                self.expect(")")?;
+                Ok(expr)
+            }
+            Token::Ident(name) if self.eat("(") => {
+                let mut args = vec![];
+                if !self.eat(")") {
+                    loop {
+                        args.push(self.expr()?);
+                        if self.eat(")") {
+                            break;
+                        }
+                        self.expect(",")?;
+                    }
+                }
+                Ok(Expr::Call(name, args))
+            }
+            Token::Ident(name) => Ok(Expr::Var(name)),
+            _ => Err(self.error("expected a value")),
+        }
+    }
+}
+
+impl Script {
+    pub fn parse(name: &str, source: &str) -> Result<Self> {
+        let mut parser = Parser {
+            name,
+            tokens: lex(name, source)?,
+            at: 0,
+            depth: 0,
+        };
+        let mut body = vec![];
+        loop {
+            parser.skip_separators();
+            if *parser.peek() == Token::End {
+                break;
+            }
+            body.push(parser.statement()?);
+        }
+        Ok(Self {
+            name: name.to_string(),
+            body,
+        })
+    }
+
+    // From `scripts/`, the name being the file name in there
+    pub fn load(name: &str) -> Result<Self> {
+        let source = match fs::read_to_string(format!("{DIRNAME}/{name}")) {
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                return Err(Error::Script(format!("there is no {DIRNAME}/{name}")))
+            }
+            source => source?,
+        };
+        Self::parse(name, &source)
+    }
+
+    pub fn run(&self, host: &mut dyn Host, vars: Vec<(&str, Value)>) -> Result<()> {
+        let mut run = Run {
+            name: &self.name,
+            host,
+            vars: vars.into_iter().map(|(k, v)| (k.to_string(), v)).collect(),
+            steps: 0,
+            line: 0,
+        };
+        run.block(&self.body)
+    }
+}
+
+struct Run<'a> {
+    name: &'a str,
+    host: &'a mut dyn Host,
+    vars: Vec<(String, Value)>,
+    steps: usize,
+    line: usize,
+}
+
+impl Run<'_> {
+    fn error(&self, message: &str) -> Error {
+        Error::Script(format!("{} line {}: {message}", self.name, self.line))
+    }
+
+    fn step(&mut self) -> Result<()> {
+        self.steps += 1;
+        match self.steps > MAX_STEPS {
+            true => Err(self.error(&format!("gave up after {MAX_STEPS} steps"))),
+            false => Ok(()),
+        }
+    }
+
+    fn block(&mut self, body: &[Line]) -> Result<()> {
+        // Variables from inside a block go away at its end
+        let scope = self.vars.len();
+        for (line, stmt) in body {
+            self.line = *line;
+            self.statement(stmt)?;
+        }
+        self.vars.truncate(scope);
+        Ok(())
+    }
+
+    fn statement(&mut self, stmt: &Stmt) -> Result<()> {
+        self.step()?;
+        match stmt {
+            Stmt::Let(name, expr) => {
+                let value = self.eval(expr)?;
+                self.vars.push((name.clone(), value));
+            }
+            Stmt::Assign(name, expr) => {
+                let value = self.eval(expr)?;
+                let var = self.vars.iter_mut().rev().find(|(n, _)| n == name);
+                match var {
+                    Some((_, var)) => *var = value,
+                    None => return Err(self.error(&format!("{name} needs a let first"))),
+                }
+            }
+            Stmt::If(condition, then, otherwise) => match self.eval(condition)?.truthy() {
+                true => self.block(then)?,
+                false => self.block(otherwise)?,
+            },
+            Stmt::While(condition, body) => {
+                while self.eval(condition)?.truthy() {
+                    self.block(body)?;
+                }
+            }
+            Stmt::Expr(expr) => {
+                self.eval(expr)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn int(&self, value: Value) -> Result<i64> {
+        match value {
+            Value::Int(n) => Ok(n),
+            Value::Str(s) => Err(self.error(&format!("{s:?} is not a number"))),
+        }
+    }
+
+    fn str(&self, value: Value) -> Result<String> {
+        match value {
+            Value::Str(s) => Ok(s),
+            Value::Int(n) => Err(self.error(&format!("{n} is not a string"))),
+        }
+    }
+
+    fn eval(&mut self, expr: &Expr) -> Result<Value> {
+        self.step()?;
+        let value = match expr {
+            Expr::Value(value) => value.clone(),
+            Expr::Var(name) => match self.vars.iter().rev().find(|(n, _)| n == name) {
+                Some((_, value)) => value.clone(),
+                None => return Err(self.error(&format!("no one knows what {name} is"))),
+            },
+            Expr::Not(expr) => Value::Int(!self.eval(expr)?.truthy() as i64),
+            Expr::Neg(expr) => {
+                let n = self.eval(expr)?;
+                Value::Int(self.int(n)?.wrapping_neg())
+            }
+            Expr::Binary(op, left, right) => {
+                let left = self.eval(left)?;
+                // Don't look at the right unless it matters
+                match (*op, left.truthy()) {
+                    ("&&", false) => return Ok(Value::Int(0)),
+                    ("||", true) => return Ok(Value::Int(1)),
+                    _ => {}
+                }
+                let right = self.eval(right)?;
+                self.binary(op, left, right)?
+            }
+            Expr::Call(name, args) => {
+                let args = args
+                    .iter()
+                    .map(|arg| self.eval(arg))
+                    .collect::<Result<Vec<_>>>()?;
+                self.call(name, args)?
+            }
+        };
+        Ok(value)
+    }
+
+    fn binary(&self, op: &str, left: Value, right: Value) -> Result<Value> {
+        let value = match (op, left, right) {
+            ("==", left, right) => Value::Int((left == right) as i64),
+            ("!=", left, right) => Value::Int((left != right) as i64),
+            ("&&" | "||", _, right) => Value::Int(right.truthy() as i64),
+            ("+", Value::Str(left), right) => Value::Str(match right {
+                Value::Str(right) => left + &right,
+                Value::Int(right) => format!("{left}{right}"),
+            }),
+            (op, left, right) => {
+                let (left, right) = (self.int(left)?, self.int(right)?);
+                Value::Int(match op {
+                    "+" => left.wrapping_add(right),
+                    "-" => left.wrapping_sub(right),
+                    "*" => left.wrapping_mul(right),
+                    "/" | "%" if right == 0 => return Err(self.error("divided by zero")),
+                    "/" => left.wrapping_div(right),
+                    "%" => left.wrapping_rem(right),
+                    "<" => (left < right) as i64,
+                    "<=" => (left <= right) as i64,
+                    ">" => (left > right) as i64,
+                    _ => (left >= right) as i64,
+                })
+            }
+        };
+        Ok(value)
+    }
+
+    fn call(&mut self, name: &str, args: Vec<Value>) -> Result<Value> {
+        let arity = match name {
+            "roll" | "enemy_of" | "ally_of" => 1,
+            "get" | "min" | "max" => 2,
+            "set" | "act" => 3,
+            _ => return Err(self.error(&format!("there is no function called {name}"))),
+        };
+        if args.len() != arity {
+            return Err(self.error(&format!("{name} takes {arity} arguments")));
+        }
+        let mut args = args.into_iter();
+        let mut arg = || args.next().expect("arity was checked");
+        let value = match name {
+            "roll" => {
+                let dice = self.str(arg())?;
+                Value::Int(self.host.roll(&dice)?)
+            }
+            "get" => {
+                let (who, field) = (self.str(arg())?, self.str(arg())?);
+                match self.host.get(&who, &field) {
+                    Some(value) => Value::Int(value),
+                    None => return Err(self.error(&format!("can't get {field} of {who:?}"))),
+                }
+            }
+            "set" => {
+                let (who, field) = (self.str(arg())?, self.str(arg())?);
+                let value = self.int(arg())?;
+                if self.host.set(&who, &field, value).is_none() {
+                    return Err(self.error(&format!("can't set {field} of {who:?}")));
+                }
+                Value::Int(value)
+            }
+            "enemy_of" | "ally_of" => {
+                let who = self.str(arg())?;
+                let picked = self.host.pick(&who, name == "enemy_of");
+                Value::Str(picked.unwrap_or_default())
+            }
+            "act" => {
+                let (verb, from, to) = (self.str(arg())?, self.str(arg())?, self.str(arg())?);
+                self.host.act(&verb, &from, &to);
+                Value::Int(0)
+            }
+            "min" | "max" => {
+                let (a, b) = (self.int(arg())?, self.int(arg())?);
+                Value::Int(if name == "min" { a.min(b) } else { a.max(b) })
+            }
+            _ => unreachable!("arity knows every function"),
+        };
+        Ok(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Host, Script, Value, MAX_DEPTH};
+    use crate::error::Result;
+
+    #[derive(Default)]
+    struct Dummy {
+        health: i64,
+        acted: Vec<String>,
+    }
+
+    impl Host for Dummy {
+        fn get(&self, name: &str, field: &str) -> Option<i64> {
+            (name == "tommy" && field == "health").then_some(self.health)
+        }
+
+        fn set(&mut self, name: &str, field: &str, value: i64) -> Option<()> {
+            (name == "tommy" && field == "health").then(|| self.health = value)
+        }
+
+        fn roll(&mut self, _: &str) -> Result<i64> {
+            Ok(4)
+        }
+
+        fn pick(&mut self, _: &str, _: bool) -> Option<String> {
+            Some("enkidu".to_string())
+        }
+
+        fn act(&mut self, verb: &str, from: &str, to: &str) {
+            self.acted.push(format!("{from} {verb} {to}"));
+        }
+    }
+
+    fn run(source: &str, host: &mut Dummy) -> Result<()> {
+        let vars = vec![("target", Value::Str("tommy".to_string()))];
+        Script::parse("test", source)?.run(host, vars)
+    }
+
+    #[test]
+    fn arithmetic_and_calls() {
+        let mut host = Dummy {
+            health: 10,
+            ..Default::default()
+        };
+        let source = "
+            let hurt = roll(\"1d6\") * 2 - (1 + 1) # 6
+            if get(target, \"health\") > hurt && 1 {
+                set(target, \"health\", get(target, \"health\") - hurt)
+            } else if 1 { set(target, \"health\", 0) } else { hurt = 0 }
+            let i = 0; while i < 3 { i = i + 1; act(\"zap\", \"me\" + i, ally_of(target)) }
+        ";
+        run(source, &mut host).unwrap();
+        assert_eq!(host.health, 4);
+        assert_eq!(
+            host.acted,
+            ["me1 zap enkidu", "me2 zap enkidu", "me3 zap enkidu"]
+        );
+    }
+
+    #[test]
+    fn errors_have_line_numbers() {
+        let mut host = Dummy::default();
+        let mut error = |source| run(source, &mut host).unwrap_err().to_string();
+        assert!(error("\n\nlet x = 1 +").contains("test line 3"));
+        assert!(error("nope(1)").contains("no function called nope"));
+        assert!(error("x = 1").contains("needs a let"));
+        assert!(error("get(target, \"mana\")").contains("can't get"));
+        assert!(error("let x = 1 / 0").contains("divided by zero"));
+        assert!(error("if 1 { let x = 1 }\nx").contains("line 2"));
+    }
+
+    #[test]
+    fn runaway_scripts_are_stopped() {
+        let mut host = Dummy::default();
+        let error = run("while 1 { }", &mut host).unwrap_err();
+        assert!(error.to_string().contains("steps"));
+
+        let deep = |open: &str, close: &str| {
+            let source = format!("{}1{}", open.repeat(100_000), close.repeat(100_000));
+            Script::parse("test", &source).unwrap_err().to_string()
+        };
+        assert!(deep("(", ")").contains("nested too deeply"));
+        assert!(deep("- ", "").contains("nested too deeply"));
+        assert!(deep("if 1 { ", " }").contains("nested too deeply"));
+        let fine = format!(
+            "{}1{}",
+            "(".repeat(MAX_DEPTH - 1),
+            ")".repeat(MAX_DEPTH - 1)
+        );
+        assert!(Script::parse("test", &fine).is_ok());
+    }
+}
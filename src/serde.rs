@@ -1,8 +1,14 @@
 use crate::actions::{Action, ActionKind};
+use crate::campaign::Campaign;
+use crate::effects::Effect;
 use crate::error::{Error, Result};
+use crate::grid::{Grid, Pos};
+use crate::items::Item;
+use crate::protocol::Message;
+use crate::relations::Relation;
 use crate::session::Session;
 use crate::strings::Boolean;
-use crate::Entity;
+use crate::{Entity, Stats};
 
 pub fn serialize(value: &impl Serialize) -> Vec<u8> {
     let mut serializer = Serializer::default();
@@ -16,23 +22,58 @@ pub struct Serializer(Vec<u8>);
 impl Serializer {
     pub fn unknown_size(&mut self, field_type: FieldType) -> impl Fn(&mut Self, usize) -> usize {
         self.0.push(field_type as u8);
-        dbg!(&field_type);
-        dbg!(&self.0);
         let idx = self.0.len();
         self.0.extend([0, 0]);
-        dbg!(&self.0);
         move |this, size| {
-            let len = size as u16;
-            this.0[idx] = len.to_be_bytes()[0];
-            this.0[idx + 1] = len.to_be_bytes()[1];
-            dbg!(&this.0);
-            size + 3
+            if let Ok(len) = u16::try_from(size) {
+                this.0[idx] = len.to_be_bytes()[0];
+                this.0[idx + 1] = len.to_be_bytes()[1];
+                return size + 3;
+            }
+            // Too big for two bytes, so the length takes four
+            this.0[idx - 1] |= LONG;
+            this.0.splice(idx..idx + 2, long_len(size));
+            size + 5
         }
     }
     pub fn known_size(&mut self, field_type: FieldType, size: usize) -> usize {
-        self.0.push(field_type as u8);
-        self.0.extend((size as u16).to_be_bytes());
-        3
+        match u16::try_from(size) {
+            Ok(len) => {
+                self.0.push(field_type as u8);
+                self.0.extend(len.to_be_bytes());
+                3
+            }
+            Err(_) => {
+                self.0.push(field_type as u8 | LONG);
+                self.0.extend(long_len(size));
+                5
+            }
+        }
+    }
+}
+
+// Set on the type of a field whose length takes four bytes instead of two.
+// Only fields over 64 KiB have it, so files from before it read the same
+pub const LONG: u8 = 0x80;
+
+fn long_len(size: usize) -> [u8; 4] {
+    u32::try_from(size)
+        .expect("a field is never 4 GiB")
+        .to_be_bytes()
+}
+
+// The length of the field at the start of `bytes`, and of its type and
+// length before it, once there are enough bytes to tell
+pub fn field_len(bytes: &[u8]) -> Option<(usize, usize)> {
+    match bytes.first()? & LONG {
+        0 => {
+            let len = bytes.get(1..3)?.try_into().ok()?;
+            Some((3, u16::from_be_bytes(len) as usize))
+        }
+        _ => {
+            let len = bytes.get(1..5)?.try_into().ok()?;
+            Some((5, u32::from_be_bytes(len) as usize))
+        }
     }
 }
 
@@ -55,15 +96,20 @@ impl Serialize for Field {
     fn serialize(&self, buf: &mut Serializer) -> usize {
         match self {
             Field::Str(s) => {
-                buf.known_size(FieldType::Str, s.len());
+                let header = buf.known_size(FieldType::Str, s.len());
                 buf.0.extend_from_slice(s.as_bytes());
-                s.len() + 3
+                s.len() + header
             }
             Field::I128(b) => {
                 buf.known_size(FieldType::I128, 16);
                 buf.0.extend(b.to_be_bytes());
                 16 + 3
             }
+            Field::U64(n) => {
+                buf.known_size(FieldType::U64, 8);
+                buf.0.extend(n.to_be_bytes());
+                8 + 3
+            }
             Field::Byte(b) => {
                 buf.known_size(FieldType::Byte, 1);
                 buf.0.push(*b);
@@ -76,12 +122,24 @@ impl Serialize for Field {
             }
             Field::Action(action) => action.serialize(buf),
             Field::Entity(entity) => entity.serialize(buf),
+            Field::Effect(effect) => effect.serialize(buf),
+            Field::Stats(stats) => stats.serialize(buf),
+            Field::Relation(relation) => relation.serialize(buf),
             Field::Session(session) => session.serialize(buf),
+            Field::Campaign(campaign) => campaign.serialize(buf),
+            Field::Pos(pos) => pos.serialize(buf),
+            Field::Grid(grid) => grid.serialize(buf),
+            Field::Message(message) => message.serialize(buf),
             Field::ActionKind(action_kind) => {
                 buf.known_size(FieldType::ActionKind, 1);
                 buf.0.push(*action_kind as u8);
                 1 + 3
             }
+            Field::Item(item) => {
+                buf.known_size(FieldType::Item, 1);
+                buf.0.push(*item as u8);
+                1 + 3
+            }
             Field::Vec(values) => values.serialize(buf),
             Field::RealBoolean(_) => {
                 buf.known_size(FieldType::Bool, 1);
@@ -108,7 +166,6 @@ where
     {
         let mut vec = Vec::new();
         let bytes = &field_reader.buffer;
-        dbg!(&field_reader.buffer);
         let mut field_reader = FieldReader { buffer: bytes };
         while !field_reader.buffer.is_empty() {
             let v: Field = field_reader.read_field()?;
@@ -140,6 +197,34 @@ pub enum FieldType {
     Session = 8,
     Vec = 9,
     RealBoolean = 10,
+    U64 = 11,
+    Effect = 12,
+    Stats = 13,
+    Item = 14,
+    Relation = 15,
+    Campaign = 16,
+    Pos = 17,
+    Grid = 18,
+    // Messages between the relay and its clients, see protocol.rs
+    Hello = 32,
+    CreateSession = 33,
+    SaveSession = 34,
+    JoinSession = 35,
+    SubmitAction = 36,
+    ListSessions = 37,
+    Snapshot = 38,
+    StateDelta = 39,
+    Sessions = 40,
+    ErrorMessage = 41,
+    Ping = 42,
+    Host = 43,
+    Start = 44,
+    Lobby = 45,
+    Tables = 46,
+    Resync = 47,
+    Say = 48,
+    Chat = 49,
+    Change = 50,
 }
 
 #[derive(Debug, PartialEq, Clone)]
@@ -148,10 +233,19 @@ pub enum Field {
     Byte(u8),
     Bool(bool),
     I128(i128),
+    U64(u64),
     Action(Action),
     ActionKind(ActionKind),
     Entity(Entity),
+    Effect(Effect),
+    Stats(Stats),
+    Item(Item),
+    Relation(Relation),
     Session(Session),
+    Campaign(Campaign),
+    Pos(Pos),
+    Grid(Grid),
+    Message(Box<Message>),
     Vec(Vec<Field>),
     RealBoolean(Boolean),
 }
@@ -162,9 +256,9 @@ macro_rules! impl_try_from {
             type Error = Error;
 
             fn try_from(value: Field) -> Result<Self> {
-                match dbg!(value) {
+                match value {
                     $field_type(val) => Ok(val.into()),
-                    value => panic!("{value:?}!={}", stringify!($type)),
+                    _ => Err(Error::InvalidFieldType),
                 }
             }
         }
@@ -172,12 +266,21 @@ macro_rules! impl_try_from {
 }
 
 impl_try_from!(i128, Field::I128);
+impl_try_from!(u64, Field::U64);
 impl_try_from!(u8, Field::Byte);
 impl_try_from!(bool, Field::Bool);
 impl_try_from!(String, Field::Str);
 impl_try_from!(Action, Field::Action);
 impl_try_from!(ActionKind, Field::ActionKind);
 impl_try_from!(Entity, Field::Entity);
+impl_try_from!(Effect, Field::Effect);
+impl_try_from!(Stats, Field::Stats);
+impl_try_from!(Item, Field::Item);
+impl_try_from!(Relation, Field::Relation);
+impl_try_from!(Session, Field::Session);
+impl_try_from!(Campaign, Field::Campaign);
+impl_try_from!(Pos, Field::Pos);
+impl_try_from!(Grid, Field::Grid);
 
 impl<T: TryFrom<Field, Error = E>, E: Into<Error>> TryFrom<Field> for Vec<T> {
     type Error = Error;
@@ -203,13 +306,12 @@ impl<'a> FieldReader<'a> {
         Self { buffer }
     }
 
+    // Left in place, `len` steps over it along with the length
     fn field_type(&mut self) -> Result<FieldType> {
         if self.buffer.is_empty() {
             return Err(Error::MissingFieldType);
         }
-        let byte = self.buffer[0];
-        self.buffer = &self.buffer[1..];
-        dbg!(&self.buffer);
+        let byte = self.buffer[0] & !LONG;
 
         log!("Field Type: {:?}", byte);
         match byte {
@@ -223,18 +325,41 @@ impl<'a> FieldReader<'a> {
             8 => Ok(FieldType::Session),
             9 => Ok(FieldType::Vec),
             10 => Ok(FieldType::RealBoolean),
+            11 => Ok(FieldType::U64),
+            12 => Ok(FieldType::Effect),
+            13 => Ok(FieldType::Stats),
+            14 => Ok(FieldType::Item),
+            15 => Ok(FieldType::Relation),
+            16 => Ok(FieldType::Campaign),
+            17 => Ok(FieldType::Pos),
+            18 => Ok(FieldType::Grid),
+            32 => Ok(FieldType::Hello),
+            33 => Ok(FieldType::CreateSession),
+            34 => Ok(FieldType::SaveSession),
+            35 => Ok(FieldType::JoinSession),
+            36 => Ok(FieldType::SubmitAction),
+            37 => Ok(FieldType::ListSessions),
+            38 => Ok(FieldType::Snapshot),
+            39 => Ok(FieldType::StateDelta),
+            40 => Ok(FieldType::Sessions),
+            41 => Ok(FieldType::ErrorMessage),
+            42 => Ok(FieldType::Ping),
+            43 => Ok(FieldType::Host),
+            44 => Ok(FieldType::Start),
+            45 => Ok(FieldType::Lobby),
+            46 => Ok(FieldType::Tables),
+            47 => Ok(FieldType::Resync),
+            48 => Ok(FieldType::Say),
+            49 => Ok(FieldType::Chat),
+            50 => Ok(FieldType::Change),
             _ => Err(Error::InvalidFieldType),
         }
     }
 
     fn len(&mut self) -> Result<usize> {
-        if self.buffer.len() < 2 {
-            return Err(Error::MissingFieldLen);
-        }
-        let bytes = &self.buffer[..2];
-        self.buffer = &self.buffer[2..];
-        let len = u16::from_be_bytes([bytes[0], bytes[1]]);
-        Ok(len as usize)
+        let (header, len) = field_len(self.buffer).ok_or(Error::MissingFieldLen)?;
+        self.buffer = &self.buffer[header..];
+        Ok(len)
     }
 
     fn read_be_i128(input: &[u8]) -> i128 {
@@ -242,6 +367,23 @@ impl<'a> FieldReader<'a> {
         i128::from_be_bytes(int_bytes.try_into().unwrap())
     }
 
+    pub fn is_empty(&self) -> bool {
+        self.buffer.is_empty()
+    }
+
+    // Fields appended to a type after files were already written: older
+    // files simply end before them
+    pub fn read_field_or_default<T, E>(&mut self) -> Result<T>
+    where
+        T: TryFrom<Field, Error = E> + Default,
+        E: Into<Error>,
+    {
+        match self.is_empty() {
+            true => Ok(T::default()),
+            false => self.read_field(),
+        }
+    }
+
     pub fn read_field<T, E>(&mut self) -> Result<T>
     where
         T: TryFrom<Field, Error = E>,
@@ -249,19 +391,21 @@ impl<'a> FieldReader<'a> {
     {
         let field_type = self.field_type()?;
         let len = self.len()?;
-        dbg!(&len);
 
         log!("Field Type parsed: {field_type:?} with length: {len}");
+        // Whatever wrote it said there'd be more
+        if self.buffer.len() < len {
+            return Err(Error::MissingFieldLen);
+        }
         let bytes = &self.buffer[..len];
         self.buffer = &self.buffer[len..];
-        dbg!(&self.buffer);
 
         log!("Entity bytes: {bytes:?}");
         log!("Remaining buffer: {:?}", self.buffer);
         let field = match field_type {
             FieldType::Str => Field::Str(std::str::from_utf8(bytes)?.to_owned()),
-            FieldType::Bool => Field::Bool(bytes[0] == 1),
-            FieldType::Byte => Field::Byte(bytes[0]),
+            FieldType::Bool => Field::Bool(*bytes.first().ok_or(Error::MissingFieldLen)? == 1),
+            FieldType::Byte => Field::Byte(*bytes.first().ok_or(Error::MissingFieldLen)?),
             FieldType::Action => {
                 let mut new_reader = FieldReader::new(bytes);
                 Field::Action(Action::deserialize(&mut new_reader)?)
@@ -270,29 +414,62 @@ impl<'a> FieldReader<'a> {
                 let mut new_reader = FieldReader::new(bytes);
                 Field::Entity(Entity::deserialize(&mut new_reader)?)
             }
+            FieldType::Effect => {
+                let mut new_reader = FieldReader::new(bytes);
+                Field::Effect(Effect::deserialize(&mut new_reader)?)
+            }
+            FieldType::Stats => {
+                let mut new_reader = FieldReader::new(bytes);
+                Field::Stats(Stats::deserialize(&mut new_reader)?)
+            }
+            FieldType::Relation => {
+                let mut new_reader = FieldReader::new(bytes);
+                Field::Relation(Relation::deserialize(&mut new_reader)?)
+            }
             FieldType::Session => {
                 let mut new_reader = FieldReader::new(bytes);
                 Field::Session(Session::deserialize(&mut new_reader)?)
             }
+            FieldType::Campaign => {
+                let mut new_reader = FieldReader::new(bytes);
+                Field::Campaign(Campaign::deserialize(&mut new_reader)?)
+            }
+            FieldType::Pos => {
+                let mut new_reader = FieldReader::new(bytes);
+                Field::Pos(Pos::deserialize(&mut new_reader)?)
+            }
+            FieldType::Grid => {
+                let mut new_reader = FieldReader::new(bytes);
+                Field::Grid(Grid::deserialize(&mut new_reader)?)
+            }
+            FieldType::I128 if bytes.len() < 16 => return Err(Error::MissingFieldLen),
             FieldType::I128 => Field::I128(Self::read_be_i128(bytes)),
-            FieldType::ActionKind => Field::ActionKind(unsafe { std::mem::transmute(bytes[0]) }),
+            FieldType::U64 => Field::U64(u64::from_be_bytes(
+                bytes.try_into().map_err(|_| Error::MissingFieldLen)?,
+            )),
+            FieldType::ActionKind => match bytes.first() {
+                Some(&kind) if kind <= ActionKind::Move as u8 => {
+                    Field::ActionKind(unsafe { std::mem::transmute::<u8, ActionKind>(kind) })
+                }
+                _ => return Err(Error::InvalidActionType),
+            },
+            FieldType::Item => Field::Item(
+                bytes
+                    .first()
+                    .ok_or(Error::MissingFieldLen)?
+                    .to_owned()
+                    .try_into()?,
+            ),
             FieldType::Vec => {
                 let mut new_reader = FieldReader::new(bytes);
                 Field::Vec(Deserialize::deserialize(&mut new_reader)?)
             }
             FieldType::RealBoolean => Field::RealBoolean(Boolean::Maybe),
+            message_type => {
+                let mut new_reader = FieldReader::new(bytes);
+                Field::Message(Box::new(Message::read(message_type, &mut new_reader)?))
+            }
         };
         field.try_into().map_err(Into::into)
     }
-
-    pub fn ensure_type(&mut self, entity: FieldType) -> Result<usize> {
-        dbg!(&self.buffer);
-        let uifh891h02h01 = self.field_type()?;
-        if uifh891h02h01 == entity {
-            self.len()
-        } else {
-            panic!("{entity:?} {uifh891h02h01:?}");
-            Err(Error::InvalidFieldType)
-        }
-    }
 }
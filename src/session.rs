@@ -2,13 +2,29 @@ use std::fmt::{self, Display, Formatter};
 use std::fs::{self, File, OpenOptions};
 use std::io::{self, Read, Write};
 
-use crate::actions::Action;
+use crate::actions::{Action, ActionKind, Outcome, Scene};
+use crate::campaign::Campaign;
+use crate::chat::Chat;
+use crate::effects::{self, EffectKind};
+use crate::encounter::Encounter;
 use crate::error::{Error, Result};
+use crate::grid::{Grid, Pos};
+use crate::items::{self, Item};
+use crate::levels::Levels;
+use crate::lobby::{self, Seats};
+use crate::protocol::{Change, StateDelta};
+use crate::relations::{self, Relations};
 use crate::serde::{serialize, Deserialize, Field, FieldReader, FieldType, Serialize, Serializer};
-use crate::strings::{self, Boolean};
+use crate::strings::Rng;
+use crate::verbs::{Cooldown, Targeting, Verb, Verbs};
 use crate::Entity;
 
-const DIRNAME: &str = "sessions";
+pub const DIRNAME: &str = "sessions";
+pub const SUFFIX: &str = ".the_most_powerful.lol";
+// How many actions scripts may chain onto one before it's called a loop
+const MAX_CHAIN: usize = 8;
+// Mana and stamina regained at the end of each turn
+pub const RECOVERY: u8 = 1;
 
 #[derive(Debug, PartialEq, Default, Clone)]
 pub struct Session {
@@ -16,8 +32,27 @@ pub struct Session {
     pub party: Vec<Entity>,
     pub opponents: Vec<Entity>,
     actions: Vec<Action>,
+    pub seed: u64,
+    // Tilts every roll in the party's favour, or against it when negative
+    pub luck: i8,
+    // Read from `levels.txt` on load rather than stored with the session
+    pub levels: Levels,
+    pub relations: Relations,
+    // Read from `actions.txt` on load, like the levels
+    pub verbs: Verbs,
+    pub encounter: Encounter,
+    // Positions only matter if there is one
+    pub grid: Option<Grid>,
+    // Which player plays which entity, only a relay checks
+    pub owners: Vec<(String, String)>,
+    // Set once someone hosts it on a relay for others to join
+    pub seats: Option<Seats>,
+    // What the players said, apart from what happened
+    pub chat: Vec<Chat>,
 }
 
+const HEARTS: usize = 10;
+
 fn table_row<T>(
     f: &mut Formatter<'_>,
     col_width: usize,
@@ -25,126 +60,864 @@ fn table_row<T>(
     mut extr: impl FnMut(T) -> String,
 ) -> fmt::Result {
     for value in values {
-        let value = &extr(value)[..col_width];
+        let value = extr(value).chars().take(col_width).collect::<String>();
         write!(f, "{value:col_width$}  ")?;
     }
     writeln!(f)
 }
 
+fn entity_table(f: &mut Formatter<'_>, entities: &[Entity]) -> fmt::Result {
+    table_row(f, 15, entities, |o| {
+        format!("{} Lv{}", o.name, o.stats.level)
+    })?;
+    table_row(f, 15, entities, |o| {
+        // Ten hearts whatever the max health, a sliver of health is still a heart
+        let max = o.stats.max_health.max(1) as usize;
+        let full = (o.health as usize * HEARTS).div_ceil(max).min(HEARTS);
+        format!("{:♥<full$}{:♡<2$}", "", "", HEARTS - full)
+    })?;
+    table_row(f, 15, entities, |o| {
+        format!(
+            "mp {}/{} sp {}/{}",
+            o.mana, o.stats.max_mana, o.stamina, o.stats.max_stamina
+        )
+    })?;
+    table_row(f, 15, entities, |o| {
+        let effects = o.effects.iter().map(ToString::to_string);
+        effects.collect::<Vec<_>>().join(" ")
+    })?;
+    table_row(f, 15, entities, |o| {
+        let equipped = o.equipped.iter().map(|item| format!("[{item}]"));
+        let items = equipped.chain(o.inventory.iter().map(ToString::to_string));
+        items.collect::<Vec<_>>().join(" ")
+    })
+}
+
 impl Display for Session {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
-        writeln!(f, "Oponents:\n=========")?;
-        table_row(f, 15, &self.opponents, |o| o.name.to_string())?;
-        table_row(f, 15, &self.opponents, |o| {
-            format!("{:♥<1$}", "", o.health as usize)
-        })?;
+        writeln!(f, "Encounter: {}\n", self.encounter)?;
+        writeln!(f, "Opponents:\n==========")?;
+        entity_table(f, &self.opponents)?;
 
         writeln!(f, "\n\n")?;
 
         writeln!(f, "Party:\n=========")?;
-        table_row(f, 15, &self.opponents, |o| o.name.to_string())?;
-        table_row(f, 15, &self.opponents, |o| {
-            format!("{:♥<1$}", "", o.health as usize)
-        })?;
+        entity_table(f, &self.party)?;
+
+        if let Some(grid) = &self.grid {
+            writeln!(f, "\n{}", grid.render(&self.party, &self.opponents))?;
+        }
         Ok(())
     }
 }
 
 impl Session {
-    pub fn new(entity: Entity) -> Result<Self> {
+    pub const MAX_LUCK: i8 = 3;
+
+    pub fn new(entity: Entity, seed: u64) -> Result<Self> {
         let inst = Self {
             name: entity.name.to_string(),
             actions: vec![Action::spawn(entity.name.to_string())],
             party: vec![entity],
+            seed,
             ..Default::default()
         };
         Ok(inst)
     }
-}
 
-fn session_file(name: &str) -> Result<File, std::io::Error> {
-    fs::create_dir_all(DIRNAME)?;
-    let file = OpenOptions::new()
-        .create(true)
-        .read(true)
-        .write(true)
-        .open(format!("{DIRNAME}/{name}.the_most_powerful.lol"))?;
-    Ok(file)
-}
+    pub fn encounter(name: String, party: Vec<Entity>, opponents: Vec<Entity>, seed: u64) -> Self {
+        let actions = party
+            .iter()
+            .chain(&opponents)
+            .map(|entity| Action::spawn(entity.name.to_string()))
+            .zip(1..)
+            .map(|(action, seq)| Action { seq, ..action })
+            .collect();
+        Self {
+            name,
+            party,
+            opponents,
+            actions,
+            seed,
+            ..Default::default()
+        }
+    }
 
-fn decide_fate_based_on_weights_and_tilting_and_random_boolean_value_in_a_totally_impartial_Way_because_ofc_god_isnt_playing_favorites_and_things_are_good_right_WONRG_things_are_not_what_you_think_tjhey_are_run_run_you_fool(
-    luck: usize,
-) -> bool {
-    luck == 2112 || (!matches!(strings::rand(), Boolean::Luck(_)))
-}
+    pub fn name(&self) -> &str {
+        &self.name
+    }
 
-impl Session {
-    pub fn load(name: &str, fate_decidor_tilter_weight: usize) -> Result<Self> {
-        let imcool = fate_decidor_tilter_weight == 2112;
-        "Lol - How good are your booleans??";
-        "wat";
-        if !imcool {
-            if decide_fate_based_on_weights_and_tilting_and_random_boolean_value_in_a_totally_impartial_Way_because_ofc_god_isnt_playing_favorites_and_things_are_good_right_WONRG_things_are_not_what_you_think_tjhey_are_run_run_you_fool(fate_decidor_tilter_weight) {
-            return Err(Error::InvalidArgs("
+    pub fn actions(&self) -> &[Action] {
+        &self.actions
+    }
+
+    // Every roll comes from the seed and how far into the log we are, so
+    // replaying the log with the same seed gives the same fight
+    pub fn rng(&self) -> Rng {
+        Rng::stream(self.seed, self.actions.len() as u64)
+    }
+
+    // Only while everyone has just shown up, a new seed later on would
+    // change the rolls behind everything already in the log
+    pub fn reseed(&mut self, seed: u64) -> Result<()> {
+        if self.actions.iter().any(|a| a.kind != ActionKind::Spawn) {
+            return Err(Error::Seeded(self.name.clone()));
+        }
+        self.seed = seed;
+        Ok(())
+    }
+
+    pub fn entity(&self, name: &str) -> Option<&Entity> {
+        self.party
+            .iter()
+            .chain(&self.opponents)
+            .find(|e| e.name == name)
+    }
+
+    fn entity_mut(&mut self, name: &str) -> Option<&mut Entity> {
+        self.party
+            .iter_mut()
+            .chain(&mut self.opponents)
+            .find(|e| e.name == name)
+    }
+
+    pub fn owner(&self, entity: &str) -> Option<&str> {
+        let owner = self.owners.iter().find(|(e, _)| e == entity);
+        owner.map(|(_, player)| player.as_str())
+    }
+
+    // The first one the player plays, if they play anyone
+    pub fn owner_of(&self, player: &str) -> Option<&str> {
+        let owned = self.owners.iter().find(|(_, p)| p == player);
+        owned.map(|(entity, _)| entity.as_str())
+    }
+
+    // Everyone playing someone, each once
+    pub fn players(&self) -> Vec<&str> {
+        let mut players = self
+            .owners
+            .iter()
+            .map(|(_, p)| p.as_str())
+            .collect::<Vec<_>>();
+        players.sort();
+        players.dedup();
+        players
+    }
+
+    // Someone in the party no other player has yet, unless `force`d. A
+    // hosted session only has so many seats, and starts once they're taken
+    pub fn claim(&mut self, entity: &str, player: &str, force: bool) -> Result<()> {
+        if !self.party.iter().any(|e| e.name == entity) {
+            return Err(Error::NoEntity(entity.to_string()));
+        }
+        if let Some(seats) = &self.seats {
+            let players = self.players();
+            if !force && !players.contains(&player) && players.len() >= seats.slots as usize {
+                return Err(Error::Full(self.name.clone(), seats.slots));
+            }
+        }
+        match self.owner(entity) {
+            Some(owner) if owner != player && !force => {
+                return Err(Error::Taken(entity.to_string(), owner.to_string()));
+            }
+            _ => {
+                self.owners.retain(|(e, _)| e != entity);
+                self.owners.push((entity.to_string(), player.to_string()));
+            }
+        }
+        if let Some(seats) = &self.seats {
+            if seats.waiting && self.players().len() >= seats.slots as usize {
+                self.start(player);
+            }
+        }
+        Ok(())
+    }
+
+    // Opens the party up for `slots` players, one per party member if 0.
+    // Whoever played them before has to join again
+    pub fn host(&mut self, player: &str, slots: u8) -> Result<()> {
+        if self.encounter == Encounter::InProgress {
+            return Err(Error::Underway(self.name.clone()));
+        }
+        let slots = match slots {
+            0 => self.party.len() as u8,
+            slots if slots as usize > self.party.len() => return Err(Error::InvalidArgs("host ")),
+            slots => slots,
+        };
+        self.owners.clear();
+        self.seats = Some(Seats {
+            host: player.to_string(),
+            slots,
+            waiting: true,
+        });
+        Ok(())
+    }
+
+    // With everyone there or not, the fight is on if there's anyone to fight
+    pub fn start(&mut self, by: &str) {
+        if let Some(seats) = &mut self.seats {
+            seats.waiting = false;
+        }
+        if self.encounter == Encounter::Setup && self.opponents.iter().any(Entity::is_alive) {
+            self.transition(Encounter::InProgress, by);
+        }
+    }
+
+    pub fn in_party(&self, name: &str) -> bool {
+        self.party.iter().any(|e| e.name == name)
+    }
+
+    // What the action does, and who it may be aimed at
+    fn verb(&self, action: &Action) -> Result<Verb> {
+        match (action.kind, action.item) {
+            (ActionKind::Use, Some(item)) => item.verb(&self.verbs),
+            _ => Ok(self.verbs.get(&action.verb)?.clone()),
+        }
+    }
+
+    pub fn may_target(&self, targets: Targeting, from: &str, to: &str) -> bool {
+        let allies = self.in_party(from) == self.in_party(to);
+        match targets {
+            Targeting::Enemy => !allies,
+            Targeting::Ally => allies,
+            Targeting::Itself => from == to,
+            Targeting::Any => true,
+        }
+    }
+
+    // Nothing happens unless all of it does, whatever scripts chain onto it
+    pub fn apply(&mut self, action: Action) -> Result<Outcome> {
+        if self.encounter.is_over() {
+            return Err(Error::Encounter(self.encounter));
+        }
+        let before = self.clone();
+        let outcome = self.take_turn(action);
+        if outcome.is_err() {
+            *self = before;
+        }
+        outcome
+    }
+
+    fn take_turn(&mut self, action: Action) -> Result<Outcome> {
+        let mut rng = self.rng();
+        let entity = self
+            .entity(&action.entity)
+            .ok_or_else(|| Error::NoEntity(action.entity.clone()))?
+            .clone();
+        if !entity.is_alive() {
+            return Err(Error::EntityDead(action.entity.clone()));
+        }
+        let verb = self.verb(&action)?;
+        let stunned = effects::has(&entity.effects, EffectKind::Stun);
+        self.prepare(&action, &verb, stunned)?;
+
+        if self.encounter == Encounter::Setup && self.opponents.iter().any(Entity::is_alive) {
+            self.transition(Encounter::InProgress, &entity.name);
+        }
+
+        // A stunned entity still takes its turn, it just doesn't get to do anything
+        let mut outcome = match stunned {
+            true => {
+                self.log(Action {
+                    stunned: true,
+                    ..action
+                });
+                Outcome {
+                    stunned: true,
+                    ..Default::default()
+                }
+            }
+            false => self.resolve(action, &verb, &mut rng)?,
+        };
+
+        // Whatever scripts asked for happens straight away, in order, and
+        // without using up anyone's turn. It still has to be something the
+        // entity could do, and pay for, on its own
+        let mut queued = outcome.queued.clone();
+        let mut chained = 0;
+        while !queued.is_empty() {
+            let action = queued.remove(0);
+            chained += 1;
+            if chained > MAX_CHAIN {
+                return Err(Error::Script(format!(
+                    "more than {MAX_CHAIN} actions in a row, something is going in circles"
+                )));
+            }
+            let verb = self.verb(&action)?;
+            let standing = |name: &str| self.entity(name).is_some_and(Entity::is_alive);
+            if !standing(&action.entity) || !action.target.as_deref().is_some_and(standing) {
+                continue;
+            }
+            // Nor can anyone stunned be made to do anything
+            let actor = self.entity(&action.entity);
+            if actor.is_some_and(|e| effects::has(&e.effects, EffectKind::Stun)) {
+                continue;
+            }
+            self.prepare(&action, &verb, false)?;
+            let next = self.resolve(action, &verb, &mut rng)?;
+            outcome.levelled_up.extend(next.levelled_up);
+            queued.extend(next.queued);
+        }
+
+        self.settle(&entity.name);
+
+        let (burned, levelled_up) = self.end_turn(&entity.name);
+        outcome.burned = burned;
+        outcome.levelled_up.extend(levelled_up);
+        self.settle(&entity.name);
+        Ok(outcome)
+    }
+
+    // Whether the entity may do it to the target, and then what it costs. A
+    // stunned entity only needs somewhere to aim, since nothing will come of it
+    fn prepare(&mut self, action: &Action, verb: &Verb, stunned: bool) -> Result<()> {
+        let name = &action.entity;
+        let harmful = verb.effect.is_harmful();
+        let charmed = self
+            .entity(name)
+            .is_some_and(|e| effects::has(&e.effects, EffectKind::Charm));
+        if !stunned && harmful && charmed {
+            return Err(Error::Charmed(name.clone()));
+        }
+
+        let target_name = action.target.clone().ok_or(Error::InvalidArgs("action "))?;
+        let target = self
+            .entity(&target_name)
+            .ok_or_else(|| Error::NoEntity(target_name.clone()))?;
+        if !target.is_alive() {
+            return Err(Error::EntityDead(target_name));
+        }
+        if !self.may_target(verb.targets, name, &target_name) {
+            return Err(Error::BadTarget(verb.name.clone(), target_name));
+        }
+        if stunned {
+            let actor = self.entity(name).ok_or(Error::NoSession)?;
+            return match (action.kind, action.item) {
+                (ActionKind::Use, Some(item)) if !actor.inventory.contains(&item) => {
+                    Err(Error::NoItem(name.clone(), item.name()))
+                }
+                _ => Ok(()),
+            };
+        }
+        self.reach(verb, name, &target_name)?;
+        if harmful && self.relations.get(name, &target_name) >= relations::ADORES {
+            return Err(Error::Refuses(name.clone(), target_name));
+        }
+        self.ready(action)?;
+
+        // The item first, so there's nothing to give back if there isn't one.
+        // Used up even if it does nothing
+        let actor = self.entity_mut(name).ok_or(Error::NoSession)?;
+        if let (ActionKind::Use, Some(item)) = (action.kind, action.item) {
+            items::take(actor, item)?;
+        }
+        if let Some(cost) = verb.cost {
+            *actor.resource_mut(cost.resource) -= cost.amount;
+        }
+        Ok(())
+    }
+
+    fn transition(&mut self, to: Encounter, by: &str) {
+        self.encounter = to;
+        self.log(Action::transition(to, by.to_string()));
+    }
+
+    // Ends the fight once a side has no one left standing, the party losing
+    // if it's down to no one too
+    fn settle(&mut self, by: &str) {
+        if self.encounter != Encounter::InProgress {
+            return;
+        }
+        if !self.party.iter().any(Entity::is_alive) {
+            self.transition(Encounter::Defeat, by);
+        } else if !self.opponents.iter().any(Entity::is_alive) {
+            self.transition(Encounter::Victory, by);
+        }
+    }
+
+    pub fn flee(&mut self, name: &str) -> Result<()> {
+        let fighting = self.opponents.iter().any(Entity::is_alive);
+        match self.encounter {
+            Encounter::Setup | Encounter::InProgress if fighting => {}
+            encounter => return Err(Error::Encounter(encounter)),
+        }
+        let entity = self
+            .party
+            .iter()
+            .find(|e| e.name == name)
+            .ok_or_else(|| Error::NoEntity(name.to_string()))?;
+        if !entity.is_alive() {
+            return Err(Error::EntityDead(name.to_string()));
+        }
+        self.transition(Encounter::Fled, name);
+        Ok(())
+    }
+
+    // Replaces the opponents with `count` fresh ones rolled from the
+    // templates, as many as there are in the party still standing if not
+    // given. Returns their names
+    pub fn next_encounter(&mut self, count: Option<usize>) -> Result<Vec<String>> {
+        if self.encounter == Encounter::InProgress {
+            return Err(Error::Encounter(self.encounter));
+        }
+        let standing = self.party.iter().filter(|e| e.is_alive()).count();
+        if standing == 0 {
+            return Err(Error::Encounter(Encounter::Defeat));
+        }
+        let templates = Entity::templates()?;
+        if templates.is_empty() {
+            return Err(Error::NoTemplate("anyone".to_string()));
+        }
+
+        let mut rng = self.rng();
+        let name = self.name.clone();
+        self.transition(Encounter::Setup, &name);
+        self.opponents.clear();
+        let mut names = vec![];
+        for _ in 0..count.unwrap_or(standing) {
+            let template = &templates[rng.below(templates.len() as u64) as usize];
+            // The second goblin is Goblin2, and so on
+            let name = (1..)
+                .map(|n| match n {
+                    1 => template.clone(),
+                    n => format!("{template}{n}"),
+                })
+                .find(|name| self.entity(name).is_none())
+                .expect("there's always another number");
+            self.opponents
+                .push(Entity::template(template, name.clone())?);
+            self.log(Action::spawn(name.clone()));
+            names.push(name);
+        }
+        if let Some(grid) = &mut self.grid {
+            let party = self
+                .party
+                .iter()
+                .map(|e| e.name.as_str())
+                .collect::<Vec<_>>();
+            grid.positions
+                .retain(|(name, _)| party.contains(&name.as_str()));
+            grid.place(&self.party, &self.opponents);
+        }
+        // Feelings for whoever is gone don't carry over to whoever replaces them
+        let present = self.party.iter().chain(&self.opponents);
+        let present = present.map(|e| e.name.clone()).collect::<Vec<_>>();
+        self.relations
+            .0
+            .retain(|r| present.contains(&r.from) && present.contains(&r.to));
+        Ok(names)
+    }
+
+    // On a grid the target has to be close enough, and in sight
+    fn reach(&self, verb: &Verb, from: &str, to: &str) -> Result<()> {
+        let (Some(grid), Some(range)) = (&self.grid, verb.range) else {
+            return Ok(());
+        };
+        let (Some(start), Some(end)) = (grid.position(from), grid.position(to)) else {
+            return Ok(());
+        };
+        let distance = start.distance(end);
+        if distance > range {
+            return Err(Error::OutOfRange(
+                verb.name.clone(),
+                to.to_string(),
+                distance,
+                range,
+            ));
+        }
+        if !grid.in_sight(start, end) {
+            return Err(Error::OutOfSight(from.to_string(), to.to_string()));
+        }
+        Ok(())
+    }
+
+    pub fn set_grid(&mut self, mut grid: Grid) {
+        grid.place(&self.party, &self.opponents);
+        self.grid = Some(grid);
+    }
+
+    // Moving takes the entity's turn, as far as its speed gets it
+    pub fn walk(&mut self, name: &str, to: Pos) -> Result<Outcome> {
+        if self.encounter.is_over() {
+            return Err(Error::Encounter(self.encounter));
+        }
+        let entity = self
+            .entity(name)
+            .ok_or_else(|| Error::NoEntity(name.to_string()))?;
+        if !entity.is_alive() {
+            return Err(Error::EntityDead(name.to_string()));
+        }
+        let stunned = effects::has(&entity.effects, EffectKind::Stun);
+        let speed = entity.stats.speed;
+        let grid = self.grid.as_ref().ok_or(Error::NoGrid)?;
+        if !stunned {
+            let from = grid.position(name).ok_or(Error::NoGrid)?;
+            let everyone = self.party.iter().chain(&self.opponents).collect::<Vec<_>>();
+            match grid.steps(from, to, &everyone) {
+                None => return Err(Error::CantReach(name.to_string(), to)),
+                Some(steps) if steps > speed => {
+                    return Err(Error::TooFar(name.to_string(), to, speed))
+                }
+                Some(_) => {}
+            }
+        }
+
+        if self.encounter == Encounter::Setup && self.opponents.iter().any(Entity::is_alive) {
+            self.transition(Encounter::InProgress, name);
+        }
+        if !stunned {
+            self.grid.as_mut().ok_or(Error::NoGrid)?.move_to(name, to);
+        }
+        self.log(Action::walk(name.to_string(), to));
+        let (burned, levelled_up) = self.end_turn(name);
+        self.settle(name);
+        Ok(Outcome {
+            stunned,
+            burned,
+            levelled_up,
+            ..Default::default()
+        })
+    }
+
+    // Whether the entity can pay for the action and isn't still cooling down
+    // from the last time it did it
+    pub fn ready(&self, action: &Action) -> Result<()> {
+        let verb = self.verb(action)?;
+        let entity = self
+            .entity(&action.entity)
+            .ok_or_else(|| Error::NoEntity(action.entity.clone()))?;
+        if let Some(cost) = verb.cost {
+            let have = entity.resource(cost.resource);
+            if have < cost.amount {
+                return Err(Error::Exhausted(entity.name.clone(), cost, have));
+            }
+        }
+
+        let Some(cooldown) = verb.cooldown else {
+            return Ok(());
+        };
+        // Trying it while stunned didn't count as doing it
+        let same = |a: &Action| a.entity == action.entity && a.kind == action.kind;
+        let Some(last) = self
+            .actions
+            .iter()
+            .rposition(|a| same(a) && a.verb == action.verb && !a.stunned)
+        else {
+            return Ok(());
+        };
+        let wait = match cooldown {
+            Cooldown::Turns(turns) => {
+                // Anything the entity did to someone since took a turn
+                let since = self.actions[last + 1..]
+                    .iter()
+                    .filter(|a| a.entity == action.entity && a.target.is_some())
+                    .count() as u32;
+                Cooldown::Turns(turns.saturating_sub(since))
+            }
+            Cooldown::Millis(ms) => {
+                // Never longer than the cooldown, whatever the clocks say
+                let since = action.start.saturating_sub(self.actions[last].start);
+                let wait = (ms as i128).saturating_sub(since).clamp(0, ms as i128);
+                Cooldown::Millis(wait as u64)
+            }
+        };
+        match wait {
+            Cooldown::Turns(0) | Cooldown::Millis(0) => Ok(()),
+            wait => Err(Error::Cooldown(verb.name, wait)),
+        }
+    }
 
+    // Does the action and deals with what follows from it: anyone it or its
+    // script killed, and the target joining the party
+    fn resolve(&mut self, mut action: Action, verb: &Verb, rng: &mut Rng) -> Result<Outcome> {
+        let luck = match self.in_party(&action.entity) {
+            true => self.luck,
+            false => -self.luck,
+        };
+        let alive = self
+            .party
+            .iter()
+            .chain(&self.opponents)
+            .filter(|e| e.is_alive())
+            .map(|e| e.name.clone())
+            .collect::<Vec<_>>();
+        let mut scene = Scene {
+            party: &mut self.party,
+            opponents: &mut self.opponents,
+        };
+        let script = verb.script.as_ref();
+        let mut outcome = action.exec(&verb.effect, script, &mut scene, rng, luck)?;
+        action.rolls = outcome.rolls.clone();
+        action.damage = outcome.damage;
+        action.healed = outcome.healed;
+        self.relations.felt(&action, &verb.effect);
+        let target = action.target.clone().unwrap_or_default();
+        self.log(action);
 
-            Lorem chad ipsum dolor sit amet, consectetur adipiscing elit, sed do eiusmod tempor incididunt ut labore et dolore magna aliqua. At urna condimentum mattis pellentesque id nibh tortor id aliquet. Convallis convallis tellus id interdum velit laoreet id donec. Magnis dis parturient montes nascetur ridiculus mus. Posuere ac ut consequat semper viverra nam. A iaculis at erat pellentesque adipiscing commodo. Ut porttitor leo a diam sollicitudin tempor id. Dolor purus non enim praesent elementum facilisis leo vel. Neque egestas congue quisque egestas. Diam volutpat commodo sed egestas egestas fringilla phasellus faucibus. Eget arcu dictum varius duis at consectetur lorem. Magna fermentum iaculis eu non diam phasellus vestibulum. Dictumst quisque sagittis purus sit amet volutpat consequat. Id donec ultrices tincidunt arcu. Ut tristique et egestas quis ipsum suspendisse ultrices gravida. Non quam lacus suspendisse faucibus interdum posuere lorem. Tristique nulla aliquet enim tortor. Commodo elit at imperdiet dui accumsan. Duis at consectetur lorem donec.
-            Enim praesent elementum facilisis leo vel fringilla est. At varius vel pharetra vel turpis nunc eget. Lectus magna fringilla urna porttitor. Egestas congue quisque egestas diam in arcu. Velit aliquet sagittis id consectetur purus ut faucibus pulvinar. Non tellus orci ac auctor. At risus viverra adipiscing at in tellus integer. Phasellus egestas tellus rutrum tellus pellentesque. Eget sit amet tellus cras adipiscing enim. Et egestas quis ipsum suspendisse. Scelerisque in dictum non consectetur a erat nam at.
-            Donec ultrices tincidunt arcu non sodales. Diam phasellus vestibulum lorem sed risus ultricies. Nunc congue nisi vitae suscipit tellus mauris. Elementum nisi quis eleifend quam adipiscing vitae proin sagittis. Platea dictumst quisque sagittis purus sit amet. Sit amet nisl purus in mollis. Praesent semper feugiat nibh sed pulvinar. Tortor condimentum lacinia quis vel eros donec. Condimentum lacinia quis vel eros. Fames ac turpis egestas integer eget aliquet nibh praesent tristique.
-            Vel quam elementum pulvinar etiam non. Duis ut diam quam nulla porttitor massa id neque. Lacinia at quis risus sed vulputate odio ut enim. Dui sapien eget mi proin sed libero enim sed faucibus. Et pharetra pharetra massa massa ultricies. Senectus et netus et malesuada. In tellus integer feugiat scelerisque. Senectus et netus et malesuada fames ac turpis egestas maecenas. Ut tortor pretium viverra suspendisse. Lobortis feugiat vivamus at augue eget arcu dictum. Ut enim blandit volutpat maecenas volutpat blandit aliquam etiam. Vestibulum sed arcu non odio euismod. Cursus mattis molestie a iaculis. Porttitor rhoncus dolor purus non. Tempus quam pellentesque nec nam aliquam sem et tortor. Euismod elementum nisi quis eleifend quam.
-            Gravida cum sociis natoque penatibus et magnis dis. Laoreet non curabitur gravida arcu. Viverra suspendisse potenti nullam ac tortor vitae purus faucibus ornare. Commodo elit at imperdiet dui accumsan sit amet nulla facilisi. Ipsum a arcu cursus vitae. Felis eget velit aliquet sagittis id consectetur. Lectus nulla at volutpat diam ut venenatis tellus. Nisl vel pretium lectus quam id. Sapien pellentesque habitant morbi tristique senectus. Diam sit amet nisl suscipit. Ante metus dictum at tempor commodo ullamcorper a. Neque laoreet suspendisse interdum consectetur libero. Pellentesque dignissim enim sit amet venenatis urna cursus eget nunc. Blandit volutpat maecenas volutpat blandit aliquam etiam. Id interdum velit laoreet id donec ultrices tincidunt. Turpis in eu mi bibendum neque egestas congue quisque.
-            Tellus pellentesque eu tincidunt tortor aliquam nulla facilisi cras fermentum. Enim facilisis gravida neque convallis a cras. Vitae aliquet nec ullamcorper sit amet risus. Duis at tellus at urna condimentum mattis pellentesque. Cras pulvinar mattis nunc sed. Ac turpis egestas sed tempus urna et pharetra pharetra massa. Ultrices eros in cursus turpis massa tincidunt dui. Massa sed elementum tempus egestas sed. Nisi scelerisque eu ultrices vitae auctor eu augue. Id neque aliquam vestibulum morbi. Sapien pellentesque habitant morbi tristique senectus et netus et. Tellus in metus vulputate eu. Feugiat in fermentum posuere urna nec tincidunt praesent. Gravida quis blandit turpis cursus. Scelerisque fermentum dui faucibus in ornare quam.
-            Sit amet dictum sit amet. Porta lorem mollis aliquam ut porttitor leo. Sit amet consectetur adipiscing elit duis tristique sollicitudin nibh sit. Felis bibendum ut tristique et. In hendrerit gravida rutrum quisque non. Ullamcorper velit sed ullamcorper morbi tincidunt ornare. Commodo quis imperdiet massa tincidunt. Faucibus turpis in eu mi bibendum neque egestas congue quisque. Integer enim neque volutpat ac tincidunt. Euismod quis viverra nibh cras pulvinar mattis. Facilisis magna etiam tempor orci eu. Senectus et netus et malesuada fames ac turpis egestas integer. Mi ipsum faucibus vitae aliquet nec. Ullamcorper morbi tincidunt ornare massa eget egestas purus viverra accumsan. Placerat orci nulla pellentesque dignissim enim sit amet venenatis.
-            Amet massa vitae tortor condimentum lacinia quis vel eros. Tellus in hac habitasse platea dictumst vestibulum. Quisque egestas diam in arcu cursus. Sit amet consectetur adipiscing elit pellentesque habitant morbi tristique senectus. Eleifend donec pretium vulputate sapien nec sagittis aliquam malesuada bibendum. Sit amet consectetur adipiscing elit pellentesque habitant morbi tristique senectus. Ut ornare lectus sit amet est placerat in egestas erat. Consectetur purus ut faucibus pulvinar elementum. Senectus et netus et malesuada fames ac turpis. Proin libero nunc consequat interdum varius sit amet mattis. Aliquet bibendum enim facilisis gravida neque convallis a cras. Sapien eget mi proin sed libero enim sed faucibus. Mi bibendum neque egestas congue. Molestie at elementum eu facilisis sed odio morbi quis commodo. Nibh tortor id aliquet lectus proin nibh. Varius quam quisque id diam vel.
+        for name in alive {
+            if !self.entity(&name).is_some_and(Entity::is_alive) {
+                let levelled_up = self.die(name);
+                outcome.levelled_up.extend(levelled_up);
+            }
+        }
+        if self.entity(&target).is_some_and(Entity::is_alive) {
+            outcome.defected = self.defect(&target);
+        }
+        Ok(outcome)
+    }
 
+    // Effects count down at the end of their carrier's own turn
+    fn end_turn(&mut self, name: &str) -> (u8, Vec<String>) {
+        let Some(entity) = self.entity_mut(name).filter(|e| e.is_alive()) else {
+            return (0, vec![]);
+        };
+        let burned = effects::tick(&mut entity.effects).min(entity.health);
+        entity.health -= burned;
+        entity.recover(RECOVERY);
+        let mut levelled_up = vec![];
+        if burned > 0 && !entity.is_alive() {
+            levelled_up = self.die(name.to_string());
+        }
+        (burned, levelled_up)
+    }
 
-            Caught exception: map::at, trace:
-            0# get_data_from_config(std::string_view) at /home/U/basic.cpp:420
-            1# bar(std::string_view) at /home/U/basic.cpp:69
-            2# main at /home/U/basic.cpp:17
+    // An opponent that has grown fond enough of someone in the party joins it
+    fn defect(&mut self, name: &str) -> bool {
+        let Some(i) = self.opponents.iter().position(|e| e.name == name) else {
+            return false;
+        };
+        let loved = self
+            .party
+            .iter()
+            .find(|e| self.relations.get(name, &e.name) >= relations::DEFECTS);
+        let Some(loved) = loved.map(|e| e.name.clone()) else {
+            return false;
+        };
+        let entity = self.opponents.remove(i);
+        self.party.push(entity);
+        self.log(Action::defect(name.to_string(), loved));
+        true
+    }
 
+    // Whatever isn't an action, the same wherever the session lives
+    pub fn change(&mut self, change: Change) -> Result<()> {
+        match change {
+            Change::Equip(name, item) => self.equip(&name, item),
+            Change::Unequip(name, item) => self.unequip(&name, item),
+            Change::Flee(name) => self.flee(&name),
+            Change::Move(name, to) => self.walk(&name, to).map(drop),
+            Change::Grid(grid) => {
+                self.set_grid(grid);
+                Ok(())
+            }
+            Change::NextEncounter(count) => self.next_encounter(count).map(drop),
+        }
+    }
 
+    // Equipping doesn't take a turn, but it goes in the log all the same
+    pub fn equip(&mut self, name: &str, item: Item) -> Result<()> {
+        let entity = self
+            .entity_mut(name)
+            .ok_or_else(|| Error::NoEntity(name.to_string()))?;
+        if !entity.is_alive() {
+            return Err(Error::EntityDead(name.to_string()));
+        }
+        items::equip(entity, item)?;
+        self.log(Action::equip(name.to_string(), item));
+        Ok(())
+    }
 
-            Segmentation fault 
-            "));
+    pub fn unequip(&mut self, name: &str, item: Item) -> Result<()> {
+        let entity = self
+            .entity_mut(name)
+            .ok_or_else(|| Error::NoEntity(name.to_string()))?;
+        if !entity.is_alive() {
+            return Err(Error::EntityDead(name.to_string()));
         }
+        items::unequip(entity, item)?;
+        self.log(Action::unequip(name.to_string(), item));
+        Ok(())
+    }
+
+    // A fallen opponent's XP is split between whoever in the party is still
+    // standing, the first in line get the remainder. Level ups go in the log
+    // right after the death so a replay levels up at the same point
+    fn die(&mut self, name: String) -> Vec<String> {
+        let reward = self
+            .opponents
+            .iter()
+            .find(|e| e.name == name)
+            .map(|e| self.levels.reward(e));
+        if let Some(entity) = self.entity_mut(&name) {
+            entity.effects.clear();
+        }
+        self.log(Action::die(name));
+
+        let survivors = self.party.iter().filter(|e| e.is_alive()).count() as u64;
+        let (Some(reward), 1..) = (reward, survivors) else {
+            return vec![];
+        };
+        let mut levelled_up = vec![];
+        let alive = self.party.iter_mut().filter(|e| e.is_alive());
+        for (i, entity) in alive.enumerate() {
+            entity.xp += reward / survivors + ((i as u64) < reward % survivors) as u64;
+            while let Some(level) = self.levels.next(entity) {
+                level.grow(entity);
+                levelled_up.push(entity.name.clone());
+            }
         }
+        for name in &levelled_up {
+            self.log(Action::level_up(name.clone()));
+        }
+        levelled_up
+    }
+}
+
+// Names come from the command line and from whoever talks to a relay, and
+// end up in a path. Whatever they are, the file stays in sessions/
+pub fn check_name(name: &str) -> Result<()> {
+    let bad = |c: char| c == '/' || c == '\\' || c.is_control();
+    match name.is_empty() || name.contains("..") || name.contains(bad) {
+        true => Err(Error::BadSessionName(name.to_string())),
+        false => Ok(()),
+    }
+}
+
+fn session_file(name: &str) -> Result<File> {
+    check_name(name)?;
+    fs::create_dir_all(DIRNAME)?;
+    let file = OpenOptions::new()
+        .create(true)
+        .read(true)
+        .write(true)
+        .open(format!("{DIRNAME}/{name}{SUFFIX}"))?;
+    Ok(file)
+}
+
+impl Session {
+    pub fn load(name: &str) -> Result<Self> {
         let mut file = match session_file(name) {
-            Err(e) if matches!(e.kind(), io::ErrorKind::NotFound) => return Err(Error::NoSession),
+            Err(Error::Io(e)) if e.kind() == io::ErrorKind::NotFound => {
+                return Err(Error::NoSession)
+            }
             e => e?,
         };
         let mut bytes = vec![];
         file.read_to_end(&mut bytes)?;
         if bytes.is_empty() {
+            // Not a session of its own, but maybe a campaign's encounter
+            if Campaign::exists(name) {
+                let campaign = Campaign::load(name)?;
+                return campaign.current().cloned().ok_or(Error::NoSession);
+            }
             log!("No session found");
             return Err(Error::NoSession);
         }
         let mut reader = FieldReader::new(bytes.as_slice());
-        Self::deserialize(&mut reader)
+        let mut session: Self = reader.read_field()?;
+        session.levels = Levels::load()?;
+        session.verbs = Verbs::load()?;
+        Ok(session)
     }
 
     pub fn save(&self) -> Result<()> {
         let mut file = session_file(&self.name)?;
+        if file.metadata()?.len() == 0 && Campaign::exists(&self.name) {
+            let mut campaign = Campaign::load(&self.name)?;
+            let current = campaign.current_mut().ok_or(Error::NoSession)?;
+            *current = self.clone();
+            return campaign.save();
+        }
+        file.set_len(0)?;
         let bytes = serialize(self);
         file.write_all(&bytes)?;
         Ok(())
     }
 }
 
+impl Session {
+    // The last action's number, 0 before there are any
+    pub fn seq(&self) -> u64 {
+        self.actions.last().map_or(0, |action| action.seq)
+    }
+
+    // Numbered right after whatever came before it
+    fn log(&mut self, mut action: Action) {
+        action.seq = self.seq() + 1;
+        self.actions.push(action);
+    }
+
+    // Everything after action number `from`, and the chat after the first
+    // `chat_from` lines
+    pub fn delta(&self, from: u64, chat_from: usize) -> StateDelta {
+        StateDelta {
+            session: self.name.clone(),
+            from,
+            actions: self
+                .actions
+                .iter()
+                .filter(|a| a.seq > from)
+                .cloned()
+                .collect(),
+            party: self.party.clone(),
+            opponents: self.opponents.clone(),
+            relations: self.relations.clone(),
+            encounter: self.encounter,
+            grid: self.grid.clone(),
+            owners: self.owners.clone(),
+            seats: self.seats.clone(),
+            chat_from: chat_from as u64,
+            chat: self.chat.get(chat_from..).unwrap_or_default().to_vec(),
+        }
+    }
+
+    // Catches up with a delta, as long as it starts where this one ends
+    pub fn merge(&mut self, delta: StateDelta) -> Result<()> {
+        let chat_from = delta.chat_from as usize;
+        if delta.session != self.name || delta.from != self.seq() || chat_from > self.chat.len() {
+            return Err(Error::OutOfSync(delta.from, self.seq()));
+        }
+        self.actions.extend(delta.actions);
+        self.party = delta.party;
+        self.opponents = delta.opponents;
+        self.relations = delta.relations;
+        self.encounter = delta.encounter;
+        self.grid = delta.grid;
+        self.owners = delta.owners;
+        self.seats = delta.seats;
+        self.chat.truncate(chat_from);
+        self.chat.extend(delta.chat);
+        Ok(())
+    }
+}
+
 impl Deserialize for Session {
     fn deserialize(reader: &mut FieldReader<'_>) -> Result<Self>
     where
         Self: Sized,
     {
-        dbg!("Deserializing");
-        reader.ensure_type(FieldType::Session).unwrap();
-        let session = Self {
+        let mut session = Self {
             party: reader.read_field()?,
             opponents: reader.read_field()?,
             actions: reader.read_field()?,
             name: reader.read_field()?,
+            seed: reader.read_field_or_default()?,
+            luck: reader.read_field_or_default::<u8, _>()? as i8,
+            levels: Default::default(),
+            relations: Relations(reader.read_field_or_default()?),
+            verbs: Default::default(),
+            encounter: reader.read_field_or_default::<u8, _>()?.try_into()?,
+            grid: reader.read_field_or_default::<Vec<Grid>, _>()?.pop(),
+            owners: lobby::read_owners(reader)?,
+            seats: Seats::read(reader)?,
+            chat: Chat::read_all(reader)?,
         };
-        dbg!("{session:?}");
+        // Logs from before actions were numbered
+        for (action, seq) in session.actions.iter_mut().zip(1..) {
+            if action.seq == 0 {
+                action.seq = seq;
+            }
+        }
 
         Ok(session)
     }
@@ -157,6 +930,14 @@ impl Serialize for Session {
         size += self.opponents.serialize(buf);
         size += self.actions.serialize(buf);
         size += Field::Str(self.name.clone()).serialize(buf);
+        size += Field::U64(self.seed).serialize(buf);
+        size += Field::Byte(self.luck as u8).serialize(buf);
+        size += self.relations.0.serialize(buf);
+        size += Field::Byte(self.encounter as u8).serialize(buf);
+        size += self.grid.iter().cloned().collect::<Vec<_>>().serialize(buf);
+        size += lobby::serialize_owners(&self.owners, buf);
+        size += Seats::serialize(self.seats.as_ref(), buf);
+        size += Chat::serialize_all(&self.chat, buf);
         s(buf, size)
     }
 }
@@ -165,39 +946,91 @@ impl Serialize for Session {
 mod tests {
     use crate::{
         actions::{Action, ActionKind},
-        serde::{serialize, Deserialize, Field, FieldReader},
-        Entity,
+        effects::{Effect, EffectKind},
+        items::Item,
+        relations::{Relation, Relations},
+        serde::{serialize, Field, FieldReader},
+        Entity, Stats,
     };
 
-    use super::Session;
+    use super::{Encounter, Error, Session};
+    use crate::grid::{Grid, Pos};
+    use crate::script::Script;
+    use crate::verbs::Cooldown;
 
-    fn deserialize<T: Deserialize>(bytes: &[u8]) -> crate::error::Result<T> {
+    fn deserialize<T, E>(bytes: &[u8]) -> crate::error::Result<T>
+    where
+        T: TryFrom<Field, Error = E>,
+        E: Into<crate::error::Error>,
+    {
         let mut reader = FieldReader::new(bytes);
-        T::deserialize(&mut reader)
+        reader.read_field()
     }
 
     #[test]
     fn session_round_trip() {
         let session = Session {
             actions: vec![
-                Action::spawn("Toerktumlare".to_string()),
+                Action {
+                    seq: 1,
+                    ..Action::spawn("Toerktumlare".to_string())
+                },
                 Action {
                     kind: ActionKind::Fight,
                     entity: "Gilgamesh".to_string(),
                     start: 1234,
                     target: Some("Tommy".to_string()),
+                    rolls: vec![1, 2],
+                    item: None,
+                    verb: "fight".to_string(),
+                    to: None,
+                    damage: 3,
+                    healed: 0,
+                    seq: 2,
+                    stunned: false,
+                },
+                Action {
+                    seq: 3,
+                    ..Action::walk("Gilgamesh".to_string(), Pos::new(2, 3))
+                },
+                Action {
+                    seq: 4,
+                    ..Action::equip("florp".to_string(), Item::Sword)
                 },
             ],
             party: vec![crate::Entity {
                 name: "florp".to_string(),
                 field_c: true,
                 health: 69,
+                damage: Some("2d6+3".parse().unwrap()),
+                effects: vec![Effect::new(EffectKind::Burn, 1, 2)],
+                stats: Stats {
+                    max_health: 69,
+                    attack: 3,
+                    defense: 2,
+                    speed: 4,
+                    level: 7,
+                    max_mana: 6,
+                    max_stamina: 1,
+                },
+                xp: 42,
+                inventory: vec![Item::Potion, Item::Potion],
+                equipped: vec![Item::Sword],
+                mana: 4,
+                stamina: 0,
             }],
+            seed: 2112,
+            luck: -2,
+            relations: Relations(vec![Relation {
+                from: "florp".to_string(),
+                to: "Tommy".to_string(),
+                affinity: -4,
+            }]),
             ..Default::default()
         };
 
         let serialized = serialize(&session);
-        let actual = deserialize::<Session>(&serialized).unwrap();
+        let actual = deserialize::<Session, _>(&serialized).unwrap();
 
         assert_eq!(actual, session);
     }
@@ -206,7 +1039,7 @@ mod tests {
     fn action_round_trip() {
         let expected = Action::spawn("".to_string());
         let serialized = serialize(&expected);
-        let actual = deserialize::<Action>(&serialized).unwrap();
+        let actual = deserialize::<Action, _>(&serialized).unwrap();
 
         assert_eq!(actual, expected);
     }
@@ -217,14 +1050,346 @@ mod tests {
             name: "florp".to_string(),
             health: 69,
             field_c: true,
+            damage: None,
+            effects: vec![],
+            stats: Stats::default(),
+            xp: 0,
+            inventory: vec![],
+            equipped: vec![],
+            mana: 1,
+            stamina: 2,
         };
         let serialized = serialize(&expected);
         eprintln!("BYTES: {serialized:?}");
-        let actual = deserialize::<Entity>(&serialized).unwrap();
+        let actual = deserialize::<Entity, _>(&serialized).unwrap();
 
         assert_eq!(actual, expected);
     }
 
+    #[test]
+    fn fallen_opponent_xp_is_shared() {
+        let mut gilgamesh = Entity::new("Gilgamesh".to_string());
+        gilgamesh.damage = Some("5".parse().unwrap());
+        let enkidu = Entity::new("Enkidu".to_string());
+        let mut tommy = Entity::new("Tommy".to_string());
+        tommy.stats.level = 4;
+        let mut session =
+            Session::encounter("test".to_string(), vec![gilgamesh, enkidu], vec![tommy], 0);
+
+        let gilgamesh = session.entity("Gilgamesh").unwrap();
+        let tommy = session.entity("Tommy").unwrap();
+        let action = Action::interact(ActionKind::Fight, gilgamesh, tommy).unwrap();
+        let outcome = session.apply(action).unwrap();
+
+        assert!(outcome.died);
+        assert_eq!(outcome.levelled_up, ["Gilgamesh", "Enkidu"]);
+        assert!(session
+            .party
+            .iter()
+            .all(|e| e.xp == 10 && e.stats.level == 2));
+        let log = session.actions.iter().map(|a| (a.kind, a.entity.as_str()));
+        assert_eq!(
+            log.skip(3).collect::<Vec<_>>(),
+            [
+                (ActionKind::Begin, "Gilgamesh"),
+                (ActionKind::Fight, "Gilgamesh"),
+                (ActionKind::Die, "Tommy"),
+                (ActionKind::LevelUp, "Gilgamesh"),
+                (ActionKind::LevelUp, "Enkidu"),
+                (ActionKind::Victory, "Gilgamesh"),
+            ]
+        );
+    }
+
+    #[test]
+    fn potions_get_used_up() {
+        let mut gilgamesh = Entity::new("Gilgamesh".to_string());
+        gilgamesh.inventory = vec![Item::Potion, Item::Sword];
+        gilgamesh.health = 1;
+        let mut session = Session::encounter("test".to_string(), vec![gilgamesh], vec![], 0);
+
+        let drink = |session: &Session, item| {
+            let gilgamesh = session.entity("Gilgamesh").unwrap();
+            Action::use_item(item, gilgamesh, gilgamesh).unwrap()
+        };
+        let outcome = session.apply(drink(&session, Item::Potion)).unwrap();
+        assert!(outcome.healed >= 2);
+        assert_eq!(session.party[0].health, 1 + outcome.healed);
+        assert_eq!(session.party[0].inventory, [Item::Sword]);
+        assert!(session.apply(drink(&session, Item::Potion)).is_err());
+        assert!(session.apply(drink(&session, Item::Sword)).is_err());
+
+        // Nothing is paid for a potion that isn't there
+        session.verbs = "[use]\ntargets = any\ncost = 1 mana".parse().unwrap();
+        let before = session.clone();
+        assert!(session.apply(drink(&session, Item::Potion)).is_err());
+        assert_eq!(session, before);
+    }
+
+    #[test]
+    fn loved_opponent_refuses_then_defects() {
+        let gilgamesh = Entity::new("Gilgamesh".to_string());
+        let tommy = Entity::new("Tommy".to_string());
+        let mut session = Session::encounter("test".to_string(), vec![gilgamesh], vec![tommy], 0);
+
+        let act = |session: &Session, kind, from, to| {
+            let from = session.entity(from).unwrap();
+            let to = session.entity(to).unwrap();
+            Action::interact(kind, from, to).unwrap()
+        };
+        for _ in 0..2 {
+            let love = act(&session, ActionKind::Love, "Gilgamesh", "Tommy");
+            assert!(!session.apply(love).unwrap().defected);
+        }
+        assert_eq!(session.relations.get("Tommy", "Gilgamesh"), 4);
+        session.opponents[0].effects.clear();
+        let fight = act(&session, ActionKind::Fight, "Tommy", "Gilgamesh");
+        assert!(matches!(session.apply(fight), Err(Error::Refuses(..))));
+
+        let love = act(&session, ActionKind::Love, "Gilgamesh", "Tommy");
+        assert!(session.apply(love).unwrap().defected);
+        assert!(session.opponents.is_empty());
+        assert_eq!(session.party[1].name, "Tommy");
+        // Nobody is left to fight
+        assert_eq!(session.encounter, Encounter::Victory);
+        let defect = &session.actions[session.actions.len() - 2];
+        assert_eq!(defect.kind, ActionKind::Defect);
+        assert_eq!(defect.target.as_deref(), Some("Gilgamesh"));
+    }
+
+    #[test]
+    fn custom_verbs_are_logged_by_id() {
+        let gilgamesh = Entity::new("Gilgamesh".to_string());
+        let enkidu = Entity::new("Enkidu".to_string());
+        let tommy = Entity::new("Tommy".to_string());
+        let mut session =
+            Session::encounter("test".to_string(), vec![gilgamesh, enkidu], vec![tommy], 0);
+        session.verbs = "[smite]\nname = smite\ntargets = enemy\neffect = damage 3"
+            .parse()
+            .unwrap();
+
+        let smite = |session: &Session, to| {
+            let verb = session.verbs.get("smite").unwrap();
+            let gilgamesh = session.entity("Gilgamesh").unwrap();
+            Action::perform(verb, gilgamesh, session.entity(to).unwrap()).unwrap()
+        };
+        let action = smite(&session, "Enkidu");
+        assert!(matches!(session.apply(action), Err(Error::BadTarget(..))));
+        let outcome = session.apply(smite(&session, "Tommy")).unwrap();
+        assert_eq!(outcome.damage, 3);
+
+        let logged = session.actions.last().unwrap();
+        assert_eq!(
+            (logged.kind, logged.verb.as_str()),
+            (ActionKind::Custom, "smite")
+        );
+        assert_eq!(
+            deserialize::<Action, _>(&serialize(logged)).unwrap(),
+            *logged
+        );
+    }
+
+    #[test]
+    fn scripts_change_entities_and_chain_actions() {
+        let gilgamesh = Entity::new("Gilgamesh".to_string());
+        let tommy = Entity::new("Tommy".to_string());
+        let timmy = Entity::new("Timmy".to_string());
+        let mut session =
+            Session::encounter("test".to_string(), vec![gilgamesh], vec![tommy, timmy], 0);
+        session.verbs = "[chain]\ntargets = enemy\neffect = damage 1\n[echo]\ntargets = any"
+            .parse()
+            .unwrap();
+        let script = "
+            set(actor, \"attack\", get(actor, \"attack\") + 1)
+            let next = ally_of(target)
+            if next != \"\" && get(actor, \"attack\") < 2 { act(\"chain\", actor, next) }
+        ";
+        session.verbs.0[0].script = Some(Script::parse("chain", script).unwrap());
+        session.verbs.0[1].script =
+            Some(Script::parse("echo", "act(\"echo\", actor, target)").unwrap());
+
+        let gilgamesh = session.entity("Gilgamesh").unwrap();
+        let chain = session.verbs.get("chain").unwrap();
+        let action = Action::perform(chain, gilgamesh, session.entity("Tommy").unwrap()).unwrap();
+        let outcome = session.apply(action).unwrap();
+        assert_eq!(outcome.queued.len(), 1);
+        // Each hit makes the next one harder
+        assert_eq!(session.entity("Gilgamesh").unwrap().stats.attack, 2);
+        assert_eq!(session.entity("Tommy").unwrap().health, 4);
+        assert_eq!(session.entity("Timmy").unwrap().health, 3);
+        assert_eq!(session.actions.len(), 6);
+
+        let gilgamesh = session.entity("Gilgamesh").unwrap();
+        let echo = session.verbs.get("echo").unwrap();
+        let action = Action::perform(echo, gilgamesh, gilgamesh).unwrap();
+        let before = session.clone();
+        assert!(matches!(session.apply(action), Err(Error::Script(_))));
+        assert_eq!(session, before);
+
+        // What a script asks for still has to be paid for
+        session.verbs = "
+            [spark]
+            targets = enemy
+            effect = damage 1
+            [burn]
+            targets = enemy
+            cost = 99 mana
+            effect = damage 5
+            "
+        .parse()
+        .unwrap();
+        session.verbs.0[0].script =
+            Some(Script::parse("spark", "act(\"burn\", actor, target)").unwrap());
+        let gilgamesh = session.entity("Gilgamesh").unwrap();
+        let spark = session.verbs.get("spark").unwrap();
+        let action = Action::perform(spark, gilgamesh, session.entity("Tommy").unwrap()).unwrap();
+        let before = session.clone();
+        assert!(matches!(session.apply(action), Err(Error::Exhausted(..))));
+        assert_eq!(session, before);
+    }
+
+    #[test]
+    fn costs_and_cooldowns_hold_actions_back() {
+        let gilgamesh = Entity::new("Gilgamesh".to_string());
+        let mut tommy = Entity::new("Tommy".to_string());
+        tommy.set_max_health(50);
+        let mut session = Session::encounter("test".to_string(), vec![gilgamesh], vec![tommy], 0);
+        session.verbs = "
+            [bolt]
+            targets = enemy
+            cost = 2 mana
+            effect = damage 1
+            [dash]
+            targets = enemy
+            cooldown = 2 turns
+            effect = damage 1
+            [parry]
+            targets = enemy
+            cooldown = 1000 ms
+            "
+        .parse()
+        .unwrap();
+        let act = |session: &mut Session, verb: &str, start: i128| {
+            let verb = session.verbs.get(verb).unwrap();
+            let gilgamesh = session.entity("Gilgamesh").unwrap();
+            let mut action = Action::perform(verb, gilgamesh, session.entity("Tommy").unwrap())?;
+            action.start = start;
+            session.apply(action)
+        };
+
+        act(&mut session, "bolt", 0).unwrap();
+        assert_eq!(session.entity("Gilgamesh").unwrap().mana, 2);
+        act(&mut session, "bolt", 0).unwrap();
+        let error = act(&mut session, "bolt", 0).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Gilgamesh has 1 of the 2 mana it takes, ready in 1 turn"
+        );
+
+        act(&mut session, "dash", 0).unwrap();
+        let error = act(&mut session, "dash", 0).unwrap_err();
+        assert!(matches!(error, Error::Cooldown(_, Cooldown::Turns(2))));
+        act(&mut session, "bolt", 0).unwrap();
+        assert!(act(&mut session, "dash", 0).is_err());
+        act(&mut session, "parry", 0).unwrap();
+        act(&mut session, "dash", 0).unwrap();
+
+        let error = act(&mut session, "parry", 400).unwrap_err();
+        assert_eq!(error.to_string(), "can't parry again yet, ready in 600 ms");
+        act(&mut session, "parry", 1000).unwrap();
+        // A clock from the dawn of time waits the whole cooldown
+        let error = act(&mut session, "parry", i128::MIN).unwrap_err();
+        assert_eq!(error.to_string(), "can't parry again yet, ready in 1000 ms");
+
+        // Trying while stunned doesn't start the cooldown
+        let stun = Effect::new(EffectKind::Stun, 0, 1);
+        session.entity_mut("Gilgamesh").unwrap().effects.push(stun);
+        act(&mut session, "dash", 0).unwrap();
+        assert!(session.actions.last().unwrap().stunned);
+        act(&mut session, "dash", 0).unwrap();
+    }
+
+    #[test]
+    fn encounters_end_and_stay_ended() {
+        let gilgamesh = Entity::new("Gilgamesh".to_string());
+        let tommy = Entity::new("Tommy".to_string());
+        let mut session = Session::encounter("test".to_string(), vec![gilgamesh], vec![tommy], 0);
+        assert_eq!(session.encounter, Encounter::Setup);
+        session.verbs = "[doom]\ntargets = any\neffect = damage 99".parse().unwrap();
+        let doom = |session: &Session, from, to| {
+            let verb = session.verbs.get("doom").unwrap();
+            let from = session.entity(from).unwrap();
+            Action::perform(verb, from, session.entity(to).unwrap()).unwrap()
+        };
+
+        let mut fled = session.clone();
+        assert!(matches!(fled.flee("Tommy"), Err(Error::NoEntity(_))));
+        fled.flee("Gilgamesh").unwrap();
+        let action = doom(&fled, "Gilgamesh", "Tommy");
+        assert!(matches!(
+            fled.apply(action),
+            Err(Error::Encounter(Encounter::Fled))
+        ));
+
+        let action = doom(&session, "Tommy", "Gilgamesh");
+        session.apply(action).unwrap();
+        assert_eq!(session.encounter, Encounter::Defeat);
+        let log = session.actions.iter().map(|a| a.kind).skip(2);
+        assert_eq!(
+            log.collect::<Vec<_>>(),
+            [
+                ActionKind::Begin,
+                ActionKind::Custom,
+                ActionKind::Die,
+                ActionKind::Defeat
+            ]
+        );
+        let action = doom(&session, "Tommy", "Tommy");
+        assert!(matches!(
+            session.apply(action),
+            Err(Error::Encounter(Encounter::Defeat))
+        ));
+        assert!(session.next_encounter(None).is_err());
+
+        let reloaded = deserialize::<Session, _>(&serialize(&session)).unwrap();
+        assert_eq!(reloaded.encounter, Encounter::Defeat);
+    }
+
+    #[test]
+    fn stunned_entity_loses_its_turn() {
+        let mut gilgamesh = Entity::new("Gilgamesh".to_string());
+        gilgamesh.effects.push(Effect::new(EffectKind::Stun, 0, 1));
+        let tommy = Entity::new("Tommy".to_string());
+        let mut session = Session::encounter("test".to_string(), vec![gilgamesh], vec![tommy], 0);
+
+        let fight = |session: &Session| {
+            let gilgamesh = session.entity("Gilgamesh").unwrap();
+            let tommy = session.entity("Tommy").unwrap();
+            Action::interact(ActionKind::Fight, gilgamesh, tommy).unwrap()
+        };
+
+        let outcome = session.apply(fight(&session)).unwrap();
+        assert!(outcome.stunned);
+        assert_eq!(session.opponents[0].health, 5);
+        assert!(session.party[0].effects.is_empty());
+
+        let outcome = session.apply(fight(&session)).unwrap();
+        assert!(!outcome.stunned);
+        assert!(outcome.damage > 0);
+    }
+
+    #[test]
+    fn entity_without_stats_gets_defaults() {
+        // An entity saved before stats existed: name, health and field_c
+        let bytes = [
+            7, 0, 16, 1, 0, 5, 102, 108, 111, 114, 112, 3, 0, 1, 9, 4, 0, 1, 0,
+        ];
+        let entity = deserialize::<Entity, _>(&bytes).unwrap();
+        assert_eq!(entity.health, 9);
+        assert_eq!(entity.stats.max_health, 9);
+        assert_eq!(entity.stats.level, 1);
+    }
+
     #[test]
     fn bool_round_trip() {
         let bool = Field::Bool(true);
@@ -232,4 +1397,56 @@ mod tests {
         assert_eq!(bytes, [4, 0, 1, 1]);
         assert_eq!(bool, deserialize(&bytes).unwrap());
     }
+
+    #[test]
+    fn the_grid_limits_reach_and_movement() {
+        let gilgamesh = Entity::new("Gilgamesh".to_string());
+        let tommy = Entity::new("Tommy".to_string());
+        let mut session = Session::encounter("test".to_string(), vec![gilgamesh], vec![tommy], 0);
+        session.set_grid(Grid::new(5, 1, vec![Pos::new(2, 0)]).unwrap());
+        let fight = |session: &mut Session| {
+            let verb = session.verbs.get("fight").unwrap();
+            let gilgamesh = session.entity("Gilgamesh").unwrap();
+            let action = Action::perform(verb, gilgamesh, session.entity("Tommy").unwrap())?;
+            session.apply(action)
+        };
+
+        let error = fight(&mut session).unwrap_err();
+        assert!(matches!(error, Error::OutOfRange(_, _, 4, 1)));
+        let error = session.walk("Gilgamesh", Pos::new(3, 0)).unwrap_err();
+        assert!(matches!(error, Error::CantReach(_, _)));
+
+        session.set_grid(Grid::new(5, 1, vec![]).unwrap());
+        let error = session.walk("Gilgamesh", Pos::new(3, 0)).unwrap_err();
+        assert!(matches!(error, Error::TooFar(_, _, 1)));
+        session.walk("Gilgamesh", Pos::new(1, 0)).unwrap();
+        session.walk("Gilgamesh", Pos::new(2, 0)).unwrap();
+        session.walk("Tommy", Pos::new(3, 0)).unwrap();
+        assert_eq!(session.encounter, Encounter::InProgress);
+        fight(&mut session).unwrap();
+        assert_eq!(session.actions.last().unwrap().kind, ActionKind::Fight);
+    }
+
+    #[test]
+    fn session_names_stay_in_their_directory() {
+        for bad in ["", "../../x", "a/b", "a\\b", "..", "a\nb"] {
+            assert!(super::check_name(bad).is_err(), "{bad:?}");
+            assert!(Session::load(bad).is_err(), "{bad:?}");
+        }
+        assert!(super::check_name("gil.gamesh").is_ok());
+    }
+
+    #[test]
+    fn seeds_change_only_before_anything_rolls() {
+        let gilgamesh = Entity::new("Gilgamesh".to_string());
+        let tommy = Entity::new("Tommy".to_string());
+        let mut session = Session::encounter("test".to_string(), vec![gilgamesh], vec![tommy], 0);
+        session.reseed(7).unwrap();
+        let verb = session.verbs.get("fight").unwrap();
+        let gilgamesh = session.entity("Gilgamesh").unwrap();
+        let action = Action::perform(verb, gilgamesh, session.entity("Tommy").unwrap()).unwrap();
+        session.apply(action).unwrap();
+        assert!(matches!(session.reseed(8), Err(Error::Seeded(_))));
+        assert_eq!(session.seed, 7);
+    }
 }
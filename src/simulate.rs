@@ -0,0 +1,300 @@
+use std::fmt::{self, Display, Formatter};
+use std::num::NonZeroUsize;
+use std::thread;
+
+use crate::actions::{Action, ActionKind};
+use crate::effects::{self, EffectKind};
+use crate::encounter::Encounter;
+use crate::error::{Error, Result};
+use crate::session::Session;
+use crate::strings::Rng;
+use crate::Entity;
+
+const MAX_ROUNDS: usize = 100;
+const TACTICS: [ActionKind; 2] = [ActionKind::Fight, ActionKind::ElectroCute];
+
+#[derive(Debug)]
+pub struct Simulation {
+    pub party: Vec<String>,
+    pub opponents: Vec<String>,
+    pub runs: usize,
+    pub seed: u64,
+    pub luck: i8,
+}
+
+#[derive(Debug, Clone, Default)]
+struct Survival {
+    name: String,
+    survived: usize,
+    // Number of battles that ended with the entity on `index` health
+    health: Vec<usize>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Report {
+    seed: u64,
+    runs: usize,
+    wins: usize,
+    draws: usize,
+    rounds: usize,
+    damage: [u64; ActionKind::ALL.len()],
+    survival: Vec<Survival>,
+}
+
+impl Report {
+    fn new(simulation: &Simulation) -> Self {
+        let survival = simulation
+            .party
+            .iter()
+            .chain(&simulation.opponents)
+            .map(|name| Survival {
+                name: name.clone(),
+                ..Default::default()
+            })
+            .collect();
+        Self {
+            seed: simulation.seed,
+            survival,
+            ..Default::default()
+        }
+    }
+
+    fn merge(&mut self, other: Report) {
+        self.runs += other.runs;
+        self.wins += other.wins;
+        self.draws += other.draws;
+        self.rounds += other.rounds;
+        for (total, damage) in self.damage.iter_mut().zip(other.damage) {
+            *total += damage;
+        }
+        for (total, survival) in self.survival.iter_mut().zip(other.survival) {
+            total.survived += survival.survived;
+            if total.health.len() < survival.health.len() {
+                total.health.resize(survival.health.len(), 0);
+            }
+            for (count, other) in total.health.iter_mut().zip(survival.health) {
+                *count += other;
+            }
+        }
+    }
+
+    fn record(&mut self, session: &Session) {
+        let entities = session.party.iter().chain(&session.opponents);
+        for (survival, entity) in self.survival.iter_mut().zip(entities) {
+            let health = entity.health as usize;
+            if survival.health.len() <= health {
+                survival.health.resize(health + 1, 0);
+            }
+            survival.health[health] += 1;
+            survival.survived += entity.is_alive() as usize;
+        }
+    }
+}
+
+fn percent(count: usize, total: usize) -> f64 {
+    count as f64 * 100.0 / total.max(1) as f64
+}
+
+impl Display for Report {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let runs = self.runs.max(1) as f64;
+        writeln!(f, "Simulated {} battles (seed {})", self.runs, self.seed)?;
+        writeln!(f, "=========")?;
+        writeln!(f, "Win rate:        {:.2}%", percent(self.wins, self.runs))?;
+        writeln!(f, "Draws:           {:.2}%", percent(self.draws, self.runs))?;
+        writeln!(f, "Average rounds:  {:.2}", self.rounds as f64 / runs)?;
+
+        writeln!(f, "\nDamage per battle:")?;
+        for kind in ActionKind::ALL {
+            let damage = self.damage[kind as usize];
+            if damage > 0 {
+                writeln!(
+                    f,
+                    "  {:<15}{:.2}",
+                    format!("{kind:?}"),
+                    damage as f64 / runs
+                )?;
+            }
+        }
+
+        writeln!(f, "\nSurvival:")?;
+        for survival in &self.survival {
+            write!(
+                f,
+                "  {:<15}{:>6.2}%  |",
+                survival.name,
+                percent(survival.survived, self.runs)
+            )?;
+            for (health, count) in survival.health.iter().enumerate() {
+                write!(f, " {health}♥ {:.1}%", percent(*count, self.runs))?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+impl Simulation {
+    pub fn run(&self) -> Result<Report> {
+        let mut names = self.party.iter().chain(&self.opponents).collect::<Vec<_>>();
+        let count = names.len();
+        names.sort();
+        names.dedup();
+        if self.party.is_empty() || self.opponents.is_empty() || names.len() != count {
+            return Err(Error::InvalidArgs("simulate "));
+        }
+
+        let spawn = |names: &[String]| {
+            names
+                .iter()
+                .map(|n| Entity::spawn(n))
+                .collect::<Result<Vec<_>>>()
+        };
+        let party = &spawn(&self.party)?;
+        let opponents = &spawn(&self.opponents)?;
+
+        let threads = thread::available_parallelism()
+            .map_or(1, NonZeroUsize::get)
+            .clamp(1, self.runs.max(1));
+        let chunk = self.runs.div_ceil(threads);
+
+        thread::scope(|scope| {
+            let handles = (0..threads)
+                .map(|thread| {
+                    let runs = thread * chunk..((thread + 1) * chunk).min(self.runs);
+                    scope.spawn(move || {
+                        let mut report = Report::new(self);
+                        for run in runs {
+                            // Every battle gets its own stream so the result
+                            // doesn't depend on how runs are split over threads
+                            self.battle(run as u64, party, opponents, &mut report)?;
+                        }
+                        Ok::<_, Error>(report)
+                    })
+                })
+                .collect::<Vec<_>>();
+
+            handles
+                .into_iter()
+                .try_fold(Report::new(self), |mut total, handle| {
+                    total.merge(handle.join().expect("simulation thread panicked")?);
+                    Ok(total)
+                })
+        })
+    }
+
+    fn battle(
+        &self,
+        run: u64,
+        party: &[Entity],
+        opponents: &[Entity],
+        report: &mut Report,
+    ) -> Result<()> {
+        let mut rng = Rng::stream(self.seed, run);
+        let seed = rng.next_u64();
+        let mut session = Session::encounter(
+            "simulation".to_string(),
+            party.to_vec(),
+            opponents.to_vec(),
+            seed,
+        );
+        session.luck = self.luck;
+
+        let mut rounds = 0;
+        let winner = loop {
+            match session.encounter {
+                Encounter::Victory => break Some(true),
+                Encounter::Defeat => break Some(false),
+                _ => {}
+            }
+            if rounds == MAX_ROUNDS {
+                break None;
+            }
+            rounds += 1;
+
+            let mut turns = session
+                .party
+                .iter()
+                .map(|e| (e.name.clone(), e.stats.speed, true))
+                .chain(
+                    session
+                        .opponents
+                        .iter()
+                        .map(|e| (e.name.clone(), e.stats.speed, false)),
+                )
+                .collect::<Vec<_>>();
+            // Fastest first, the party wins ties
+            turns.sort_by_key(|(_, speed, _)| std::cmp::Reverse(*speed));
+
+            for (name, _, in_party) in turns {
+                let entity = session.entity(&name).ok_or(Error::NoEntity(name))?;
+                if !entity.is_alive() {
+                    continue;
+                }
+                let enemies = match in_party {
+                    true => &session.opponents,
+                    false => &session.party,
+                };
+                let targets = enemies.iter().filter(|e| e.is_alive()).collect::<Vec<_>>();
+                if targets.is_empty() {
+                    break;
+                }
+                let mut target = targets[rng.below(targets.len() as u64) as usize];
+                let mut kind = TACTICS[rng.below(TACTICS.len() as u64) as usize];
+                // Charmed entities won't hurt anyone, so they keep their guard up instead
+                if effects::has(&entity.effects, EffectKind::Charm) {
+                    (kind, target) = (ActionKind::Neutral, entity);
+                }
+
+                let mut action = Action::interact(kind, entity, target)?;
+                // Out of mana or still cooling down, so a plain hit it is
+                if session.ready(&action).is_err() {
+                    kind = ActionKind::Fight;
+                    action = Action::interact(kind, entity, target)?;
+                }
+                let outcome = session.apply(action)?;
+                report.damage[kind as usize] += outcome.damage as u64;
+            }
+        };
+
+        report.runs += 1;
+        report.rounds += rounds;
+        match winner {
+            Some(true) => report.wins += 1,
+            Some(false) => {}
+            None => report.draws += 1,
+        }
+        report.record(&session);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Simulation;
+
+    fn simulation(seed: u64) -> Simulation {
+        Simulation {
+            party: vec!["Gilgamesh".to_string(), "Enkidu".to_string()],
+            opponents: vec!["Tommy".to_string()],
+            runs: 200,
+            seed,
+            luck: 0,
+        }
+    }
+
+    #[test]
+    fn same_seed_same_report() {
+        let a = simulation(2112).run().unwrap();
+        let b = simulation(2112).run().unwrap();
+        assert_eq!(a.to_string(), b.to_string());
+        assert_eq!(a.runs, 200);
+    }
+
+    #[test]
+    fn duplicate_names_are_rejected() {
+        let mut simulation = simulation(0);
+        simulation.opponents.push("Gilgamesh".to_string());
+        assert!(simulation.run().is_err());
+    }
+}
@@ -0,0 +1,553 @@
+use std::env;
+use std::fs;
+use std::io::ErrorKind;
+use std::net::{SocketAddr, TcpStream};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::actions::Action;
+use crate::campaign::Campaign;
+use crate::chat::Chat;
+use crate::error::{Error, Result};
+use crate::levels::Levels;
+use crate::lobby::Table;
+use crate::players::Credentials;
+use crate::protocol::{self, Change, Frames, Message, VERSION};
+use crate::session::{self, Session};
+use crate::verbs::Verbs;
+
+pub const REMOTE_VAR: &str = "RELAY_CODE_REMOTE";
+// How often a local session file is checked for changes
+const POLL: Duration = Duration::from_millis(250);
+// After a drop, a relay is tried again this many times, waiting a little
+// longer each time
+const RETRIES: u32 = 5;
+const RETRY: Duration = Duration::from_millis(500);
+
+// Wherever sessions live, sessions/ or a relay somewhere else
+pub trait SessionStore {
+    fn load(&mut self, name: &str) -> Result<Session>;
+    // Like save, but for a session that's meant to be new
+    fn create(&mut self, session: &Session) -> Result<()>;
+    fn save(&mut self, session: &Session) -> Result<()>;
+    // Applies the action where the session lives, and brings the session
+    // up to date with however that went
+    fn submit(&mut self, session: &mut Session, action: Action) -> Result<()>;
+    // Like submit, for whatever isn't an action
+    fn change(&mut self, session: &mut Session, change: Change) -> Result<()>;
+    fn list(&mut self) -> Result<Vec<String>>;
+    // Blocks until someone else changes the session, then catches up
+    fn wait(&mut self, session: &mut Session) -> Result<()>;
+    // Like wait, but gives up after `timeout`, saying whether anything changed
+    fn poll(&mut self, session: &mut Session, timeout: Duration) -> Result<bool>;
+    // Adds to the chat, which a relay sends on to everyone in the session
+    fn say(&mut self, session: &mut Session, chat: Chat) -> Result<()>;
+    // Whether other players act on the sessions too, so there's no taking
+    // anything back
+    fn shared(&self) -> bool;
+}
+
+// The relay from --remote, or the environment, or none for sessions/
+fn relay(remote: Option<SocketAddr>) -> Result<Option<SocketAddr>> {
+    match remote {
+        Some(addr) => Ok(Some(addr)),
+        None => match env::var(REMOTE_VAR) {
+            Ok(addr) => Ok(Some(addr.parse().map_err(|_| Error::InvalidArgs(""))?)),
+            Err(_) => Ok(None),
+        },
+    }
+}
+
+pub fn open(remote: Option<SocketAddr>) -> Result<Box<dyn SessionStore>> {
+    Ok(match relay(remote)? {
+        Some(addr) => Box::new(Remote::connect(addr, &Credentials::load()?)?),
+        None => Box::new(Local),
+    })
+}
+
+// For what only a relay does, like the lobby
+pub fn open_remote(remote: Option<SocketAddr>) -> Result<Remote> {
+    let addr = relay(remote)?.ok_or(Error::NoRelay)?;
+    Remote::connect(addr, &Credentials::load()?)
+}
+
+pub struct Local;
+
+impl SessionStore for Local {
+    fn load(&mut self, name: &str) -> Result<Session> {
+        Session::load(name)
+    }
+
+    // `new` has always started over on top of an old session here
+    fn create(&mut self, session: &Session) -> Result<()> {
+        session.save()
+    }
+
+    fn save(&mut self, session: &Session) -> Result<()> {
+        session.save()
+    }
+
+    // A script going wrong halfway through leaves the session as it was
+    fn submit(&mut self, session: &mut Session, action: Action) -> Result<()> {
+        let mut next = session.clone();
+        next.apply(action)?;
+        next.save()?;
+        *session = next;
+        Ok(())
+    }
+
+    fn change(&mut self, session: &mut Session, change: Change) -> Result<()> {
+        let mut next = session.clone();
+        next.change(change)?;
+        next.save()?;
+        *session = next;
+        Ok(())
+    }
+
+    fn list(&mut self) -> Result<Vec<String>> {
+        let entries = match fs::read_dir(session::DIRNAME) {
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(vec![]),
+            entries => entries?,
+        };
+        let mut names = vec![];
+        for entry in entries {
+            let entry = entry?;
+            let file_name = entry.file_name();
+            let Some(name) = file_name
+                .to_str()
+                .and_then(|f| f.strip_suffix(session::SUFFIX))
+            else {
+                continue;
+            };
+            // Loading a name that isn't there leaves an empty file behind
+            if entry.metadata()?.len() > 0 || Campaign::exists(name) {
+                names.push(name.to_string());
+            }
+        }
+        names.sort();
+        Ok(names)
+    }
+
+    // Nothing says when the file changes, so it's read until it has. Caught
+    // halfway through a save it won't load, and is read again
+    fn wait(&mut self, session: &mut Session) -> Result<()> {
+        while !self.poll(session, POLL)? {}
+        Ok(())
+    }
+
+    fn poll(&mut self, session: &mut Session, timeout: Duration) -> Result<bool> {
+        let until = Instant::now() + timeout;
+        loop {
+            match Session::load(session.name()) {
+                Ok(latest) if latest != *session => {
+                    *session = latest;
+                    return Ok(true);
+                }
+                _ => {}
+            }
+            let left = until.saturating_duration_since(Instant::now());
+            if left.is_zero() {
+                return Ok(false);
+            }
+            thread::sleep(POLL.min(left));
+        }
+    }
+
+    fn say(&mut self, session: &mut Session, chat: Chat) -> Result<()> {
+        session.chat.push(chat);
+        session.save()
+    }
+
+    fn shared(&self) -> bool {
+        false
+    }
+}
+
+// Talks to a relay, which owns the sessions and decides what happened
+pub struct Remote {
+    addr: SocketAddr,
+    credentials: Credentials,
+    stream: TcpStream,
+    frames: Frames,
+    // The relay sends what happens to this one as it happens
+    joined: Option<String>,
+}
+
+impl Remote {
+    pub fn connect(addr: SocketAddr, credentials: &Credentials) -> Result<Self> {
+        let mut remote = Self {
+            addr,
+            credentials: credentials.clone(),
+            stream: TcpStream::connect(addr)?,
+            frames: Frames::default(),
+            joined: None,
+        };
+        let (name, token) = (credentials.name.clone(), credentials.token.clone());
+        remote.send(&Message::Hello(VERSION, name, token))?;
+        match remote.receive()? {
+            Message::Hello(VERSION, _, _) => Ok(remote),
+            Message::Hello(version, _, _) => Err(Error::Version(VERSION, version)),
+            _ => Err(Error::InvalidFieldType),
+        }
+    }
+
+    fn send(&mut self, message: &Message) -> Result<()> {
+        protocol::send(&mut self.stream, message)
+    }
+
+    fn receive(&mut self) -> Result<Message> {
+        match protocol::receive(&mut self.stream, &mut self.frames)? {
+            Message::Error(message) => Err(Error::Remote(message)),
+            message => Ok(message),
+        }
+    }
+
+    fn reconnect(&mut self) -> Result<()> {
+        let mut attempt = 0;
+        loop {
+            match Self::connect(self.addr, &self.credentials) {
+                Ok(remote) => {
+                    *self = remote;
+                    return Ok(());
+                }
+                Err(Error::Io(_)) if attempt < RETRIES => {
+                    attempt += 1;
+                    thread::sleep(RETRY * attempt);
+                }
+                Err(err) => return Err(err),
+            }
+        }
+    }
+
+    // Connects again after a drop and catches up from the last action seen
+    pub fn resync(&mut self, session: &mut Session) -> Result<()> {
+        self.reconnect()?;
+        let name = session.name().to_string();
+        let chat = session.chat.len() as u64;
+        self.send(&Message::Resync(name.clone(), session.seq(), chat))?;
+        let message = self.receive()?;
+        self.joined = Some(name);
+        self.catch_up(session, message)
+    }
+
+    fn catch_up(&mut self, session: &mut Session, message: Message) -> Result<()> {
+        match message {
+            Message::Snapshot(mut snapshot) => {
+                snapshot.levels = session.levels.clone();
+                snapshot.verbs = session.verbs.clone();
+                *session = snapshot;
+            }
+            Message::StateDelta(delta) => {
+                if session.merge(delta).is_err() {
+                    *session = self.load(session.name())?;
+                }
+            }
+            Message::Chat(name, chat) if name == session.name() => session.chat.push(chat),
+            // Still going on somewhere it used to be joined
+            Message::Chat(_, _) => {}
+            _ => return Err(Error::InvalidFieldType),
+        }
+        Ok(())
+    }
+
+    // The answer to what was just sent, taking in whatever chat comes first
+    fn reply(&mut self, session: &mut Session) -> Result<Message> {
+        loop {
+            match self.receive()? {
+                message @ Message::Chat(_, _) => self.catch_up(session, message)?,
+                message => return Ok(message),
+            }
+        }
+    }
+
+    pub fn lobby(&mut self) -> Result<Vec<Table>> {
+        self.send(&Message::Lobby)?;
+        match self.receive()? {
+            Message::Tables(tables) => Ok(tables),
+            _ => Err(Error::InvalidFieldType),
+        }
+    }
+
+    pub fn host(&mut self, name: &str, slots: u8) -> Result<Session> {
+        self.send(&Message::Host(name.to_string(), slots))?;
+        self.snapshot()
+    }
+
+    // Takes a seat as the entity, and follows the session from then on
+    pub fn join(&mut self, name: &str, entity: &str) -> Result<Session> {
+        let claim = Some(entity.to_string());
+        self.send(&Message::JoinSession(name.to_string(), claim))?;
+        let session = self.snapshot()?;
+        self.joined = Some(name.to_string());
+        Ok(session)
+    }
+
+    pub fn start(&mut self, name: &str) -> Result<Session> {
+        self.send(&Message::Start(name.to_string()))?;
+        self.snapshot()
+    }
+
+    // Sends an action or a change, and brings the session up to date with
+    // what the relay made of it. If the connection drops on the way the
+    // session is still caught up, so whoever sent it can see whether it got in
+    fn update(&mut self, session: &mut Session, message: &Message) -> Result<()> {
+        let name = session.name().to_string();
+        let sent = self.send(message);
+        let delta = match sent.and_then(|_| self.reply(session)) {
+            Ok(Message::StateDelta(delta)) => delta,
+            Ok(_) => return Err(Error::InvalidFieldType),
+            Err(err @ Error::Io(_)) => {
+                self.resync(session)?;
+                return Err(err);
+            }
+            Err(err) => return Err(err),
+        };
+        // Someone else got something in first, start over from what's there
+        if session.merge(delta).is_err() {
+            *session = self.load(&name)?;
+        }
+        Ok(())
+    }
+
+    // The relay doesn't send levels.txt or actions.txt, this side has its own
+    fn snapshot(&mut self) -> Result<Session> {
+        let Message::Snapshot(mut session) = self.receive()? else {
+            return Err(Error::InvalidFieldType);
+        };
+        session.levels = Levels::load()?;
+        session.verbs = Verbs::load()?;
+        Ok(session)
+    }
+}
+
+impl SessionStore for Remote {
+    fn load(&mut self, name: &str) -> Result<Session> {
+        self.send(&Message::JoinSession(name.to_string(), None))?;
+        let session = self.snapshot()?;
+        self.joined = Some(name.to_string());
+        Ok(session)
+    }
+
+    fn create(&mut self, session: &Session) -> Result<()> {
+        self.send(&Message::CreateSession(session.clone()))?;
+        self.snapshot().map(drop)
+    }
+
+    fn save(&mut self, session: &Session) -> Result<()> {
+        self.send(&Message::SaveSession(session.clone()))?;
+        self.snapshot().map(drop)
+    }
+
+    fn submit(&mut self, session: &mut Session, action: Action) -> Result<()> {
+        let name = session.name().to_string();
+        self.update(session, &Message::SubmitAction(name, action))
+    }
+
+    fn change(&mut self, session: &mut Session, change: Change) -> Result<()> {
+        let name = session.name().to_string();
+        self.update(session, &Message::Change(name, change))
+    }
+
+    fn list(&mut self) -> Result<Vec<String>> {
+        self.send(&Message::ListSessions)?;
+        match self.receive()? {
+            Message::Sessions(names) => Ok(names),
+            _ => Err(Error::InvalidFieldType),
+        }
+    }
+
+    fn wait(&mut self, session: &mut Session) -> Result<()> {
+        if self.joined.as_deref() != Some(session.name()) {
+            *session = self.load(session.name())?;
+        }
+        match self.receive() {
+            Err(Error::Io(_)) => self.resync(session),
+            message => self.catch_up(session, message?),
+        }
+    }
+
+    // Half a message in when time runs out is kept for next time
+    fn poll(&mut self, session: &mut Session, timeout: Duration) -> Result<bool> {
+        if self.joined.as_deref() != Some(session.name()) {
+            *session = self.load(session.name())?;
+            return Ok(true);
+        }
+        // A zero timeout would mean no timeout at all
+        let timeout = timeout.max(Duration::from_millis(1));
+        self.stream.set_read_timeout(Some(timeout))?;
+        let message = self.receive();
+        self.stream.set_read_timeout(None)?;
+        match message {
+            Err(Error::Io(err))
+                if matches!(err.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) =>
+            {
+                Ok(false)
+            }
+            Err(Error::Io(_)) => self.resync(session).map(|_| true),
+            message => self.catch_up(session, message?).map(|_| true),
+        }
+    }
+
+    // Comes back as chat from whoever the relay knows this side as
+    fn say(&mut self, session: &mut Session, chat: Chat) -> Result<()> {
+        if self.joined.as_deref() != Some(session.name()) {
+            *session = self.load(session.name())?;
+        }
+        let name = session.name().to_string();
+        self.send(&Message::Say(name.clone(), chat.text))?;
+        loop {
+            match self.receive()? {
+                Message::Chat(to, chat) if to == name && chat.from == self.credentials.name => {
+                    session.chat.push(chat);
+                    return Ok(());
+                }
+                message => self.catch_up(session, message)?,
+            }
+        }
+    }
+
+    fn shared(&self) -> bool {
+        true
+    }
+}
+
+// For a relay that shouldn't touch the disk
+#[cfg(test)]
+#[derive(Default)]
+pub struct Memory(pub std::collections::HashMap<String, Session>);
+
+#[cfg(test)]
+impl SessionStore for Memory {
+    fn load(&mut self, name: &str) -> Result<Session> {
+        self.0.get(name).cloned().ok_or(Error::NoSession)
+    }
+
+    fn create(&mut self, session: &Session) -> Result<()> {
+        self.save(session)
+    }
+
+    fn save(&mut self, session: &Session) -> Result<()> {
+        self.0.insert(session.name().to_string(), session.clone());
+        Ok(())
+    }
+
+    fn submit(&mut self, session: &mut Session, action: Action) -> Result<()> {
+        let mut next = session.clone();
+        next.apply(action)?;
+        *session = next;
+        self.save(session)
+    }
+
+    fn change(&mut self, session: &mut Session, change: Change) -> Result<()> {
+        let mut next = session.clone();
+        next.change(change)?;
+        *session = next;
+        self.save(session)
+    }
+
+    fn list(&mut self) -> Result<Vec<String>> {
+        let mut names = self.0.keys().cloned().collect::<Vec<_>>();
+        names.sort();
+        Ok(names)
+    }
+
+    // Nothing else can change it
+    fn wait(&mut self, _: &mut Session) -> Result<()> {
+        Ok(())
+    }
+
+    fn poll(&mut self, _: &mut Session, _: Duration) -> Result<bool> {
+        Ok(false)
+    }
+
+    fn say(&mut self, session: &mut Session, chat: Chat) -> Result<()> {
+        session.chat.push(chat);
+        self.save(session)
+    }
+
+    fn shared(&self) -> bool {
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::{Shutdown, TcpListener};
+    use std::thread;
+    use std::time::Duration;
+
+    use super::{Memory, Remote, SessionStore};
+    use crate::actions::Action;
+    use crate::players::{Credentials, Players};
+    use crate::relay::{self, Relay};
+    use crate::session::Session;
+    use crate::Entity;
+
+    #[test]
+    fn remote_sessions_behave_like_local_ones() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        thread::spawn(move || {
+            relay::run(
+                listener,
+                Relay::new(Box::new(Memory::default()), Players::default()),
+            )
+        });
+
+        let mut remote = Remote::connect(addr, &Credentials::new("alice".to_string())).unwrap();
+        assert!(remote.load("test").is_err());
+        let gilgamesh = Entity::new("Gilgamesh".to_string());
+        let tommy = Entity::new("Tommy".to_string());
+        let session = Session::encounter("test".to_string(), vec![gilgamesh], vec![tommy], 0);
+        remote.create(&session).unwrap();
+        assert!(remote.create(&session).is_err());
+        assert_eq!(remote.list().unwrap(), ["test"]);
+
+        let mut session = remote.load("test").unwrap();
+        let verb = session.verbs.get("fight").unwrap();
+        let gilgamesh = session.entity("Gilgamesh").unwrap();
+        let action = Action::perform(verb, gilgamesh, session.entity("Tommy").unwrap()).unwrap();
+        let mut expected = session.clone();
+        expected.apply(action.clone()).unwrap();
+        remote.submit(&mut session, action).unwrap();
+        // Both stamp the time, maybe a millisecond apart
+        let log = |s: &Session| {
+            s.actions()
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(log(&session), log(&expected));
+        assert_eq!(session.opponents, expected.opponents);
+
+        // Someone else sees it too, and what happens next
+        let mut other = Remote::connect(addr, &Credentials::new("bob".to_string())).unwrap();
+        let mut watched = other.load("test").unwrap();
+        assert_eq!(log(&watched), log(&expected));
+        let verb = session.verbs.get("fight").unwrap();
+        let gilgamesh = session.entity("Gilgamesh").unwrap();
+        let action = Action::perform(verb, gilgamesh, session.entity("Tommy").unwrap()).unwrap();
+        remote.submit(&mut session, action).unwrap();
+        other.wait(&mut watched).unwrap();
+        assert_eq!(watched.actions(), session.actions());
+
+        // Or looks in now and then without waiting around
+        let now_and_then = Duration::from_millis(50);
+        assert!(!other.poll(&mut watched, now_and_then).unwrap());
+        let verb = session.verbs.get("fight").unwrap();
+        let gilgamesh = session.entity("Gilgamesh").unwrap();
+        let action = Action::perform(verb, gilgamesh, session.entity("Tommy").unwrap()).unwrap();
+        remote.submit(&mut session, action).unwrap();
+        assert!(other.poll(&mut watched, Duration::from_secs(5)).unwrap());
+        assert_eq!(watched.actions(), session.actions());
+
+        // Dropped while something happens, and caught up on what was missed
+        other.stream.shutdown(Shutdown::Both).unwrap();
+        let verb = session.verbs.get("fight").unwrap();
+        let gilgamesh = session.entity("Gilgamesh").unwrap();
+        let action = Action::perform(verb, gilgamesh, session.entity("Tommy").unwrap()).unwrap();
+        remote.submit(&mut session, action).unwrap();
+        other.wait(&mut watched).unwrap();
+        assert_eq!(watched.actions(), session.actions());
+    }
+}
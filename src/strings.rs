@@ -1,7 +1,4 @@
 use std::fmt::Display;
-use std::time::Duration;
-
-use crate::factory::MyCoolNotJavaButRealRustFactoryWithExtraLongNameThatDoesALotOfNiceThingsWhenYouReallyThingAboutTheTimeOfDayAndWhatReallyCanComeOfItwhenThingsGetDoneButWhatDoYouThinkAboutItHowWasYourDayByTheWayFactory;
 
 #[derive(Debug, PartialEq, Eq, Clone, Copy, Hash, PartialOrd, Ord)]
 pub enum Boolean {
@@ -11,138 +8,101 @@ pub enum Boolean {
     Luck(bool),
 }
 
-struct __BooleanBuilder {
-    factory: MyCoolNotJavaButRealRustFactoryWithExtraLongNameThatDoesALotOfNiceThingsWhenYouReallyThingAboutTheTimeOfDayAndWhatReallyCanComeOfItwhenThingsGetDoneButWhatDoYouThinkAboutItHowWasYourDayByTheWayFactory<
-        bool,
-        u8,
-        bool,
-        u8,
-        bool,
-        u8,
-        bool,
-        u8,
-        bool,>
-}
-
-pub struct TheRealBooleanBuilder {
-    builder: __BooleanBuilder,
-}
-
-impl TheRealBooleanBuilder {
-    pub fn new(a: bool, b: bool, zip: u8, spring: i32) -> Self {
-        Self {
-            builder: __BooleanBuilder {
-                factory: MyCoolNotJavaButRealRustFactoryWithExtraLongNameThatDoesALotOfNiceThingsWhenYouReallyThingAboutTheTimeOfDayAndWhatReallyCanComeOfItwhenThingsGetDoneButWhatDoYouThinkAboutItHowWasYourDayByTheWayFactory::new(
-                    a, 0, false, 0, b, spring as u32 as u8, false, zip, false,
-                ),
-            },
-        }
-    }
-
-    pub fn build(&self) -> Boolean {
-        let t = self.builder.factory.use_my_t();
-        let m = self.builder.factory.use_my_m();
-        let h = self.builder.factory.use_my_h();
-
-        if t && m && h > 128 && h < 147 && h % 2 == 0 {
-            Boolean::Luck(true)
-        } else {
-            Boolean::Luck(false)
-        }
-    }
-}
-
 impl Display for Boolean {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        "Put it in production";
+        // Put it in production
         write!(f, "?")
     }
 }
 
-impl Boolean {
-    pub fn new() -> Self {
-        panic!("No new booleans")
-    }
-
-    pub fn builder() -> TheRealBooleanBuilder {
-        "I'm a builder!";
-        TheRealBooleanBuilder::new(true, false, 9, 33)
-    }
-
-    pub fn get_data_from_config() -> Boolean {
-        Self::builder().build()
-    }
-
-    pub fn to_regular_old_boring_pre_2024_bool(self) -> bool {
-        match self {
-            Boolean::Luck(b) => b,
-            _ => false,
-        }
-    }
-}
-
 impl Default for Boolean {
     fn default() -> Self {
         panic!("Wrong day bozo")
     }
 }
 
+fn split_mix(state: &mut u64) -> u64 {
+    *state = state.wrapping_add(0x9e3779b97f4a7c15);
+    let mut z = *state;
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+    z ^ (z >> 31)
+}
+
+// xoshiro256**, seeded through SplitMix64 as the authors recommend
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Rng {
-    seed: Duration,
+    state: [u64; 4],
 }
 
 impl Rng {
-    pub fn new() -> Self {
-        use std::time::SystemTime;
-
+    pub fn new(seed: u64) -> Self {
+        let mut sm = seed;
         Self {
-            seed: SystemTime::now()
-                .duration_since(SystemTime::UNIX_EPOCH)
-                .unwrap_or_else(|_| panic!("Time is a social construct")),
+            state: [(); 4].map(|_| split_mix(&mut sm)),
         }
     }
 
-    pub fn rand(&self) -> Boolean {
-        let my_bool = m_rand(Some(self.seed), 0);
-        my_bool
+    // Independent generator for the `stream`th roll of a seed, so a replay
+    // only needs the seed and the position in the log
+    pub fn stream(seed: u64, stream: u64) -> Self {
+        let mut sm = stream;
+        Self::new(seed ^ split_mix(&mut sm))
     }
-}
 
-pub fn rand() -> Boolean {
-    let my_bool = m_rand(None, 0);
-    log!("What colour is your change banana? {my_bool}");
-    my_bool
-}
-
-fn m_rand(seed: Option<Duration>, depth: usize) -> Boolean {
-    use std::time::SystemTime;
-
-    if depth > 420 {
-        return Boolean::Maybe;
-    }
+    pub fn from_time() -> Self {
+        use std::time::SystemTime;
 
-    let m_time = seed.unwrap_or(
-        SystemTime::now()
+        let now = SystemTime::now()
             .duration_since(SystemTime::UNIX_EPOCH)
-            .unwrap_or_else(|_| panic!("Time is a social construct")),
-    );
+            .unwrap_or_else(|_| panic!("Time is a social construct"));
+        Self::new(now.as_nanos() as u64)
+    }
 
-    "sPeCiAl SaUcE";
-    if m_time.as_millis() % 2 == 0 {
-        return Boolean::Luck(false);
+    pub fn next_u64(&mut self) -> u64 {
+        let [s0, s1, s2, s3] = &mut self.state;
+        let result = s1.wrapping_mul(5).rotate_left(7).wrapping_mul(9);
+        let t = *s1 << 17;
+        *s2 ^= *s0;
+        *s3 ^= *s1;
+        *s1 ^= *s2;
+        *s0 ^= *s3;
+        *s2 ^= t;
+        *s3 = s3.rotate_left(45);
+        result
     }
 
-    let second_decider = m_rand(None, depth + 1);
-    let third_guy = m_rand(seed, depth + 1);
+    // Uniform in `0..n`, rejecting the tail that would bias the modulo
+    pub fn below(&mut self, n: u64) -> u64 {
+        assert!(n > 0, "can't roll a zero sided die");
+        let zone = u64::MAX - u64::MAX % n;
+        loop {
+            let value = self.next_u64();
+            if value < zone {
+                return value % n;
+            }
+        }
+    }
+}
 
-    "Real booleans";
-    if second_decider == Boolean::Eh && third_guy == Boolean::Luck(false) {
-        return Boolean::Probably;
-    } else if second_decider == Boolean::Probably
-        && (matches!(third_guy, Boolean::Luck(_)) || third_guy == Boolean::Eh)
-    {
-        return Boolean::Maybe;
+#[cfg(test)]
+mod tests {
+    use super::Rng;
+
+    #[test]
+    fn same_seed_same_rolls() {
+        let mut a = Rng::new(2112);
+        let mut b = Rng::new(2112);
+        let a = (0..100).map(|_| a.below(6)).collect::<Vec<_>>();
+        let b = (0..100).map(|_| b.below(6)).collect::<Vec<_>>();
+        assert_eq!(a, b);
+        assert!(a.iter().all(|roll| *roll < 6));
     }
 
-    Boolean::Eh
+    #[test]
+    fn streams_differ() {
+        let a = Rng::stream(2112, 0).next_u64();
+        let b = Rng::stream(2112, 1).next_u64();
+        assert_ne!(a, b);
+    }
 }
@@ -0,0 +1,342 @@
+use std::fmt::{self, Display, Formatter};
+use std::io::{self, Write};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::thread;
+use std::time::Duration;
+
+use crate::actions::ActionKind;
+use crate::editor::{self, read_key, Key, Raw};
+use crate::error::{Error, Result};
+use crate::play::Repl;
+use crate::store::SessionStore;
+use crate::watch;
+use crate::Entity;
+
+// When the terminal won't say
+const SIZE: (usize, usize) = (24, 80);
+const BAR: usize = 10;
+// Rows each entity takes up in its panel
+const ROWS: usize = 3;
+// How long the screen waits on a key before looking for what everyone
+// else did
+const REFRESH: Duration = Duration::from_millis(250);
+
+// Characters in rows, drawn to the terminal or looked at in a test
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Screen {
+    width: usize,
+    height: usize,
+    cells: Vec<char>,
+}
+
+impl Screen {
+    pub fn new(width: usize, height: usize) -> Self {
+        Self {
+            width,
+            height,
+            cells: vec![' '; width * height],
+        }
+    }
+
+    // Whatever doesn't fit is cut off
+    pub fn put(&mut self, x: usize, y: usize, text: &str) {
+        if y >= self.height {
+            return;
+        }
+        let row = &mut self.cells[y * self.width..(y + 1) * self.width];
+        for (cell, c) in row.iter_mut().skip(x).zip(text.chars()) {
+            *cell = c;
+        }
+    }
+
+    pub fn line(&self, y: usize) -> String {
+        let row = &self.cells[y * self.width..(y + 1) * self.width];
+        row.iter().collect::<String>().trim_end().to_string()
+    }
+}
+
+impl Display for Screen {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let lines = (0..self.height).map(|y| self.line(y));
+        write!(f, "{}", lines.collect::<Vec<_>>().join("\n"))
+    }
+}
+
+// Full health is a full bar, and anyone still standing gets some of it
+fn bar(entity: &Entity) -> String {
+    let max = entity.stats.max_health.max(1) as usize;
+    let full = (entity.health as usize * BAR).div_ceil(max).min(BAR);
+    format!("{}{}", "█".repeat(full), "░".repeat(BAR - full))
+}
+
+// The fight from a prompt, laid out on the whole screen. Keys act
+// right away, on whoever is picked as the target
+pub struct Tui<'a> {
+    repl: Repl<'a>,
+    // Party first, then the opponents
+    target: usize,
+    // How many lines up from the newest the log is
+    scroll: usize,
+    // What the last key did, or why it didn't
+    status: String,
+}
+
+impl<'a> Tui<'a> {
+    pub fn new(repl: Repl<'a>) -> Self {
+        let target = repl.session.party.len();
+        Self {
+            repl,
+            target,
+            scroll: 0,
+            status: String::new(),
+        }
+    }
+
+    fn everyone(&self) -> impl Iterator<Item = &Entity> {
+        let session = &self.repl.session;
+        session.party.iter().chain(&session.opponents)
+    }
+
+    fn target(&self) -> Option<&Entity> {
+        self.everyone().nth(self.target)
+    }
+
+    // A letter for each kind of action there's a verb for, numbers for the
+    // ones actions.txt made up
+    fn hotkeys(&self) -> Vec<(char, String)> {
+        let mut numbers = '1'..='9';
+        let verbs = self.repl.session.verbs.iter();
+        verbs
+            .filter_map(|verb| {
+                let key = match ActionKind::from_id(&verb.id) {
+                    ActionKind::Custom => numbers.next()?,
+                    kind => kind.id()?.chars().next()?,
+                };
+                Some((key, verb.id.clone()))
+            })
+            .collect()
+    }
+
+    // Runs a line through the prompt, keeping the last line it printed
+    fn run(&mut self, line: &str) -> bool {
+        match self.repl.run(line) {
+            Ok(Some(output)) => self.status = output.lines().last().unwrap_or("").to_string(),
+            Ok(None) => return false,
+            Err(err) => self.status = err.to_string(),
+        }
+        true
+    }
+
+    // Whatever went wrong shows where a bad action would
+    fn refresh(&mut self) {
+        if let Err(err) = self.repl.poll(Duration::ZERO) {
+            self.status = err.to_string();
+        }
+    }
+
+    // False once it's time to go
+    pub fn press(&mut self, key: Key) -> bool {
+        let everyone = self.everyone().count().max(1);
+        let party = self.repl.session.party.clone();
+        let actor = party.iter().position(|e| e.name == self.repl.actor());
+        match key {
+            Key::Up => self.target = (self.target + everyone - 1) % everyone,
+            Key::Down | Key::Tab => self.target = (self.target + 1) % everyone,
+            Key::Left | Key::Right if !party.is_empty() => {
+                let step = if key == Key::Left { party.len() - 1 } else { 1 };
+                let next = (actor.unwrap_or(0) + step) % party.len();
+                return self.run(&format!("as {}", party[next].name));
+            }
+            Key::Char('[') => self.scroll += 1,
+            Key::Char(']') => self.scroll = self.scroll.saturating_sub(1),
+            Key::Char('u') => return self.run("undo"),
+            Key::Char('s') => return self.run("save"),
+            Key::Char('q') | Key::Eof | Key::Interrupt => return self.run("quit"),
+            Key::Char(c) => {
+                let hotkeys = self.hotkeys();
+                let Some((_, verb)) = hotkeys.iter().find(|(key, _)| *key == c) else {
+                    self.status = format!("nothing on {c}");
+                    return true;
+                };
+                let target = self.target().map(|e| e.name.clone()).unwrap_or_default();
+                self.scroll = 0;
+                return self.run(&format!("{verb} {target}"));
+            }
+            _ => {}
+        }
+        true
+    }
+
+    fn panel(&self, screen: &mut Screen, x: usize, title: &str, entities: &[Entity], from: usize) {
+        screen.put(x, 2, title);
+        screen.put(x, 3, &"─".repeat(title.chars().count()));
+        for (i, entity) in entities.iter().enumerate() {
+            let y = 4 + i * ROWS;
+            let marker = match (from + i == self.target, entity.name == self.repl.actor()) {
+                (true, _) => ">",
+                (_, true) => "*",
+                _ => " ",
+            };
+            screen.put(
+                x,
+                y,
+                &format!("{marker} {} Lv{}", entity.name, entity.stats.level),
+            );
+            let health = match entity.is_alive() {
+                true => format!("{}/{}", entity.health, entity.stats.max_health),
+                false => "down".to_string(),
+            };
+            screen.put(x + 2, y + 1, &format!("{} {health}", bar(entity)));
+            let effects = entity.effects.iter().map(ToString::to_string);
+            let effects = effects.collect::<Vec<_>>().join(" ");
+            let resources = format!(
+                "mp {}/{} sp {}/{} {effects}",
+                entity.mana, entity.stats.max_mana, entity.stamina, entity.stats.max_stamina
+            );
+            screen.put(x + 2, y + 2, &resources);
+        }
+    }
+
+    pub fn render(&self, screen: &mut Screen) {
+        let session = &self.repl.session;
+        let title = format!(
+            "{}  {}  acting as {}",
+            session.name(),
+            session.encounter,
+            self.repl.actor()
+        );
+        screen.put(0, 0, &title);
+        let half = screen.width / 2;
+        let party = &session.party;
+        self.panel(screen, 0, "Party", party, 0);
+        self.panel(screen, half, "Opponents", &session.opponents, party.len());
+
+        // The log takes whatever the panels and the last two lines leave
+        let rows = party.len().max(session.opponents.len()) * ROWS;
+        let top = 5 + rows;
+        screen.put(0, top, "Log");
+        let height = screen.height.saturating_sub(top + 3);
+        let news = watch::news(session, 0, 0);
+        let end = news.len().saturating_sub(self.scroll.min(news.len()));
+        let start = end.saturating_sub(height);
+        for (i, line) in news[start..end].iter().enumerate() {
+            screen.put(2, top + 1 + i, line);
+        }
+
+        let bottom = screen.height.saturating_sub(1);
+        screen.put(0, bottom.saturating_sub(1), &self.status);
+        let keys = self.hotkeys();
+        let help = keys.iter().map(|(key, verb)| format!("{key} {verb}"));
+        let help = help.collect::<Vec<_>>().join("  ");
+        let help = format!("{help}  ↑↓ target  ←→ actor  [] log  u undo  s save  q quit");
+        screen.put(0, bottom, &help);
+    }
+}
+
+pub fn run(store: &mut dyn SessionStore, name: &str) -> Result<()> {
+    let mut tui = Tui::new(Repl::new(store, name)?);
+    let raw = Raw::enter().ok_or(Error::NoTerminal)?;
+    let mut out = io::stdout();
+    // A screen of its own, put back the way it was after
+    write!(out, "\x1b[?1049h\x1b[?25l")?;
+    let keys = keys();
+    let result = loop {
+        let (height, width) = editor::size().unwrap_or(SIZE);
+        let mut screen = Screen::new(width, height);
+        tui.render(&mut screen);
+        let lines = (0..height).map(|y| format!("{}\x1b[K", screen.line(y)));
+        write!(out, "\x1b[H{}", lines.collect::<Vec<_>>().join("\r\n"))?;
+        out.flush()?;
+        match keys.recv_timeout(REFRESH) {
+            Ok(Ok(key)) if tui.press(key) => {}
+            Ok(Ok(_)) | Err(RecvTimeoutError::Disconnected) => break Ok(()),
+            Ok(Err(err)) => break Err(err),
+            Err(RecvTimeoutError::Timeout) => tui.refresh(),
+        }
+    };
+    write!(out, "\x1b[?25h\x1b[?1049l")?;
+    out.flush()?;
+    drop(raw);
+    result
+}
+
+// Keys come in on a thread of their own, so the screen keeps up with the
+// session in between
+fn keys() -> Receiver<Result<Key>> {
+    let (send, keys) = mpsc::channel();
+    thread::spawn(move || {
+        let mut stdin = io::stdin().lock();
+        loop {
+            let key = read_key(&mut stdin);
+            let failed = key.is_err();
+            if send.send(key).is_err() || failed {
+                break;
+            }
+        }
+    });
+    keys
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Screen, Tui};
+    use crate::editor::Key;
+    use crate::play::Repl;
+    use crate::session::Session;
+    use crate::store::{Memory, SessionStore};
+    use crate::Entity;
+
+    fn render(tui: &Tui) -> Screen {
+        let mut screen = Screen::new(80, 24);
+        tui.render(&mut screen);
+        screen
+    }
+
+    #[test]
+    fn the_fight_fits_on_a_screen() {
+        let gilgamesh = Entity::new("Gilgamesh".to_string());
+        let enkidu = Entity::new("Enkidu".to_string());
+        let mut tommy = Entity::new("Tommy".to_string());
+        tommy.set_max_health(50);
+        let party = vec![gilgamesh, enkidu];
+        let session = Session::encounter("test".to_string(), party, vec![tommy], 0);
+        let mut store = Memory::default();
+        store.save(&session).unwrap();
+        let mut tui = Tui::new(Repl::new(&mut store, "test").unwrap());
+
+        let screen = render(&tui);
+        assert!(screen
+            .line(0)
+            .starts_with("test  setting up  acting as Gilgamesh"));
+        assert!(screen.line(2).starts_with("Party"));
+        assert!(screen.line(2).contains("Opponents"));
+        assert!(screen.line(4).starts_with("* Gilgamesh Lv1"));
+        assert!(screen.line(4).ends_with("> Tommy Lv1"));
+        assert!(screen.line(5).contains("██████████ 5/5"));
+        assert!(screen
+            .line(23)
+            .starts_with("f fight  l love  n neutral  e electrocute"));
+
+        assert!(tui.press(Key::Char('f')));
+        let screen = render(&tui);
+        assert!(screen.line(5).ends_with("/50"), "{screen}");
+        assert!(screen.to_string().contains("Gilgamesh fights Tommy"));
+
+        // Enkidu loves Gilgamesh, a charm shows up under him
+        tui.press(Key::Right);
+        tui.press(Key::Down);
+        tui.press(Key::Char('l'));
+        let screen = render(&tui);
+        assert!(screen.line(0).ends_with("acting as Enkidu"));
+        assert!(screen.line(6).contains("charm"), "{screen}");
+
+        tui.press(Key::Char('u'));
+        tui.press(Key::Char('x'));
+        assert_eq!(render(&tui).line(22), "nothing on x");
+        assert!(!render(&tui).line(6).contains("charm"));
+        // Not without saving first
+        assert!(tui.press(Key::Char('q')));
+        tui.press(Key::Char('s'));
+        assert!(!tui.press(Key::Char('q')));
+    }
+}
@@ -0,0 +1,402 @@
+use std::fmt::{self, Display, Formatter};
+use std::fs;
+use std::str::FromStr;
+
+use crate::dice::Dice;
+use crate::effects::{Effect, EffectKind};
+use crate::error::{Error, Result};
+use crate::script::Script;
+
+pub const FILENAME: &str = "actions.txt";
+
+// Used as is when there is no `actions.txt` next to the sessions. The file
+// only has to mention what it changes or adds, anything it leaves out keeps
+// its built in definition
+const DEFAULT: &str = "\
+# One block per action. The id in brackets is what saved sessions refer to,
+# so rename with `name` rather than changing the id
+#
+# name    | What to type, the id if left out
+# aliases | Other things to type
+# targets | enemy, ally, self or any
+# cost    | Like `2 mana` or `1 stamina`, both come back 1 a turn
+# cooldown| Like `2 turns` or `1500 ms` before it can be done again
+# range   | Squares it reaches on a grid, anywhere if left out
+# effect  | Comma separated: damage <dice>, heal <dice>, <effect> <magnitude> <turns>
+# help    | One line for action --help
+# script  | A file in scripts/ to run after the effect, see script.rs
+
+[fight]
+aliases = hit attack
+targets = enemy
+range = 1
+effect = damage 1d2
+help = Hit someone, with your own damage dice if you have them
+
+[love]
+aliases = hug
+targets = any
+range = 1
+effect = heal 1d2, charm 0 2
+help = Heal someone, it leaves them too charmed to hurt anyone
+
+[neutral]
+aliases = guard wait
+targets = ally
+range = 1
+effect = shield 2 2
+help = Shield yourself or a friend
+
+[electrocute]
+aliases = zap shock
+targets = enemy
+cost = 2 mana
+cooldown = 1 turn
+range = 5
+effect = damage 1d6-3, stun 0 1, burn 1 2
+help = Zap someone, stunning and burning them if it hurts
+";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Targeting {
+    Enemy,
+    Ally,
+    Itself,
+    Any,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Resource {
+    Mana,
+    Stamina,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cost {
+    pub amount: u8,
+    pub resource: Resource,
+}
+
+// Counted from the last time the same entity did it. Turns are that
+// entity's own, milliseconds are measured between `Action::start`s
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Cooldown {
+    Turns(u32),
+    Millis(u64),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Clause {
+    Damage(Dice),
+    Heal(Dice),
+    Effect(Effect),
+}
+
+// What an action does, in the order it does it
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Formula(pub Vec<Clause>);
+
+impl Formula {
+    pub fn is_harmful(&self) -> bool {
+        self.0.iter().any(|c| matches!(c, Clause::Damage(_)))
+    }
+
+    pub fn heals(&self) -> bool {
+        self.0.iter().any(|c| matches!(c, Clause::Heal(_)))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Verb {
+    pub id: String,
+    pub name: String,
+    pub aliases: Vec<String>,
+    pub targets: Targeting,
+    pub cost: Option<Cost>,
+    pub cooldown: Option<Cooldown>,
+    pub range: Option<u8>,
+    pub effect: Formula,
+    pub script: Option<Script>,
+    pub help: String,
+}
+
+impl Verb {
+    pub fn new(id: &str) -> Self {
+        Self {
+            id: id.to_string(),
+            name: id.to_string(),
+            aliases: vec![],
+            targets: Targeting::Any,
+            cost: None,
+            cooldown: None,
+            range: None,
+            effect: Formula::default(),
+            script: None,
+            help: String::new(),
+        }
+    }
+
+    pub fn is_called(&self, name: &str) -> bool {
+        let name = name.to_lowercase();
+        self.name == name || self.aliases.contains(&name)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Verbs(pub Vec<Verb>);
+
+impl Default for Verbs {
+    fn default() -> Self {
+        DEFAULT.parse().expect("built in actions are valid")
+    }
+}
+
+impl Verbs {
+    pub fn load() -> Result<Self> {
+        let text = match fs::read_to_string(FILENAME) {
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Self::default()),
+            text => text?,
+        };
+        let mut verbs = Self::default();
+        for verb in text.parse::<Self>()?.0 {
+            match verbs.0.iter_mut().find(|v| v.id == verb.id) {
+                Some(builtin) => *builtin = verb,
+                None => verbs.0.push(verb),
+            }
+        }
+        Ok(verbs)
+    }
+
+    pub fn get(&self, id: &str) -> Result<&Verb> {
+        self.0
+            .iter()
+            .find(|v| v.id == id)
+            .ok_or_else(|| Error::NoVerb(id.to_string()))
+    }
+
+    // By name or alias, what the CLI takes
+    pub fn find(&self, name: &str) -> Result<&Verb> {
+        self.0
+            .iter()
+            .find(|v| v.is_called(name))
+            .ok_or_else(|| Error::NoVerb(name.to_string()))
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Verb> {
+        self.0.iter()
+    }
+}
+
+impl FromStr for Targeting {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "enemy" => Ok(Self::Enemy),
+            "ally" => Ok(Self::Ally),
+            "self" => Ok(Self::Itself),
+            "any" => Ok(Self::Any),
+            _ => Err(Error::InvalidArgs("action ")),
+        }
+    }
+}
+
+impl FromStr for Cost {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let invalid = || Error::InvalidArgs("action ");
+        let (amount, resource) = s.split_once(char::is_whitespace).ok_or_else(invalid)?;
+        let resource = match resource.trim() {
+            "mana" => Resource::Mana,
+            "stamina" => Resource::Stamina,
+            _ => return Err(invalid()),
+        };
+        Ok(Self {
+            amount: amount.parse().map_err(|_| invalid())?,
+            resource,
+        })
+    }
+}
+
+impl FromStr for Cooldown {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let invalid = || Error::InvalidArgs("action ");
+        let (amount, unit) = s.split_once(char::is_whitespace).ok_or_else(invalid)?;
+        match unit.trim() {
+            "turn" | "turns" => Ok(Self::Turns(amount.parse().map_err(|_| invalid())?)),
+            "ms" => Ok(Self::Millis(amount.parse().map_err(|_| invalid())?)),
+            _ => Err(invalid()),
+        }
+    }
+}
+
+impl FromStr for Clause {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let invalid = || Error::InvalidArgs("action ");
+        let (word, rest) = s
+            .trim()
+            .split_once(char::is_whitespace)
+            .ok_or_else(invalid)?;
+        let kind = match word {
+            "damage" => return Ok(Self::Damage(rest.parse()?)),
+            "heal" => return Ok(Self::Heal(rest.parse()?)),
+            "stun" => EffectKind::Stun,
+            "burn" => EffectKind::Burn,
+            "charm" => EffectKind::Charm,
+            "shield" => EffectKind::Shield,
+            _ => return Err(invalid()),
+        };
+        let Some((magnitude, turns)) = rest.trim().split_once(char::is_whitespace) else {
+            return Err(invalid());
+        };
+        let magnitude = magnitude.parse().map_err(|_| invalid())?;
+        let turns = turns.trim().parse().map_err(|_| invalid())?;
+        Ok(Self::Effect(Effect::new(kind, magnitude, turns)))
+    }
+}
+
+impl FromStr for Formula {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let clauses = s.split(',').map(str::parse).collect::<Result<_>>()?;
+        Ok(Self(clauses))
+    }
+}
+
+impl FromStr for Verbs {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let mut verbs: Vec<Verb> = vec![];
+        for (i, line) in s.lines().enumerate() {
+            let invalid = || Error::InvalidData(FILENAME, i + 1);
+            let line = line.split('#').next().unwrap_or_default().trim();
+            if line.is_empty() {
+                continue;
+            }
+            if let Some(id) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+                let id = id.trim().to_lowercase();
+                if id.is_empty() || id.contains(char::is_whitespace) {
+                    return Err(invalid());
+                }
+                if verbs.iter().any(|v| v.id == id) {
+                    return Err(invalid());
+                }
+                verbs.push(Verb::new(&id));
+                continue;
+            }
+
+            let verb = verbs.last_mut().ok_or_else(invalid)?;
+            let (key, value) = line.split_once('=').ok_or_else(invalid)?;
+            let value = value.trim();
+            match key.trim() {
+                "name" => verb.name = value.to_lowercase(),
+                "aliases" => {
+                    verb.aliases = value.split_whitespace().map(str::to_lowercase).collect()
+                }
+                "targets" => verb.targets = value.parse().map_err(|_| invalid())?,
+                "cost" => verb.cost = Some(value.parse().map_err(|_| invalid())?),
+                "cooldown" => verb.cooldown = Some(value.parse().map_err(|_| invalid())?),
+                "range" => verb.range = Some(value.parse().map_err(|_| invalid())?),
+                "effect" => verb.effect = value.parse().map_err(|_| invalid())?,
+                "script" => verb.script = Some(Script::load(value)?),
+                "help" => verb.help = value.to_string(),
+                _ => return Err(invalid()),
+            }
+        }
+        Ok(Self(verbs))
+    }
+}
+
+impl Display for Targeting {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let targets = match self {
+            Self::Enemy => "enemy",
+            Self::Ally => "ally",
+            Self::Itself => "self",
+            Self::Any => "any",
+        };
+        write!(f, "{targets}")
+    }
+}
+
+impl Display for Cost {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let resource = match self.resource {
+            Resource::Mana => "mana",
+            Resource::Stamina => "stamina",
+        };
+        write!(f, "{} {resource}", self.amount)
+    }
+}
+
+impl Display for Cooldown {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Turns(1) => write!(f, "1 turn"),
+            Self::Turns(turns) => write!(f, "{turns} turns"),
+            Self::Millis(ms) => write!(f, "{ms} ms"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Clause, Cooldown, Targeting, Verbs};
+
+    #[test]
+    fn parse_definitions() {
+        let verbs: Verbs = "
+            [fireball]   # a comment
+            name = fireball
+            aliases = fb
+            targets = enemy
+            cost = 3 mana
+            cooldown = 2 turns
+            effect = damage 2d6 adv, burn 2 3
+            help = Hot
+        "
+        .parse()
+        .unwrap();
+        let fireball = verbs.find("FB").unwrap();
+        assert_eq!(fireball.id, "fireball");
+        assert_eq!(fireball.targets, Targeting::Enemy);
+        assert_eq!(fireball.cost.unwrap().to_string(), "3 mana");
+        assert_eq!(fireball.cooldown, Some(Cooldown::Turns(2)));
+        assert!(fireball.effect.is_harmful());
+        assert!(
+            matches!(&fireball.effect.0[0], Clause::Damage(dice) if dice.to_string() == "2d6 adv")
+        );
+        assert!(verbs.get("fight").is_err());
+    }
+
+    #[test]
+    fn reject_nonsense() {
+        for text in [
+            "name = orphan",
+            "[a]\n[a]",
+            "[a]\ntargets = everyone",
+            "[a]\neffect = damage",
+            "[a]\neffect = stun 1",
+            "[a]\ncost = 3 gold",
+            "[a]\ncooldown = 2 days",
+            "[a]\nflavour = spicy",
+        ] {
+            assert!(text.parse::<Verbs>().is_err(), "{text}");
+        }
+    }
+
+    #[test]
+    fn builtins_are_all_there() {
+        let verbs = Verbs::default();
+        for name in ["fight", "love", "neutral", "electrocute", "zap"] {
+            assert!(verbs.find(name).is_ok(), "{name}");
+        }
+    }
+}
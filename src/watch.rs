@@ -0,0 +1,70 @@
+use crate::error::Result;
+use crate::session::Session;
+use crate::store::SessionStore;
+
+// How many of the lines already in the log get printed first
+pub const BACKFILL: usize = 10;
+
+// Prints the log as it grows, for whoever isn't playing. Runs until it's
+// stopped or the session goes away
+pub fn watch(store: &mut dyn SessionStore, name: &str, last: usize) -> Result<()> {
+    let mut session = store.load(name)?;
+    let backfill = news(&session, 0, 0);
+    for line in &backfill[backfill.len().saturating_sub(last)..] {
+        println!("{line}");
+    }
+    loop {
+        let seen = (session.actions().len(), session.chat.len());
+        store.wait(&mut session)?;
+        // Someone started it over with `new`, so it's all news
+        let seen = match session.actions().len() < seen.0 {
+            true => (0, 0),
+            false => seen,
+        };
+        for line in news(&session, seen.0, seen.1.min(session.chat.len())) {
+            println!("{line}");
+        }
+    }
+}
+
+// Actions and chat after the ones already seen, as one log in the order
+// they happened
+pub fn news(session: &Session, actions: usize, chat: usize) -> Vec<String> {
+    let actions = session.actions()[actions..].iter();
+    let chat = session.chat[chat..].iter();
+    let mut news = actions
+        .map(|action| (action.start, action.to_string()))
+        .chain(chat.map(|chat| (chat.start, chat.to_string())))
+        .collect::<Vec<_>>();
+    news.sort_by_key(|(start, _)| *start);
+    news.into_iter().map(|(_, line)| line).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::chat::Chat;
+    use crate::session::Session;
+    use crate::Entity;
+
+    #[test]
+    fn chat_comes_between_the_actions() {
+        let gilgamesh = Entity::new("Gilgamesh".to_string());
+        let tommy = Entity::new("Tommy".to_string());
+        let mut session = Session::encounter("test".to_string(), vec![gilgamesh], vec![tommy], 0);
+        let spawned = session.actions()[0].start;
+        let chat = |start, text| Chat {
+            start,
+            ..Chat::new("alice".to_string(), text)
+        };
+        session.chat = vec![
+            chat(spawned - 1, "ready?"),
+            chat(spawned + 1000, "/me waves"),
+        ];
+
+        let news = super::news(&session, 0, 0);
+        assert_eq!(news[0], "alice: ready?");
+        assert_eq!(news[3], "* alice waves");
+        let tommy = session.actions()[1].to_string();
+        assert_eq!(super::news(&session, 1, 1), [tommy, news[3].clone()]);
+    }
+}